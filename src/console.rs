@@ -0,0 +1,54 @@
+use std::io::IsTerminal;
+
+/// What decides whether to wait for Enter before exiting.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct PauseContext {
+    /// Nobody can see our console, e.g. a relaunched process or a broker.
+    pub unattended: bool,
+    /// Both stdin and stdout are a terminal, so not a script, pipe or CI job.
+    pub interactive: bool,
+    /// The console window was made for us and closes when we exit, as when
+    /// double-clicked in Explorer.
+    pub own_console: bool,
+}
+
+impl PauseContext {
+    pub fn detect(unattended: bool) -> Self {
+        Self {
+            unattended,
+            interactive: std::io::stdin().is_terminal() && std::io::stdout().is_terminal(),
+            own_console: owns_console(),
+        }
+    }
+
+    /// Whether to pause, given `--pause` (`Some(true)`) or `--no-pause`
+    /// (`Some(false)`). By default only when the window would otherwise close
+    /// before the output can be read. Never when unattended, since nobody
+    /// could press Enter and whoever started us would wait forever.
+    pub fn should_pause(&self, requested: Option<bool>) -> bool {
+        if self.unattended {
+            return false;
+        }
+        match requested {
+            Some(pause) => pause,
+            None => self.interactive && self.own_console,
+        }
+    }
+}
+
+/// Whether we are the only process attached to our console, which means it
+/// was created for us rather than belonging to a shell we were started from.
+#[cfg(windows)]
+pub fn owns_console() -> bool {
+    use windows::Win32::System::Console::GetConsoleProcessList;
+
+    let mut processes = [0u32; 2];
+    let count = unsafe { GetConsoleProcessList(&mut processes) };
+    count == 1
+}
+
+/// Terminals outside Windows belong to a shell or terminal emulator.
+#[cfg(not(windows))]
+pub fn owns_console() -> bool {
+    false
+}
@@ -0,0 +1,31 @@
+use eyre::eyre;
+use std::path::PathBuf;
+
+/// Overrides [`data_dir`], mostly useful for tests.
+pub const DATA_DIR_ENV_VAR: &str = "ENV_EDIT_DATA_DIR";
+
+/// Directory where env-edit keeps its own files.
+///
+/// `%LOCALAPPDATA%\env-edit` on Windows, `$XDG_DATA_HOME/env-edit`
+/// (or `~/.local/share/env-edit`) elsewhere.
+pub fn data_dir() -> eyre::Result<PathBuf> {
+    if let Some(dir) = std::env::var_os(DATA_DIR_ENV_VAR) {
+        return Ok(PathBuf::from(dir));
+    }
+    let base = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+            })
+    };
+    let base = base.ok_or_else(|| eyre!("Could not determine the user data directory"))?;
+    Ok(base.join(env!("CARGO_PKG_NAME")))
+}
+
+/// The JSON file used in place of the registry on non-Windows platforms.
+pub fn default_store_path() -> eyre::Result<PathBuf> {
+    Ok(data_dir()?.join("store.json"))
+}
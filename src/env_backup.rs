@@ -0,0 +1,274 @@
+use crate::data_dir::data_dir;
+use crate::env_change::ChangeSet;
+use crate::env_change::plan_changes;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvironmentVariable;
+use crate::env_store::EnvStore;
+use eyre::Context;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+pub const BACKUP_VERSION: u32 = 1;
+
+/// How many backups are kept before the oldest are removed.
+pub const DEFAULT_BACKUP_LIMIT: usize = 50;
+
+/// The state of every variable a change touched, from just before it was applied.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Backup {
+    pub version: u32,
+    pub id: String,
+    /// The [`EnvStore::identity`] of the store the changes were made to.
+    /// Backups from before this was recorded match no store.
+    #[serde(default)]
+    pub store: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// What was about to happen, e.g. `set machine Path`.
+    pub description: String,
+    pub entries: Vec<BackupEntry>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BackupEntry {
+    pub scope: EnvScope,
+    pub key: String,
+    /// `None` if the variable did not exist.
+    pub previous: Option<EnvironmentVariable>,
+}
+
+impl Backup {
+    /// Record what `changes` is about to overwrite in `store`.
+    pub fn from_changes(changes: &ChangeSet, store: &str, description: &str) -> Self {
+        let mut entries: Vec<BackupEntry> = Vec::new();
+        for change in &changes.changes {
+            // Only the first change to a variable saw its original state
+            let seen = entries.iter().any(|entry| {
+                entry.scope == change.scope() && entry.key.eq_ignore_ascii_case(change.key())
+            });
+            if !seen {
+                entries.push(BackupEntry {
+                    scope: change.scope(),
+                    key: change.key().to_string(),
+                    previous: change.previous().cloned(),
+                });
+            }
+        }
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Self {
+            version: BACKUP_VERSION,
+            id: format_timestamp(created),
+            store: store.to_string(),
+            created,
+            description: description.to_string(),
+            entries,
+        }
+    }
+
+    /// The changes that put every backed-up variable in `scopes` back the way it was.
+    pub fn plan_restore(
+        &self,
+        store: &dyn EnvStore,
+        scopes: &[EnvScope],
+    ) -> eyre::Result<ChangeSet> {
+        let mut current = Vec::new();
+        for &scope in scopes {
+            current.extend(store.list(scope)?);
+        }
+        let is_backed_up = |var: &EnvironmentVariable| {
+            self.entries
+                .iter()
+                .any(|entry| entry.scope == var.scope && entry.key.eq_ignore_ascii_case(&var.key))
+        };
+        let mut target: Vec<EnvironmentVariable> = current
+            .iter()
+            .filter(|var| !is_backed_up(var))
+            .cloned()
+            .collect();
+        target.extend(
+            self.entries
+                .iter()
+                .filter_map(|entry| entry.previous.clone())
+                .filter(|var| scopes.contains(&var.scope)),
+        );
+        Ok(plan_changes(&current, &target, scopes))
+    }
+}
+
+/// A directory of backups, one JSON file each, seen through the backups of a
+/// single store and pruned to its newest `limit`.
+#[derive(Debug, Clone)]
+pub struct BackupStore {
+    dir: PathBuf,
+    store: String,
+    limit: usize,
+}
+
+impl BackupStore {
+    /// The backups in `dir` taken from the store with identity `store`.
+    pub fn new(dir: impl Into<PathBuf>, store: impl Into<String>, limit: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            store: store.into(),
+            limit,
+        }
+    }
+
+    /// The backups of `store` in the `backups` folder in [`data_dir`].
+    pub fn open_default(store: &dyn EnvStore) -> eyre::Result<Self> {
+        Ok(Self::new(
+            data_dir()?.join("backups"),
+            store.identity(),
+            DEFAULT_BACKUP_LIMIT,
+        ))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Save a backup of what `changes` will overwrite, returning its id.
+    pub fn record(&self, changes: &ChangeSet, description: &str) -> eyre::Result<String> {
+        let mut backup = Backup::from_changes(changes, &self.store, description);
+        std::fs::create_dir_all(&self.dir)
+            .wrap_err_with(|| format!("Failed to create {}", self.dir.display()))?;
+        // Several writes can land in the same second. Number them after the
+        // highest one seen, since pruning may have freed a lower id. Ids are
+        // shared by every store.
+        let highest = self
+            .load_all()?
+            .iter()
+            .filter_map(|existing| match existing.id.strip_prefix(&backup.id)? {
+                "" => Some(1),
+                suffix => suffix.strip_prefix('-')?.parse::<u32>().ok(),
+            })
+            .max();
+        if let Some(highest) = highest {
+            backup.id = format!("{}-{}", backup.id, highest + 1);
+        }
+        let path = self.path_for(&backup.id);
+        std::fs::write(&path, serde_json::to_string_pretty(&backup)?)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))?;
+        self.prune()?;
+        Ok(backup.id)
+    }
+
+    /// Every backup of this store, oldest first.
+    pub fn list(&self) -> eyre::Result<Vec<Backup>> {
+        let mut backups: Vec<Backup> = self
+            .load_all()?
+            .into_iter()
+            .filter(|backup| backup.store == self.store)
+            .collect();
+        // A longer id from the same second has a higher collision suffix
+        backups.sort_by(|a, b| (a.created, a.id.len(), &a.id).cmp(&(b.created, b.id.len(), &b.id)));
+        Ok(backups)
+    }
+
+    pub fn latest(&self) -> eyre::Result<Option<Backup>> {
+        Ok(self.list()?.pop())
+    }
+
+    pub fn get(&self, id: &str) -> eyre::Result<Backup> {
+        let path = self.path_for(id);
+        if !path.exists() {
+            eyre::bail!("There is no backup with id {id}");
+        }
+        let backup = load_backup(&path)?;
+        if backup.store != self.store {
+            eyre::bail!(
+                "Backup {id} was taken from {}, not {}",
+                describe_store(&backup.store),
+                self.store
+            );
+        }
+        Ok(backup)
+    }
+
+    fn prune(&self) -> eyre::Result<()> {
+        let backups = self.list()?;
+        let excess = backups.len().saturating_sub(self.limit);
+        for backup in &backups[..excess] {
+            std::fs::remove_file(self.path_for(&backup.id))?;
+        }
+        Ok(())
+    }
+
+    /// Every backup in the directory, whichever store it came from.
+    fn load_all(&self) -> eyre::Result<Vec<Backup>> {
+        self.backup_files()?
+            .iter()
+            .map(|path| load_backup(path))
+            .collect()
+    }
+
+    fn backup_files(&self) -> eyre::Result<Vec<PathBuf>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut files = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                files.push(path);
+            }
+        }
+        Ok(files)
+    }
+
+    fn path_for(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+}
+
+fn describe_store(store: &str) -> &str {
+    if store.is_empty() {
+        "an unknown store"
+    } else {
+        store
+    }
+}
+
+fn load_backup(path: &Path) -> eyre::Result<Backup> {
+    let text = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+    let backup: Backup = serde_json::from_str(&text)
+        .wrap_err_with(|| format!("Failed to parse {}", path.display()))?;
+    if backup.version != BACKUP_VERSION {
+        eyre::bail!(
+            "{} has unsupported backup version {}",
+            path.display(),
+            backup.version
+        );
+    }
+    Ok(backup)
+}
+
+/// Format seconds since the Unix epoch as a sortable UTC timestamp, e.g. `20241031T235959Z`.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let time = secs % 86_400;
+    // Howard Hinnant's civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
@@ -0,0 +1,505 @@
+use crate::env_change::Change;
+use crate::env_change::ChangeSet;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use crate::env_store::EnvStore;
+use crate::env_validate::Severity;
+use crate::win_strings::quote_windows_arg;
+use eyre::Context;
+use serde::Deserialize;
+use serde::Serialize;
+use std::io::BufRead;
+use std::io::Write;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long to keep trying to reach a broker that was just launched, which
+/// includes the time spent answering the elevation prompt.
+pub const LAUNCH_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// One variable, or family of variables, a broker is willing to change,
+/// written `SCOPE:NAME` such as `machine:Path`. The name matches ignoring
+/// case, and a name ending in `*` matches every name starting with the rest.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AllowEntry {
+    pub scope: EnvScope,
+    pub pattern: String,
+}
+
+impl AllowEntry {
+    pub fn new(scope: EnvScope, pattern: &str) -> Self {
+        Self {
+            scope,
+            pattern: pattern.to_string(),
+        }
+    }
+
+    pub fn allows(&self, scope: EnvScope, key: &str) -> bool {
+        scope == self.scope
+            && match self.pattern.strip_suffix('*') {
+                Some(prefix) => key
+                    .get(..prefix.len())
+                    .is_some_and(|start| start.eq_ignore_ascii_case(prefix)),
+                None => self.pattern.eq_ignore_ascii_case(key),
+            }
+    }
+}
+
+impl std::str::FromStr for AllowEntry {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        let Some((scope, pattern)) = s.split_once(':') else {
+            eyre::bail!("{s:?} should be SCOPE:NAME, such as machine:Path");
+        };
+        let scope = match scope.to_ascii_lowercase().as_str() {
+            "machine" => EnvScope::Machine,
+            "user" => EnvScope::User,
+            _ => eyre::bail!("{scope:?} is not a scope, use machine or user"),
+        };
+        if pattern.is_empty() {
+            eyre::bail!("{s:?} has no variable name");
+        }
+        Ok(Self::new(scope, pattern))
+    }
+}
+
+impl std::fmt::Display for AllowEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.scope, self.pattern)
+    }
+}
+
+/// Every variable a broker is willing to change.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Allowlist {
+    pub entries: Vec<AllowEntry>,
+}
+
+impl Allowlist {
+    pub fn new(entries: impl IntoIterator<Item = AllowEntry>) -> Self {
+        Self {
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    /// Parse `SCOPE:NAME` entries, see [`AllowEntry`].
+    pub fn parse(entries: impl IntoIterator<Item = impl AsRef<str>>) -> eyre::Result<Self> {
+        let entries = entries
+            .into_iter()
+            .map(|entry| entry.as_ref().parse())
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self { entries })
+    }
+
+    /// Exactly the variables `changes` touches, in the scopes it touches them.
+    pub fn for_changes(changes: &ChangeSet) -> Self {
+        let mut allowlist = Self::default();
+        for change in &changes.changes {
+            if !allowlist.allows(change.scope(), change.key()) {
+                allowlist
+                    .entries
+                    .push(AllowEntry::new(change.scope(), change.key()));
+            }
+        }
+        allowlist
+    }
+
+    pub fn allows(&self, scope: EnvScope, key: &str) -> bool {
+        self.entries.iter().any(|entry| entry.allows(scope, key))
+    }
+}
+
+/// Why a broker refused a change.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "reason")]
+pub enum RejectReason {
+    /// The variable is not on the broker's [`Allowlist`].
+    NotAllowed,
+    /// The variable no longer has the value the change expects to replace.
+    Stale,
+    /// Applying the change set would break the environment.
+    Invalid { message: String },
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RejectReason::NotAllowed => write!(f, "not on the broker's allowlist"),
+            RejectReason::Stale => write!(f, "changed since the change set was made"),
+            RejectReason::Invalid { message } => write!(f, "{message}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Rejection {
+    /// What was rejected, e.g. `machine Path`.
+    pub subject: String,
+    #[serde(flatten)]
+    pub reason: RejectReason,
+}
+
+impl std::fmt::Display for Rejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.subject, self.reason)
+    }
+}
+
+/// What a broker sends back for a change set.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "status")]
+pub enum BrokerResponse {
+    /// Every change was applied.
+    Applied { changes: usize },
+    /// Nothing was applied because of these.
+    Rejected { rejections: Vec<Rejection> },
+    /// The change set was accepted, but applying it failed part way.
+    Failed { message: String },
+}
+
+impl BrokerResponse {
+    /// `Ok` if the change set was applied, otherwise an error saying why not.
+    pub fn into_result(self) -> eyre::Result<usize> {
+        match self {
+            BrokerResponse::Applied { changes } => Ok(changes),
+            BrokerResponse::Rejected { rejections } => {
+                let reasons: Vec<String> = rejections.iter().map(ToString::to_string).collect();
+                eyre::bail!("The broker rejected the changes:\n{}", reasons.join("\n"))
+            }
+            BrokerResponse::Failed { message } => {
+                eyre::bail!("The broker failed to apply the changes: {message}")
+            }
+        }
+    }
+}
+
+/// Check `changes` against `allowlist` and the current contents of `store`,
+/// and apply them only if nothing is rejected.
+pub fn handle_changes(
+    store: &mut dyn EnvStore,
+    allowlist: &Allowlist,
+    changes: &ChangeSet,
+) -> BrokerResponse {
+    let rejections = match check_changes(store, allowlist, changes) {
+        Ok(rejections) => rejections,
+        Err(error) => {
+            return BrokerResponse::Failed {
+                message: format!("{error:#}"),
+            };
+        }
+    };
+    if !rejections.is_empty() {
+        return BrokerResponse::Rejected { rejections };
+    }
+    match changes.apply(store) {
+        Ok(()) => BrokerResponse::Applied {
+            changes: changes.len(),
+        },
+        Err(error) => BrokerResponse::Failed {
+            message: format!("{error:#}"),
+        },
+    }
+}
+
+fn check_changes(
+    store: &dyn EnvStore,
+    allowlist: &Allowlist,
+    changes: &ChangeSet,
+) -> eyre::Result<Vec<Rejection>> {
+    let mut rejections = Vec::new();
+    for change in &changes.changes {
+        let subject = format!("{} {}", change.scope(), change.key());
+        let reason = if !allowlist.allows(change.scope(), change.key()) {
+            RejectReason::NotAllowed
+        } else if !same_value(
+            store.get(change.scope(), change.key())?.as_ref(),
+            change.previous(),
+        ) {
+            RejectReason::Stale
+        } else {
+            continue;
+        };
+        rejections.push(Rejection { subject, reason });
+    }
+    let mut vars = Vec::new();
+    for scope in EnvScope::ALL {
+        vars.extend(store.list(scope)?);
+    }
+    rejections.extend(
+        changes
+            .validate(&vars)
+            .into_iter()
+            .filter(|issue| issue.severity == Severity::Error)
+            .map(|issue| Rejection {
+                subject: issue.subject,
+                reason: RejectReason::Invalid {
+                    message: issue.message,
+                },
+            }),
+    );
+    Ok(rejections)
+}
+
+fn same_value(
+    current: Option<&EnvironmentVariable>,
+    expected: Option<&EnvironmentVariable>,
+) -> bool {
+    let contents = |var: &EnvironmentVariable| (var.value.clone(), var.kind);
+    current.map(contents) == expected.map(contents)
+}
+
+/// Answer one change set sent as a line of JSON with a line of JSON.
+pub fn serve_connection(
+    store: &mut dyn EnvStore,
+    allowlist: &Allowlist,
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+) -> eyre::Result<BrokerResponse> {
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .wrap_err("Failed to read the change set")?;
+    let response = match serde_json::from_str::<ChangeSet>(&line) {
+        Ok(changes) => handle_changes(store, allowlist, &changes),
+        Err(error) => BrokerResponse::Failed {
+            message: format!("Failed to parse the change set: {error}"),
+        },
+    };
+    writeln!(writer, "{}", serde_json::to_string(&response)?)?;
+    writer.flush()?;
+    Ok(response)
+}
+
+/// Send `changes` as a line of JSON and read back the broker's answer.
+pub fn send_changes(
+    mut reader: impl BufRead,
+    mut writer: impl Write,
+    changes: &ChangeSet,
+) -> eyre::Result<BrokerResponse> {
+    writeln!(writer, "{}", serde_json::to_string(changes)?)?;
+    writer.flush()?;
+    let mut line = String::new();
+    reader
+        .read_line(&mut line)
+        .wrap_err("Failed to read the broker's response")?;
+    if line.is_empty() {
+        eyre::bail!("The broker closed the connection without answering");
+    }
+    serde_json::from_str(&line).wrap_err("Failed to parse the broker's response")
+}
+
+/// The arguments that start a broker on `endpoint` that applies one change
+/// set from the process `client_pid` to the variables `allowlist` allows,
+/// then exits.
+pub fn broker_args(endpoint: &str, client_pid: u32, allowlist: &Allowlist) -> Vec<String> {
+    let mut args = vec![
+        "broker".to_string(),
+        "--endpoint".to_string(),
+        endpoint.to_string(),
+        "--client-pid".to_string(),
+        client_pid.to_string(),
+        "--once".to_string(),
+    ];
+    for entry in &allowlist.entries {
+        args.push("--allow".to_string());
+        args.push(entry.to_string());
+    }
+    args
+}
+
+/// [`broker_args`] quoted into a Windows command line.
+pub fn broker_command_line(endpoint: &str, client_pid: u32, allowlist: &Allowlist) -> String {
+    broker_args(endpoint, client_pid, allowlist)
+        .iter()
+        .map(|arg| quote_windows_arg(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Serve change sets on the Unix socket at `endpoint`, one per connection,
+/// until the first one when `once` is set. Connections from any process but
+/// `client_pid` are refused. This stands in for the named pipe used on Windows.
+#[cfg(unix)]
+pub fn serve(
+    endpoint: &str,
+    store: &mut dyn EnvStore,
+    allowlist: &Allowlist,
+    client_pid: Option<u32>,
+    once: bool,
+) -> eyre::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::UnixListener;
+
+    let _ = std::fs::remove_file(endpoint);
+    let listener =
+        UnixListener::bind(endpoint).wrap_err_with(|| format!("Failed to listen on {endpoint}"))?;
+    std::fs::set_permissions(endpoint, std::fs::Permissions::from_mode(0o600))?;
+    let served = serve_listener(&listener, store, allowlist, client_pid, once);
+    let _ = std::fs::remove_file(endpoint);
+    served
+}
+
+#[cfg(unix)]
+fn serve_listener(
+    listener: &std::os::unix::net::UnixListener,
+    store: &mut dyn EnvStore,
+    allowlist: &Allowlist,
+    client_pid: Option<u32>,
+    once: bool,
+) -> eyre::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        if let Some(client_pid) = client_pid {
+            let pid = stream.peer_cred()?.pid;
+            if pid.and_then(|pid| u32::try_from(pid).ok()) != Some(client_pid) {
+                tracing::warn!(
+                    "Refusing a connection from process {pid:?}, only {client_pid} may send changes"
+                );
+                continue;
+            }
+        }
+        let served = stream
+            .try_clone()
+            .map_err(eyre::Report::from)
+            .and_then(|reader| {
+                serve_connection(store, allowlist, std::io::BufReader::new(reader), stream)
+            });
+        // One bad client must not stop the broker for everyone else
+        match served {
+            Ok(response) => tracing::info!("Answered a change set: {response:?}"),
+            Err(error) => tracing::warn!("Failed to answer a change set: {error:#}"),
+        }
+        if once {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Send `changes` to the broker listening on the Unix socket at `endpoint`.
+#[cfg(unix)]
+pub fn request(endpoint: &str, changes: &ChangeSet) -> eyre::Result<BrokerResponse> {
+    let stream = std::os::unix::net::UnixStream::connect(endpoint)?;
+    let reader = std::io::BufReader::new(stream.try_clone()?);
+    send_changes(reader, stream, changes)
+}
+
+/// Send `changes` to the broker listening on the named pipe `endpoint`.
+#[cfg(windows)]
+pub fn request(endpoint: &str, changes: &ChangeSet) -> eyre::Result<BrokerResponse> {
+    let pipe = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(pipe_path(endpoint))?;
+    let reader = std::io::BufReader::new(pipe.try_clone()?);
+    send_changes(reader, pipe, changes)
+}
+
+/// The full name of the named pipe for `endpoint`.
+#[cfg(windows)]
+pub fn pipe_path(endpoint: &str) -> String {
+    format!(r"\\.\pipe\{endpoint}")
+}
+
+/// Starts a broker process given its endpoint and allowlist.
+pub type LaunchBroker = Box<dyn Fn(&str, &Allowlist) -> eyre::Result<()>>;
+
+/// Reads from another store, but sends every write to a broker as a change set.
+pub struct BrokerEnvStore {
+    inner: Box<dyn EnvStore>,
+    endpoint: String,
+    launch: Option<LaunchBroker>,
+}
+
+impl BrokerEnvStore {
+    /// Send writes to the broker already listening on `endpoint`.
+    pub fn new(inner: Box<dyn EnvStore>, endpoint: &str) -> Self {
+        Self {
+            inner,
+            endpoint: endpoint.to_string(),
+            launch: None,
+        }
+    }
+
+    /// Start a broker with `launch` for every change set, allowed to change
+    /// only the variables in it, and send the change set to it on `endpoint`.
+    pub fn launching(
+        inner: Box<dyn EnvStore>,
+        endpoint: &str,
+        launch: impl Fn(&str, &Allowlist) -> eyre::Result<()> + 'static,
+    ) -> Self {
+        Self {
+            inner,
+            endpoint: endpoint.to_string(),
+            launch: Some(Box::new(launch)),
+        }
+    }
+
+    fn send(&self, changes: &ChangeSet) -> eyre::Result<usize> {
+        let Some(launch) = &self.launch else {
+            return request(&self.endpoint, changes)
+                .wrap_err_with(|| format!("Failed to reach the broker on {}", self.endpoint))?
+                .into_result();
+        };
+        launch(&self.endpoint, &Allowlist::for_changes(changes))?;
+        let started = Instant::now();
+        loop {
+            match request(&self.endpoint, changes) {
+                Ok(response) => return response.into_result(),
+                Err(error) if started.elapsed() > LAUNCH_TIMEOUT => {
+                    return Err(error).wrap_err_with(|| {
+                        format!("The broker never started listening on {}", self.endpoint)
+                    });
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(100)),
+            }
+        }
+    }
+}
+
+impl EnvStore for BrokerEnvStore {
+    fn identity(&self) -> String {
+        self.inner.identity()
+    }
+
+    fn list(&self, scope: EnvScope) -> eyre::Result<Vec<EnvironmentVariable>> {
+        self.inner.list(scope)
+    }
+
+    fn get(&self, scope: EnvScope, key: &str) -> eyre::Result<Option<EnvironmentVariable>> {
+        self.inner.get(scope, key)
+    }
+
+    fn set(
+        &mut self,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        kind: EnvValueKind,
+    ) -> eyre::Result<()> {
+        let mut changes = ChangeSet::new();
+        changes.push_set(self, scope, key, value, kind)?;
+        self.apply_changes(&changes)
+    }
+
+    fn delete(&mut self, scope: EnvScope, key: &str) -> eyre::Result<bool> {
+        let Some(previous) = self.inner.get(scope, key)? else {
+            return Ok(false);
+        };
+        let mut changes = ChangeSet::new();
+        changes.push(Change::Delete { previous });
+        self.apply_changes(&changes)?;
+        Ok(true)
+    }
+
+    /// The broker broadcasts after applying.
+    fn apply_changes(&mut self, changes: &ChangeSet) -> eyre::Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.send(changes)?;
+        Ok(())
+    }
+}
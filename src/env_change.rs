@@ -0,0 +1,335 @@
+use crate::env_expand::VariableSet;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use crate::env_store::EnvStore;
+use crate::env_validate::Issue;
+use crate::env_validate::check_block;
+use crate::env_validate::check_value;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A single edit to an environment, remembering what it replaces.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "op")]
+pub enum Change {
+    /// Create or overwrite `var`.
+    Set {
+        var: EnvironmentVariable,
+        previous: Option<EnvironmentVariable>,
+    },
+    /// Remove `previous`.
+    Delete { previous: EnvironmentVariable },
+}
+
+impl Change {
+    pub fn scope(&self) -> EnvScope {
+        match self {
+            Change::Set { var, .. } => var.scope,
+            Change::Delete { previous } => previous.scope,
+        }
+    }
+
+    pub fn key(&self) -> &str {
+        match self {
+            Change::Set { var, .. } => &var.key,
+            Change::Delete { previous } => &previous.key,
+        }
+    }
+
+    /// The variable as it was before this change, if it existed.
+    pub fn previous(&self) -> Option<&EnvironmentVariable> {
+        match self {
+            Change::Set { previous, .. } => previous.as_ref(),
+            Change::Delete { previous } => Some(previous),
+        }
+    }
+}
+
+impl std::fmt::Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Set {
+                var,
+                previous: None,
+            } => write!(
+                f,
+                "+ {} {} = {} ({})",
+                var.scope, var.key, var.value, var.kind
+            ),
+            Change::Set {
+                var,
+                previous: Some(previous),
+            } => {
+                write!(
+                    f,
+                    "~ {} {} = {} ({})",
+                    var.scope, var.key, var.value, var.kind
+                )?;
+                if previous.value != var.value || previous.kind != var.kind {
+                    write!(f, "\n    was {} ({})", previous.value, previous.kind)?;
+                }
+                if previous.key != var.key {
+                    write!(f, "\n    was named {}", previous.key)?;
+                }
+                Ok(())
+            }
+            Change::Delete { previous } => write!(f, "- {} {}", previous.scope, previous.key),
+        }
+    }
+}
+
+/// An ordered list of changes that can be previewed, validated and applied as one.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub changes: Vec<Change>,
+}
+
+impl ChangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn push(&mut self, change: Change) {
+        self.changes.push(change);
+    }
+
+    /// Queue setting a variable, looking up what it replaces in `store`.
+    pub fn push_set(
+        &mut self,
+        store: &dyn EnvStore,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        kind: EnvValueKind,
+    ) -> eyre::Result<()> {
+        let previous = store.get(scope, key)?;
+        self.push(Change::Set {
+            var: EnvironmentVariable::new(scope, key, value, kind),
+            previous,
+        });
+        Ok(())
+    }
+
+    /// Like [`ChangeSet::push_set`], but skip it if `store` already has this value and kind.
+    pub fn push_set_if_changed(
+        &mut self,
+        store: &dyn EnvStore,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        kind: EnvValueKind,
+    ) -> eyre::Result<()> {
+        let previous = store.get(scope, key)?;
+        if previous
+            .as_ref()
+            .is_some_and(|previous| previous.value == value && previous.kind == kind)
+        {
+            return Ok(());
+        }
+        self.push(Change::Set {
+            var: EnvironmentVariable::new(scope, key, value, kind),
+            previous,
+        });
+        Ok(())
+    }
+
+    /// What `vars` would look like after applying this change set.
+    pub fn simulate(&self, vars: &[EnvironmentVariable]) -> Vec<EnvironmentVariable> {
+        let mut after = vars.to_vec();
+        for change in &self.changes {
+            let position = after.iter().position(|var| {
+                var.scope == change.scope() && var.key.eq_ignore_ascii_case(change.key())
+            });
+            match (change, position) {
+                (Change::Set { var, .. }, Some(i)) => {
+                    // The registry keeps the existing spelling when overwriting
+                    let key = after[i].key.clone();
+                    after[i] = EnvironmentVariable { key, ..var.clone() };
+                }
+                (Change::Set { var, .. }, None) => after.push(var.clone()),
+                (Change::Delete { .. }, Some(i)) => {
+                    after.remove(i);
+                }
+                (Change::Delete { .. }, None) => {}
+            }
+        }
+        after.sort_by_key(|var| var.scope);
+        after
+    }
+
+    /// Length problems this change set would cause in an environment holding `vars`.
+    pub fn validate(&self, vars: &[EnvironmentVariable]) -> Vec<Issue> {
+        let after = self.simulate(vars);
+        let set = VariableSet::from_vars(&after);
+        let mut issues: Vec<Issue> = self
+            .changes
+            .iter()
+            .filter_map(|change| match change {
+                Change::Set { var, .. } if var.kind.is_string() => Some(check_value(var, &set)),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        issues.extend(check_block(&after));
+        issues
+    }
+
+    /// Apply every change in order, then broadcast once, see [`EnvStore::apply_changes`].
+    pub fn apply(&self, store: &mut dyn EnvStore) -> eyre::Result<()> {
+        store.apply_changes(self)
+    }
+
+    /// Apply every change in order without broadcasting.
+    pub fn write<S: EnvStore + ?Sized>(&self, store: &mut S) -> eyre::Result<()> {
+        for change in &self.changes {
+            match change {
+                Change::Set { var, .. } => store.set(var.scope, &var.key, &var.value, var.kind)?,
+                Change::Delete { previous } => {
+                    store.delete(previous.scope, &previous.key)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The change set that would undo this one.
+    pub fn inverse(&self) -> ChangeSet {
+        let mut inverse = ChangeSet::new();
+        for change in self.changes.iter().rev() {
+            match change {
+                Change::Set {
+                    var,
+                    previous: Some(previous),
+                } if previous.key == var.key => inverse.push(Change::Set {
+                    var: previous.clone(),
+                    previous: Some(var.clone()),
+                }),
+                Change::Set { var, previous } => {
+                    inverse.push(Change::Delete {
+                        previous: var.clone(),
+                    });
+                    if let Some(previous) = previous {
+                        inverse.push(Change::Set {
+                            var: previous.clone(),
+                            previous: None,
+                        });
+                    }
+                }
+                Change::Delete { previous } => inverse.push(Change::Set {
+                    var: previous.clone(),
+                    previous: None,
+                }),
+            }
+        }
+        inverse
+    }
+}
+
+impl std::fmt::Display for ChangeSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for change in &self.changes {
+            writeln!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The minimal changes that turn the `current` variables into `target` within
+/// `scopes`. Variables in other scopes are left alone.
+pub fn plan_changes(
+    current: &[EnvironmentVariable],
+    target: &[EnvironmentVariable],
+    scopes: &[EnvScope],
+) -> ChangeSet {
+    let in_scope = |var: &&EnvironmentVariable| scopes.contains(&var.scope);
+    let find = |vars: &[EnvironmentVariable], wanted: &EnvironmentVariable| {
+        vars.iter()
+            .find(|var| var.scope == wanted.scope && var.key.eq_ignore_ascii_case(&wanted.key))
+            .cloned()
+    };
+
+    let mut changes = ChangeSet::new();
+    for var in current.iter().filter(in_scope) {
+        if find(target, var).is_none() {
+            changes.push(Change::Delete {
+                previous: var.clone(),
+            });
+        }
+    }
+    for var in target.iter().filter(in_scope) {
+        let wanted = EnvironmentVariable::new(var.scope, &var.key, &var.value, var.kind);
+        match find(current, var) {
+            None => changes.push(Change::Set {
+                var: wanted,
+                previous: None,
+            }),
+            // A case-only rename needs the old spelling removed first
+            Some(previous) if previous.key != var.key => {
+                changes.push(Change::Delete {
+                    previous: previous.clone(),
+                });
+                changes.push(Change::Set {
+                    var: wanted,
+                    previous: Some(previous),
+                });
+            }
+            Some(previous) if previous.value != var.value || previous.kind != var.kind => changes
+                .push(Change::Set {
+                    var: wanted,
+                    previous: Some(previous),
+                }),
+            Some(_) => {}
+        }
+    }
+    changes
+}
+
+/// The changes that rename `from` to `to` within `scope`, keeping the value and kind.
+pub fn plan_rename(
+    store: &dyn EnvStore,
+    scope: EnvScope,
+    from: &str,
+    to: &str,
+) -> eyre::Result<ChangeSet> {
+    let Some(var) = store.get(scope, from)? else {
+        eyre::bail!("{from} is not set in the {scope} environment");
+    };
+    let mut changes = ChangeSet::new();
+    if var.key == to {
+        return Ok(changes);
+    }
+    let renamed = EnvironmentVariable::new(scope, to, &var.value, var.kind);
+    if var.key.eq_ignore_ascii_case(to) {
+        // Overwriting would keep the old spelling, so remove it first
+        changes.push(Change::Delete {
+            previous: var.clone(),
+        });
+        changes.push(Change::Set {
+            var: renamed,
+            previous: Some(var),
+        });
+    } else {
+        if store.get(scope, to)?.is_some() {
+            eyre::bail!("{to} already exists in the {scope} environment");
+        }
+        // Write the new name first so a failure never loses the value
+        changes.push(Change::Set {
+            var: renamed,
+            previous: None,
+        });
+        changes.push(Change::Delete { previous: var });
+    }
+    Ok(changes)
+}
@@ -0,0 +1,297 @@
+use crate::env_list::builtin_lists;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// How a variable differs between two environment states.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "change")]
+pub enum VarDiff {
+    Added {
+        after: EnvironmentVariable,
+    },
+    Removed {
+        before: EnvironmentVariable,
+    },
+    Modified {
+        before: EnvironmentVariable,
+        after: EnvironmentVariable,
+        /// Entry by entry changes when the value is a list, such as `Path`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        entries: Option<Vec<EntryDiff>>,
+    },
+}
+
+impl VarDiff {
+    /// The variable's name, as spelled on whichever side has it (the new side if both).
+    pub fn key(&self) -> &str {
+        match self {
+            VarDiff::Added { after } | VarDiff::Modified { after, .. } => &after.key,
+            VarDiff::Removed { before } => &before.key,
+        }
+    }
+
+    /// The type change, if the kind is different on each side.
+    pub fn kind_change(&self) -> Option<(EnvValueKind, EnvValueKind)> {
+        match self {
+            VarDiff::Modified { before, after, .. } if before.kind != after.kind => {
+                Some((before.kind, after.kind))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One entry of a list value, compared across both sides.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "change", content = "entry")]
+pub enum EntryDiff {
+    Unchanged(String),
+    Added(String),
+    Removed(String),
+}
+
+/// Every difference between two sets of variables.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+pub struct EnvDiff {
+    pub changes: Vec<VarDiff>,
+}
+
+impl EnvDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// A listing with one `+`, `-` or `~` line per variable and indented details.
+    pub fn to_human(&self) -> String {
+        let mut out = String::new();
+        for change in &self.changes {
+            match change {
+                VarDiff::Added { after } => out.push_str(&format!(
+                    "+ {} {} = {} ({})\n",
+                    after.scope, after.key, after.value, after.kind
+                )),
+                VarDiff::Removed { before } => out.push_str(&format!(
+                    "- {} {} = {} ({})\n",
+                    before.scope, before.key, before.value, before.kind
+                )),
+                VarDiff::Modified {
+                    before,
+                    after,
+                    entries,
+                } => {
+                    out.push_str(&format!("~ {} {}\n", after.scope, after.key));
+                    if let Some((from, to)) = change.kind_change() {
+                        out.push_str(&format!("    type {from} -> {to}\n"));
+                    }
+                    match entries {
+                        Some(entries) => {
+                            for entry in entries {
+                                match entry {
+                                    EntryDiff::Added(entry) => {
+                                        out.push_str(&format!("    + {entry}\n"))
+                                    }
+                                    EntryDiff::Removed(entry) => {
+                                        out.push_str(&format!("    - {entry}\n"))
+                                    }
+                                    EntryDiff::Unchanged(_) => {}
+                                }
+                            }
+                        }
+                        None if before.value != after.value => {
+                            out.push_str(&format!("    - {}\n", before.value));
+                            out.push_str(&format!("    + {}\n", after.value));
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// A unified diff with one file per variable, and one line per list entry.
+    pub fn to_unified(&self, before_label: &str, after_label: &str) -> String {
+        let mut out = String::new();
+        for change in &self.changes {
+            let (before, after) = match change {
+                VarDiff::Added { after } => (None, Some(after)),
+                VarDiff::Removed { before } => (Some(before), None),
+                VarDiff::Modified { before, after, .. } => (Some(before), Some(after)),
+            };
+            let header = |label: &str, var: Option<&EnvironmentVariable>| match var {
+                Some(var) => format!("{label}/{}/{}\t{}", var.scope, var.key, var.kind),
+                None => "/dev/null".to_string(),
+            };
+            out.push_str(&format!("--- {}\n", header(before_label, before)));
+            out.push_str(&format!("+++ {}\n", header(after_label, after)));
+
+            let lines = match change {
+                VarDiff::Modified {
+                    entries: Some(entries),
+                    ..
+                } => entries.clone(),
+                _ => {
+                    let mut lines = Vec::new();
+                    lines.extend(before.map(|var| EntryDiff::Removed(var.value.clone())));
+                    lines.extend(after.map(|var| EntryDiff::Added(var.value.clone())));
+                    // A type-only change still needs a line to show
+                    if let [EntryDiff::Removed(a), EntryDiff::Added(b)] = lines.as_slice()
+                        && a == b
+                    {
+                        lines = vec![EntryDiff::Unchanged(a.clone())];
+                    }
+                    lines
+                }
+            };
+            let old_len = lines
+                .iter()
+                .filter(|line| !matches!(line, EntryDiff::Added(_)))
+                .count();
+            let new_len = lines
+                .iter()
+                .filter(|line| !matches!(line, EntryDiff::Removed(_)))
+                .count();
+            out.push_str(&format!(
+                "@@ -{} +{} @@\n",
+                hunk_range(old_len),
+                hunk_range(new_len)
+            ));
+            for line in lines {
+                match line {
+                    EntryDiff::Unchanged(entry) => out.push_str(&format!(" {entry}\n")),
+                    EntryDiff::Added(entry) => out.push_str(&format!("+{entry}\n")),
+                    EntryDiff::Removed(entry) => out.push_str(&format!("-{entry}\n")),
+                }
+            }
+        }
+        out
+    }
+}
+
+fn hunk_range(len: usize) -> String {
+    match len {
+        0 => "0,0".to_string(),
+        1 => "1".to_string(),
+        len => format!("1,{len}"),
+    }
+}
+
+/// Compare two sets of variables, matching names case-insensitively.
+///
+/// With `match_scopes`, a variable only matches one in the same scope. Without
+/// it, scopes are ignored, which is how machine and user variables are
+/// compared against each other.
+pub fn diff(
+    before: &[EnvironmentVariable],
+    after: &[EnvironmentVariable],
+    match_scopes: bool,
+) -> EnvDiff {
+    let id = |var: &EnvironmentVariable| {
+        let scope = match_scopes.then_some(var.scope);
+        (scope, var.key.to_uppercase())
+    };
+    let mut sides: BTreeMap<_, (Option<&EnvironmentVariable>, Option<&EnvironmentVariable>)> =
+        BTreeMap::new();
+    for var in before {
+        sides.entry(id(var)).or_default().0 = Some(var);
+    }
+    for var in after {
+        sides.entry(id(var)).or_default().1 = Some(var);
+    }
+
+    let mut changes = Vec::new();
+    for (before, after) in sides.into_values() {
+        match (before, after) {
+            (None, Some(after)) => changes.push(VarDiff::Added {
+                after: after.clone(),
+            }),
+            (Some(before), None) => changes.push(VarDiff::Removed {
+                before: before.clone(),
+            }),
+            (Some(before), Some(after))
+                if before.value != after.value || before.kind != after.kind =>
+            {
+                let entries = list_separator(before, after).map(|separator| {
+                    diff_entries(
+                        &split_list(&before.value, separator),
+                        &split_list(&after.value, separator),
+                    )
+                });
+                changes.push(VarDiff::Modified {
+                    before: before.clone(),
+                    after: after.clone(),
+                    entries,
+                });
+            }
+            _ => {}
+        }
+    }
+    EnvDiff { changes }
+}
+
+/// The separator to diff entry by entry with, for a built-in list variable
+/// even when it holds a single entry, or for any value containing a `;`.
+fn list_separator(before: &EnvironmentVariable, after: &EnvironmentVariable) -> Option<char> {
+    if !before.kind.is_string() || !after.kind.is_string() {
+        return None;
+    }
+    let known = builtin_lists()
+        .into_iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(&after.key));
+    match known {
+        Some(spec) => Some(spec.separator),
+        None if before.value.contains(';') || after.value.contains(';') => Some(';'),
+        None => None,
+    }
+}
+
+fn split_list(value: &str, separator: char) -> Vec<&str> {
+    value
+        .split(separator)
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// Line up two lists along their longest common subsequence.
+pub fn diff_entries(before: &[&str], after: &[&str]) -> Vec<EntryDiff> {
+    // lcs[i][j] is the LCS length of before[i..] and after[j..]
+    let mut lcs = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            lcs[i][j] = if before[i] == after[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut rtn = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() && j < after.len() {
+        if before[i] == after[j] {
+            rtn.push(EntryDiff::Unchanged(before[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            rtn.push(EntryDiff::Removed(before[i].to_string()));
+            i += 1;
+        } else {
+            rtn.push(EntryDiff::Added(after[j].to_string()));
+            j += 1;
+        }
+    }
+    rtn.extend(
+        before[i..]
+            .iter()
+            .map(|entry| EntryDiff::Removed(entry.to_string())),
+    );
+    rtn.extend(
+        after[j..]
+            .iter()
+            .map(|entry| EntryDiff::Added(entry.to_string())),
+    );
+    rtn
+}
@@ -0,0 +1,190 @@
+use crate::env_change::ChangeSet;
+use crate::env_expand::VariableSet;
+use crate::env_reader::EnvScope;
+use crate::env_reader::KindChoice;
+use crate::env_store::EnvStore;
+
+/// The variables defined by a `.env` file, in file order.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct DotenvFile {
+    pub entries: Vec<(String, String)>,
+    /// Problems that did not stop parsing, such as undefined `${VAR}` references.
+    pub warnings: Vec<String>,
+}
+
+impl DotenvFile {
+    /// The changes that would write every entry into `scope`, skipping ones
+    /// that are already set to the same value.
+    ///
+    /// Existing variables keep their registry type.
+    pub fn plan(&self, store: &dyn EnvStore, scope: EnvScope) -> eyre::Result<ChangeSet> {
+        let mut changes = ChangeSet::new();
+        for (key, value) in &self.entries {
+            let existing = store.get(scope, key)?.map(|var| var.kind);
+            let kind = KindChoice::Preserve.resolve(existing, value);
+            changes.push_set_if_changed(store, scope, key, value, kind)?;
+        }
+        Ok(changes)
+    }
+}
+
+/// Parse a `.env` file.
+///
+/// * `#` starts a comment, at the start of a line or after whitespace in an
+///   unquoted value.
+/// * A leading `export ` is ignored.
+/// * `'single quoted'` values are taken literally.
+/// * `"double quoted"` values may span lines and understand `\n`, `\t`, `\"`,
+///   `\\` and `\$`.
+/// * `${VAR}`, `${VAR:-default}` and `$VAR` are replaced in unquoted and double
+///   quoted values, looking first at earlier entries in the file and then at `env`.
+pub fn parse_dotenv(text: &str, env: &VariableSet) -> eyre::Result<DotenvFile> {
+    let mut file = DotenvFile::default();
+    let mut defined = env.clone();
+    let mut rest = text;
+    let mut line_number = 0;
+    while !rest.is_empty() {
+        line_number += 1;
+        let (line, after) = rest.split_once('\n').unwrap_or((rest, ""));
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            rest = after;
+            continue;
+        }
+        let statement = trimmed.strip_prefix("export ").unwrap_or(trimmed);
+        let (Some((key, _)), Some(eq)) = (statement.split_once('='), line.find('=')) else {
+            eyre::bail!("Line {line_number}: expected KEY=value, found {trimmed:?}");
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            eyre::bail!("Line {line_number}: {key:?} is not a valid variable name");
+        }
+
+        // Quoted values can continue past this line, so parse from the rest of the text
+        let value_text = rest[eq + 1..].trim_start_matches([' ', '\t']);
+        let (value, consumed) = match value_text.chars().next() {
+            Some(quote @ ('\'' | '"')) => {
+                let body = &value_text[1..];
+                let Some(end) = find_closing_quote(body, quote) else {
+                    eyre::bail!("Line {line_number}: unterminated {quote} quoted value for {key}");
+                };
+                let raw = &body[..end];
+                line_number += raw.matches('\n').count();
+                let value = if quote == '\'' {
+                    raw.to_string()
+                } else {
+                    interpolate(&unescape(raw), &defined, key, &mut file.warnings)
+                        .replace(ESCAPED_DOLLAR, "$")
+                };
+                (value, 1 + end + 1)
+            }
+            _ => {
+                let raw = value_text.split('\n').next().unwrap_or_default();
+                let raw = match raw.find(" #").or_else(|| raw.find("\t#")) {
+                    Some(comment) => &raw[..comment],
+                    None => raw,
+                };
+                let consumed = raw.len();
+                let value = interpolate(raw.trim(), &defined, key, &mut file.warnings);
+                (value, consumed)
+            }
+        };
+
+        defined.insert(key, &value, None);
+        file.entries.push((key.to_string(), value));
+
+        let remaining = &value_text[consumed..];
+        let (tail, after) = remaining.split_once('\n').unwrap_or((remaining, ""));
+        let tail = tail.trim();
+        if !tail.is_empty() && !tail.starts_with('#') {
+            eyre::bail!("Line {line_number}: unexpected {tail:?} after the value of {key}");
+        }
+        rest = after;
+    }
+    Ok(file)
+}
+
+fn find_closing_quote(body: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            '\\' if quote == '"' && !escaped => escaped = true,
+            c if c == quote && !escaped => return Some(i),
+            _ => escaped = false,
+        }
+    }
+    None
+}
+
+/// Stands in for `\$` between unescaping and interpolating.
+const ESCAPED_DOLLAR: char = '\u{e000}';
+
+/// Resolve backslash escapes in a double quoted value. `\$` becomes
+/// [`ESCAPED_DOLLAR`] so that [`interpolate`] leaves it alone.
+fn unescape(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('t') => out.push('\t'),
+            Some('$') => out.push(ESCAPED_DOLLAR),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn interpolate(
+    value: &str,
+    defined: &VariableSet,
+    key: &str,
+    warnings: &mut Vec<String>,
+) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let (name, default, after) = if let Some(braced) = after.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                out.push('$');
+                rest = after;
+                continue;
+            };
+            let inner = &braced[..end];
+            match inner.split_once(":-") {
+                Some((name, default)) => (name, Some(default), &braced[end + 1..]),
+                None => (inner, None, &braced[end + 1..]),
+            }
+        } else {
+            let len = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..len], None, &after[len..])
+        };
+        if name.is_empty() {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+        // `:-` also replaces an empty value, like POSIX shells
+        let found = defined
+            .get(name)
+            .filter(|entry| !entry.value.is_empty() || default.is_none());
+        match (found, default) {
+            (Some(entry), _) => out.push_str(&entry.value),
+            (None, Some(default)) => out.push_str(default),
+            (None, None) => warnings.push(format!("{key}: ${name} is not defined, using \"\"")),
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,194 @@
+use crate::env_expand::VariableSet;
+use crate::env_expand::expand;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Variables whose user value is appended to the machine value, after a `;`,
+/// instead of replacing it.
+pub const APPENDED_VARIABLES: &[&str] = &["Path", "LibPath", "Os2LibPath"];
+
+/// Variables Windows sets for a session before reading the registry, from
+/// the system and the user's profile. Machine and user values can refer to
+/// them, such as `%USERPROFILE%\bin`.
+pub const DEFAULT_VARIABLES: &[&str] = &[
+    "ALLUSERSPROFILE",
+    "APPDATA",
+    "CommonProgramFiles",
+    "CommonProgramFiles(x86)",
+    "CommonProgramW6432",
+    "COMPUTERNAME",
+    "HOMEDRIVE",
+    "HOMEPATH",
+    "LOCALAPPDATA",
+    "LOGONSERVER",
+    "ProgramData",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "ProgramW6432",
+    "PUBLIC",
+    "SystemDrive",
+    "SystemRoot",
+    "USERDOMAIN",
+    "USERNAME",
+    "USERPROFILE",
+];
+
+/// Where the final value of an effective variable came from.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Origin {
+    /// One of the session defaults, not overridden by the registry.
+    Default,
+    Machine,
+    User,
+    /// The machine value with the user value appended, see [`APPENDED_VARIABLES`].
+    Appended,
+}
+
+impl std::fmt::Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::Machine => write!(f, "machine"),
+            Origin::User => write!(f, "user"),
+            Origin::Appended => write!(f, "appended"),
+        }
+    }
+}
+
+/// A variable as a new process sees it, fully expanded.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct EffectiveVariable {
+    pub name: String,
+    pub value: String,
+    pub origin: Origin,
+    /// `%NAME%` references that were still undefined when this was expanded.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unresolved: Vec<String>,
+}
+
+/// The environment a process started from Explorer gets, sorted by name.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct EffectiveEnvironment {
+    vars: BTreeMap<String, EffectiveVariable>,
+}
+
+impl EffectiveEnvironment {
+    /// Build the environment the way Windows does at logon:
+    ///
+    /// 1. Start from `defaults`.
+    /// 2. Apply machine variables, then user variables. Within each scope,
+    ///    `REG_SZ` values are set first and `REG_EXPAND_SZ` values after, in
+    ///    order, each expanded against everything set so far. A reference to a
+    ///    variable that is only set later in the same pass stays unexpanded.
+    /// 3. User values of [`APPENDED_VARIABLES`] are added to the end of the
+    ///    machine value rather than replacing it.
+    ///
+    /// Values that are not strings are not part of the environment.
+    pub fn simulate(defaults: &[(String, String)], vars: &[EnvironmentVariable]) -> Self {
+        let mut env = Self::default();
+        for (name, value) in defaults {
+            env.set(name, value.clone(), Origin::Default, Vec::new());
+        }
+        for scope in EnvScope::ALL {
+            let in_scope = || vars.iter().filter(move |var| var.scope == scope);
+            let plain = in_scope().filter(|var| var.kind == EnvValueKind::String);
+            let expandable = in_scope().filter(|var| var.kind == EnvValueKind::ExpandString);
+            for var in plain.chain(expandable) {
+                let (value, unresolved) = if var.kind == EnvValueKind::ExpandString {
+                    let expansion = expand(&var.value, &env.variable_set());
+                    (expansion.output, expansion.unresolved)
+                } else {
+                    (var.value.clone(), Vec::new())
+                };
+                env.apply(scope, &var.key, value, unresolved);
+            }
+        }
+        env
+    }
+
+    fn apply(&mut self, scope: EnvScope, name: &str, value: String, unresolved: Vec<String>) {
+        let appends = APPENDED_VARIABLES
+            .iter()
+            .any(|appended| appended.eq_ignore_ascii_case(name));
+        if scope == EnvScope::User
+            && appends
+            && let Some(existing) = self.vars.get_mut(&name.to_uppercase())
+            && existing.origin == Origin::Machine
+        {
+            if !value.is_empty() {
+                if !existing.value.is_empty() && !existing.value.ends_with(';') {
+                    existing.value.push(';');
+                }
+                existing.value.push_str(&value);
+            }
+            existing.origin = Origin::Appended;
+            existing.unresolved.extend(unresolved);
+            return;
+        }
+        let origin = match scope {
+            EnvScope::Machine => Origin::Machine,
+            EnvScope::User => Origin::User,
+        };
+        self.set(name, value, origin, unresolved);
+    }
+
+    /// Set or replace a variable. A replaced variable keeps its original spelling.
+    fn set(&mut self, name: &str, value: String, origin: Origin, unresolved: Vec<String>) {
+        let entry = self
+            .vars
+            .entry(name.to_uppercase())
+            .or_insert_with(|| EffectiveVariable {
+                name: name.to_string(),
+                value: String::new(),
+                origin,
+                unresolved: Vec::new(),
+            });
+        entry.value = value;
+        entry.origin = origin;
+        entry.unresolved = unresolved;
+    }
+
+    pub fn get(&self, name: &str) -> Option<&EffectiveVariable> {
+        self.vars.get(&name.to_uppercase())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EffectiveVariable> {
+        self.vars.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+
+    /// The final values, for expanding text the way a new process would.
+    pub fn variable_set(&self) -> VariableSet {
+        let mut set = VariableSet::new();
+        for var in self.vars.values() {
+            let scope = match var.origin {
+                Origin::Machine => Some(EnvScope::Machine),
+                Origin::User | Origin::Appended => Some(EnvScope::User),
+                Origin::Default => None,
+            };
+            set.insert(&var.name, &var.value, scope);
+        }
+        set
+    }
+}
+
+/// The [`DEFAULT_VARIABLES`] that this process has, which on Windows are the
+/// ones its session was started with.
+pub fn process_defaults() -> Vec<(String, String)> {
+    DEFAULT_VARIABLES
+        .iter()
+        .filter_map(|name| Some((name.to_string(), std::env::var(name).ok()?)))
+        .collect()
+}
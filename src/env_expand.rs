@@ -0,0 +1,224 @@
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// A set of variables that `%NAME%` references are resolved against.
+///
+/// Lookups are case-insensitive. This can be built from one scope, several
+/// scopes merged, or a hypothetical snapshot that was never written anywhere.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct VariableSet {
+    vars: BTreeMap<String, VariableEntry>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct VariableEntry {
+    pub name: String,
+    pub value: String,
+    pub scope: Option<EnvScope>,
+}
+
+impl VariableSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build a set from raw variable values. Later variables win, so pass
+    /// machine variables before user ones to get the usual override order.
+    pub fn from_vars<'a>(vars: impl IntoIterator<Item = &'a EnvironmentVariable>) -> Self {
+        let mut set = Self::new();
+        for var in vars {
+            set.insert(&var.key, &var.value, Some(var.scope));
+        }
+        set
+    }
+
+    /// Add or replace a variable.
+    pub fn insert(&mut self, name: &str, value: &str, scope: Option<EnvScope>) {
+        self.vars.insert(
+            name.to_uppercase(),
+            VariableEntry {
+                name: name.to_string(),
+                value: value.to_string(),
+                scope,
+            },
+        );
+    }
+
+    pub fn get(&self, name: &str) -> Option<&VariableEntry> {
+        self.vars.get(&name.to_uppercase())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &VariableEntry> {
+        self.vars.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.vars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vars.is_empty()
+    }
+}
+
+/// Where a piece of expanded output came from.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum SpanOrigin {
+    /// Copied straight from the input, including unresolved references.
+    Literal,
+    /// The value of a variable that a `%NAME%` reference resolved to.
+    Variable {
+        name: String,
+        scope: Option<EnvScope>,
+    },
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct ExpansionSpan {
+    /// Byte range in the expanded output.
+    pub output: Range<usize>,
+    /// Byte range in the input, e.g. covering `%SystemRoot%` for a variable.
+    pub source: Range<usize>,
+    pub origin: SpanOrigin,
+}
+
+/// The result of expanding a string, with a trace of where each part came from.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct Expansion {
+    pub output: String,
+    pub spans: Vec<ExpansionSpan>,
+    /// Names between `%` pairs that did not resolve and were left as-is.
+    ///
+    /// Text like the `;` in `%A%;%B%` also sits between two `%`, so only
+    /// plausible variable names are reported.
+    pub unresolved: Vec<String>,
+}
+
+impl Expansion {
+    /// The spans that came from variables.
+    pub fn substitutions(&self) -> impl Iterator<Item = &ExpansionSpan> {
+        self.spans
+            .iter()
+            .filter(|span| matches!(span.origin, SpanOrigin::Variable { .. }))
+    }
+
+    fn push_literal(&mut self, input: &str, source: Range<usize>) {
+        if source.is_empty() {
+            return;
+        }
+        let start = self.output.len();
+        self.output.push_str(&input[source.clone()]);
+        // Merge with a preceding literal so the trace stays readable
+        if let Some(last) = self.spans.last_mut()
+            && last.origin == SpanOrigin::Literal
+            && last.source.end == source.start
+        {
+            last.source.end = source.end;
+            last.output.end = self.output.len();
+            return;
+        }
+        self.spans.push(ExpansionSpan {
+            output: start..self.output.len(),
+            source,
+            origin: SpanOrigin::Literal,
+        });
+    }
+}
+
+/// Fill in [`EnvironmentVariable::value_expanded`] for every expandable value,
+/// resolving references against `vars` themselves.
+pub fn fill_expanded(vars: &mut [EnvironmentVariable]) {
+    let set = VariableSet::from_vars(vars.iter());
+    for var in vars {
+        if var.kind == EnvValueKind::ExpandString {
+            var.value_expanded = Some(expand(&var.value, &set).output);
+        }
+    }
+}
+
+/// Expand `%NAME%` references in `input` the way `ExpandEnvironmentStringsW` does.
+///
+/// * Names are matched case-insensitively.
+/// * Substituted values are not expanded again.
+/// * An unknown name is left as-is, and its closing `%` is reconsidered as the
+///   start of the next reference, so `%NOPE%PATH%` still expands `%PATH%`.
+/// * `%%` is an empty name, which never resolves, so both `%` are kept.
+pub fn expand(input: &str, vars: &VariableSet) -> Expansion {
+    let mut expansion = Expansion {
+        output: String::with_capacity(input.len()),
+        spans: Vec::new(),
+        unresolved: Vec::new(),
+    };
+    let mut literal_start = 0;
+    let mut pos = 0;
+    while let Some(offset) = input[pos..].find('%') {
+        let open = pos + offset;
+        let Some(len) = input[open + 1..].find('%') else {
+            break;
+        };
+        let close = open + 1 + len;
+        let name = &input[open + 1..close];
+        match vars.get(name).filter(|_| !name.is_empty()) {
+            Some(entry) => {
+                expansion.push_literal(input, literal_start..open);
+                let start = expansion.output.len();
+                expansion.output.push_str(&entry.value);
+                expansion.spans.push(ExpansionSpan {
+                    output: start..expansion.output.len(),
+                    source: open..close + 1,
+                    origin: SpanOrigin::Variable {
+                        name: entry.name.clone(),
+                        scope: entry.scope,
+                    },
+                });
+                pos = close + 1;
+                literal_start = pos;
+            }
+            None => {
+                if looks_like_name(name) && !expansion.unresolved.iter().any(|n| n == name) {
+                    expansion.unresolved.push(name.to_string());
+                }
+                pos = close;
+            }
+        }
+    }
+    expansion.push_literal(input, literal_start..input.len());
+    expansion
+}
+
+/// Whether the text between two `%` could be a variable name rather than the
+/// gap between two neighbouring references.
+fn looks_like_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains([';', '\\', '/'])
+}
+
+/// Every `%NAME%` reference in `value` that resolves against `vars`, or every
+/// plausible-looking one when `vars` is `None`.
+///
+/// The same scanning rules as [`expand`] apply, so this agrees with what
+/// Windows would substitute.
+pub fn references(value: &str, vars: Option<&VariableSet>) -> Vec<(Range<usize>, String)> {
+    let mut rtn = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = value[pos..].find('%') {
+        let open = pos + offset;
+        let Some(len) = value[open + 1..].find('%') else {
+            break;
+        };
+        let close = open + 1 + len;
+        let name = &value[open + 1..close];
+        let resolves = looks_like_name(name) && vars.is_none_or(|vars| vars.get(name).is_some());
+        if resolves {
+            rtn.push((open..close + 1, name.to_string()));
+            pos = close + 1;
+        } else {
+            pos = close;
+        }
+    }
+    rtn
+}
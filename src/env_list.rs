@@ -0,0 +1,187 @@
+use crate::data_dir::data_dir;
+use eyre::Context;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// What the entries of a list variable are, which decides how they are
+/// compared and what `path lint` checks.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    /// Directories, like `Path`. Compared after expanding, unquoting and
+    /// dropping trailing separators, ignoring case, and expected to exist.
+    #[default]
+    Directory,
+    /// Files or directories, like `CLASSPATH`. Compared like directories.
+    Path,
+    /// File extensions starting with `.`, like `PATHEXT`, compared ignoring case.
+    Extension,
+    /// Anything else, compared exactly after trimming whitespace.
+    Text,
+}
+
+impl std::fmt::Display for EntryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EntryKind::Directory => write!(f, "directory"),
+            EntryKind::Path => write!(f, "path"),
+            EntryKind::Extension => write!(f, "extension"),
+            EntryKind::Text => write!(f, "text"),
+        }
+    }
+}
+
+/// Declares a variable as holding a list, and how to split and compare it.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ListSpec {
+    pub name: String,
+    #[serde(default = "default_separator")]
+    pub separator: char,
+    #[serde(default)]
+    pub entries: EntryKind,
+}
+
+fn default_separator() -> char {
+    ';'
+}
+
+impl ListSpec {
+    pub fn new(name: &str, separator: char, entries: EntryKind) -> Self {
+        Self {
+            name: name.to_string(),
+            separator,
+            entries,
+        }
+    }
+
+    /// How `Path` is split and compared.
+    pub fn path() -> Self {
+        Self::new("Path", ';', EntryKind::Directory)
+    }
+}
+
+/// The list variables env-edit knows about without being told.
+pub fn builtin_lists() -> Vec<ListSpec> {
+    vec![
+        ListSpec::path(),
+        ListSpec::new("PATHEXT", ';', EntryKind::Extension),
+        ListSpec::new("PSModulePath", ';', EntryKind::Directory),
+        ListSpec::new("CLASSPATH", ';', EntryKind::Path),
+    ]
+}
+
+/// The built-in list variables plus the ones declared in `lists.json` in the
+/// [`data_dir`]. A declaration with the same name as a built-in replaces it.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ListSpecs {
+    path: Option<PathBuf>,
+    declared: Vec<ListSpec>,
+}
+
+impl ListSpecs {
+    /// Only the built-in lists, with nowhere to save declarations.
+    pub fn builtin() -> Self {
+        Self::default()
+    }
+
+    /// Read declarations from `path`. A missing file declares nothing.
+    pub fn load(path: impl Into<PathBuf>) -> eyre::Result<Self> {
+        let path = path.into();
+        let declared = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .wrap_err_with(|| format!("Failed to parse {}", path.display()))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => {
+                return Err(error).wrap_err_with(|| format!("Failed to read {}", path.display()));
+            }
+        };
+        Ok(Self {
+            path: Some(path),
+            declared,
+        })
+    }
+
+    pub fn open_default() -> eyre::Result<Self> {
+        Self::load(data_dir()?.join("lists.json"))
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// How `name` is declared, if it is a list variable.
+    pub fn get(&self, name: &str) -> Option<ListSpec> {
+        self.declared
+            .iter()
+            .find(|spec| spec.name.eq_ignore_ascii_case(name))
+            .cloned()
+            .or_else(|| {
+                builtin_lists()
+                    .into_iter()
+                    .find(|spec| spec.name.eq_ignore_ascii_case(name))
+            })
+    }
+
+    /// How to treat `name` as a list, falling back to `;`-separated text when
+    /// it was never declared.
+    pub fn spec_for(&self, name: &str) -> ListSpec {
+        self.get(name)
+            .unwrap_or_else(|| ListSpec::new(name, ';', EntryKind::Text))
+    }
+
+    /// Every list variable, with whether it was declared rather than built in.
+    pub fn all(&self) -> Vec<(ListSpec, bool)> {
+        let mut rtn: Vec<(ListSpec, bool)> = builtin_lists()
+            .into_iter()
+            .filter(|builtin| {
+                !self
+                    .declared
+                    .iter()
+                    .any(|spec| spec.name.eq_ignore_ascii_case(&builtin.name))
+            })
+            .map(|spec| (spec, false))
+            .collect();
+        rtn.extend(self.declared.iter().map(|spec| (spec.clone(), true)));
+        rtn
+    }
+
+    /// Add or replace the declaration for `spec.name`.
+    pub fn declare(&mut self, spec: ListSpec) -> eyre::Result<()> {
+        if spec.name.is_empty() || spec.name.contains('=') {
+            eyre::bail!("{:?} is not a valid variable name", spec.name);
+        }
+        if spec.separator == '%'
+            || spec.separator.is_alphanumeric()
+            || spec.separator.is_whitespace()
+        {
+            eyre::bail!("{:?} cannot be used as a separator", spec.separator);
+        }
+        self.forget(&spec.name);
+        self.declared.push(spec);
+        Ok(())
+    }
+
+    /// Remove the declaration for `name`, returning whether there was one.
+    /// A built-in list goes back to its built-in declaration.
+    pub fn forget(&mut self, name: &str) -> bool {
+        let before = self.declared.len();
+        self.declared
+            .retain(|spec| !spec.name.eq_ignore_ascii_case(name));
+        self.declared.len() != before
+    }
+
+    pub fn save(&self) -> eyre::Result<()> {
+        let Some(path) = &self.path else {
+            eyre::bail!("These list declarations were not loaded from a file");
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("Failed to create {}", parent.display()))?;
+        }
+        let json = serde_json::to_string_pretty(&self.declared)?;
+        std::fs::write(path, json + "\n")
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,547 @@
+use crate::env_expand::VariableSet;
+use crate::env_expand::expand;
+use crate::env_list::EntryKind;
+use crate::env_list::ListSpec;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::has_env_reference;
+use crate::env_validate::Severity;
+use serde::Serialize;
+
+/// The name Windows gives the search path variable.
+pub const PATH_VAR: &str = "Path";
+
+/// Variables whose values commonly start `Path` entries, and which
+/// [`PathList::compact`] may substitute back in.
+pub const PREFIX_VARIABLES: &[&str] = &[
+    "SystemRoot",
+    "ProgramFiles",
+    "ProgramFiles(x86)",
+    "ProgramData",
+    "USERPROFILE",
+    "LOCALAPPDATA",
+    "APPDATA",
+];
+
+/// The [`PREFIX_VARIABLES`] that mean the same thing for everyone who logs on.
+/// The rest point into one user's profile, so only belong in a user `Path`.
+pub fn prefix_variables(scope: EnvScope) -> Vec<&'static str> {
+    PREFIX_VARIABLES
+        .iter()
+        .copied()
+        .filter(|name| {
+            scope == EnvScope::User || !matches!(*name, "USERPROFILE" | "LOCALAPPDATA" | "APPDATA")
+        })
+        .collect()
+}
+
+/// Where to put an entry in a [`PathList`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Position {
+    Prepend,
+    Append,
+    /// Right before the first entry matching this one.
+    Before(String),
+    /// At this index, counting from 0. Past the end means appending.
+    Index(usize),
+}
+
+/// A list value, such as `Path`, split into its entries.
+///
+/// Entries are kept exactly as written, including empty ones, so an
+/// unchanged list always joins back into the same value. How entries are
+/// compared and checked depends on their [`EntryKind`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PathList {
+    pub entries: Vec<String>,
+    pub separator: char,
+    pub kind: EntryKind,
+}
+
+impl Default for PathList {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            separator: ';',
+            kind: EntryKind::Directory,
+        }
+    }
+}
+
+impl PathList {
+    /// Split a `Path` style value: `;`-separated directories.
+    pub fn parse(value: &str) -> Self {
+        Self::parse_as(value, &ListSpec::path())
+    }
+
+    /// Split a value of the list variable described by `spec`.
+    pub fn parse_as(value: &str, spec: &ListSpec) -> Self {
+        let list = Self {
+            entries: Vec::new(),
+            separator: spec.separator,
+            kind: spec.entries,
+        };
+        if value.is_empty() {
+            return list;
+        }
+        list.with_entries(value.split(spec.separator).map(str::to_string).collect())
+    }
+
+    /// A list of the same kind holding `entries`.
+    pub fn with_entries(&self, entries: Vec<String>) -> Self {
+        Self {
+            entries,
+            separator: self.separator,
+            kind: self.kind,
+        }
+    }
+
+    pub fn to_value(&self) -> String {
+        self.entries.join(&self.separator.to_string())
+    }
+
+    /// The form of `entry` used to decide whether two entries are the same.
+    pub fn normalize(&self, entry: &str, vars: &VariableSet) -> String {
+        match self.kind {
+            EntryKind::Directory | EntryKind::Path => normalize_entry(entry, vars),
+            EntryKind::Extension => entry.trim().to_lowercase(),
+            EntryKind::Text => entry.trim().to_string(),
+        }
+    }
+
+    /// The index of the first entry that is the same as `entry`.
+    pub fn position(&self, entry: &str, vars: &VariableSet) -> Option<usize> {
+        let wanted = self.normalize(entry, vars);
+        self.entries
+            .iter()
+            .position(|existing| self.normalize(existing, vars) == wanted)
+    }
+
+    /// Add `entry` at `position`. Returns the index it ended up at, or an
+    /// error if it is already in the list.
+    pub fn insert(
+        &mut self,
+        entry: &str,
+        position: &Position,
+        vars: &VariableSet,
+    ) -> eyre::Result<usize> {
+        if entry.trim().is_empty() || entry.contains(self.separator) {
+            eyre::bail!("{entry:?} is not a single entry");
+        }
+        if self.kind == EntryKind::Extension && !is_extension(entry) {
+            eyre::bail!("{entry:?} is not an extension like .EXE");
+        }
+        if let Some(existing) = self.position(entry, vars) {
+            eyre::bail!(
+                "{entry} is already in the list at index {existing} as {}",
+                self.entries[existing]
+            );
+        }
+        let index = self.resolve(position, vars)?;
+        self.entries.insert(index, entry.to_string());
+        Ok(index)
+    }
+
+    /// Remove every entry that is the same as `entry`, returning what was removed.
+    pub fn remove(&mut self, entry: &str, vars: &VariableSet) -> Vec<String> {
+        let wanted = self.normalize(entry, vars);
+        let mut removed = Vec::new();
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.retain(|existing| {
+            let matches = self.normalize(existing, vars) == wanted;
+            if matches {
+                removed.push(existing.clone());
+            }
+            !matches
+        });
+        self.entries = entries;
+        removed
+    }
+
+    /// Move `entry` to `position`, returning its new index.
+    pub fn move_entry(
+        &mut self,
+        entry: &str,
+        position: &Position,
+        vars: &VariableSet,
+    ) -> eyre::Result<usize> {
+        let Some(from) = self.position(entry, vars) else {
+            eyre::bail!("{entry} is not in the list");
+        };
+        if let Position::Before(anchor) = position
+            && self.normalize(anchor, vars) == self.normalize(entry, vars)
+        {
+            eyre::bail!("Cannot move {entry} before itself");
+        }
+        let moved = self.entries.remove(from);
+        let index = match self.resolve(position, vars) {
+            Ok(index) => index,
+            Err(error) => {
+                self.entries.insert(from, moved);
+                return Err(error);
+            }
+        };
+        self.entries.insert(index, moved);
+        Ok(index)
+    }
+
+    /// Remove later entries that are the same as one already in the list.
+    /// The first one wins because it is the one Windows searches.
+    pub fn dedupe(&mut self, vars: &VariableSet) -> Vec<String> {
+        let mut seen = Vec::new();
+        let mut removed = Vec::new();
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.retain(|entry| {
+            let normalized = self.normalize(entry, vars);
+            // Empty entries are not duplicates of anything
+            if normalized.is_empty() {
+                return true;
+            }
+            if seen.contains(&normalized) {
+                removed.push(entry.clone());
+                false
+            } else {
+                seen.push(normalized);
+                true
+            }
+        });
+        self.entries = entries;
+        removed
+    }
+
+    /// Look for entries that are broken, redundant or slow. Directory and path
+    /// entries are expanded against `vars` and checked on disk, except
+    /// network paths.
+    pub fn lint(&self, vars: &VariableSet) -> Vec<PathIssue> {
+        let mut issues = Vec::new();
+        let mut seen: Vec<(String, usize)> = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let mut report = |problem| {
+                issues.push(PathIssue {
+                    index,
+                    entry: entry.clone(),
+                    problem,
+                })
+            };
+            if entry.trim().is_empty() {
+                report(PathProblem::Empty);
+                continue;
+            }
+            let normalized = self.normalize(entry, vars);
+            if let Some(&(_, first)) = seen.iter().find(|(seen, _)| *seen == normalized) {
+                report(PathProblem::Duplicate { first });
+                continue;
+            }
+            seen.push((normalized, index));
+
+            match self.kind {
+                EntryKind::Directory | EntryKind::Path => {}
+                EntryKind::Extension => {
+                    if !is_extension(entry) {
+                        report(PathProblem::NotAnExtension);
+                    }
+                    continue;
+                }
+                EntryKind::Text => continue,
+            }
+            let expansion = expand(entry.trim(), vars);
+            let expanded = expansion.output;
+            if expanded.contains('"') {
+                report(PathProblem::Quoted);
+            }
+            let path = expanded.trim().trim_matches('"');
+            if is_network_path(path) {
+                // Checking would mean waiting on the network, which is the problem
+                report(PathProblem::Network);
+                continue;
+            }
+            // It may well exist once the variable is defined, such as in another user's session
+            if !expansion.unresolved.is_empty() {
+                report(PathProblem::Unresolved {
+                    names: expansion.unresolved,
+                });
+                continue;
+            }
+            if !is_absolute_path(path) {
+                report(PathProblem::Relative);
+                continue;
+            }
+            // A drive letter means nothing here, e.g. when editing a JSON store
+            if cfg!(not(windows)) && is_drive_path(path) {
+                continue;
+            }
+            match std::fs::metadata(path) {
+                Ok(metadata) if metadata.is_dir() || self.kind == EntryKind::Path => {}
+                Ok(_) => report(PathProblem::NotADirectory),
+                Err(_) => report(PathProblem::Missing),
+            }
+        }
+        issues
+    }
+
+    /// The list with the fixable `issues` from [`PathList::lint`] fixed:
+    /// quotes are removed, extensions get their missing `.`, and empty,
+    /// duplicate and file entries are dropped. Missing entries are only
+    /// dropped with `drop_missing`.
+    pub fn fixed(&self, issues: &[PathIssue], drop_missing: bool) -> PathList {
+        let mut entries = Vec::new();
+        for (index, entry) in self.entries.iter().enumerate() {
+            let problems = || {
+                issues
+                    .iter()
+                    .filter(move |issue| issue.index == index)
+                    .map(|issue| &issue.problem)
+            };
+            if problems().any(|problem| {
+                matches!(
+                    problem,
+                    PathProblem::Empty | PathProblem::Duplicate { .. } | PathProblem::NotADirectory
+                ) || (drop_missing && *problem == PathProblem::Missing)
+            }) {
+                continue;
+            }
+            if problems().any(|problem| *problem == PathProblem::Quoted) {
+                entries.push(entry.replace('"', "").trim().to_string());
+            } else if problems().any(|problem| *problem == PathProblem::NotAnExtension) {
+                entries.push(format!(".{}", entry.trim().trim_start_matches('.')));
+            } else {
+                entries.push(entry.clone());
+            }
+        }
+        self.with_entries(entries)
+    }
+
+    /// A shorter list meaning the same thing: fixable [`PathList::lint`]
+    /// problems such as duplicates are fixed, and missing entries dropped with
+    /// `drop_missing`. Then each remaining entry that starts with the value of
+    /// one of `prefixes` has it replaced by a `%NAME%` reference, when that is
+    /// shorter.
+    pub fn compact(
+        &self,
+        vars: &VariableSet,
+        prefixes: &VariableSet,
+        drop_missing: bool,
+    ) -> Compaction {
+        let removed: Vec<PathIssue> = self
+            .lint(vars)
+            .into_iter()
+            .filter(|issue| issue.problem.is_fixable(drop_missing))
+            .collect();
+        let mut list = self.fixed(&removed, drop_missing);
+        let mut substituted = Vec::new();
+        for entry in &mut list.entries {
+            if let Some(shorter) = substitute_prefix(entry, prefixes) {
+                substituted.push((entry.clone(), shorter.clone()));
+                *entry = shorter;
+            }
+        }
+        Compaction {
+            list,
+            removed,
+            substituted,
+        }
+    }
+
+    fn resolve(&self, position: &Position, vars: &VariableSet) -> eyre::Result<usize> {
+        Ok(match position {
+            Position::Prepend => 0,
+            Position::Append => self.entries.len(),
+            Position::Index(index) => (*index).min(self.entries.len()),
+            Position::Before(anchor) => match self.position(anchor, vars) {
+                Some(index) => index,
+                None => eyre::bail!("{anchor} is not in the list"),
+            },
+        })
+    }
+}
+
+/// The result of [`PathList::compact`].
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Compaction {
+    pub list: PathList,
+    /// The problems that were fixed by removing or unquoting an entry.
+    pub removed: Vec<PathIssue>,
+    /// Entries rewritten to use a variable, as `(before, after)`.
+    pub substituted: Vec<(String, String)>,
+}
+
+/// `entry` with its longest prefix that is the value of a variable in
+/// `prefixes` replaced by a reference to it, if that makes it shorter.
+/// Prefixes only match whole path components.
+fn substitute_prefix(entry: &str, prefixes: &VariableSet) -> Option<String> {
+    let mut best: Option<String> = None;
+    for var in prefixes.iter() {
+        let prefix = var.value.trim_end_matches(['\\', '/']);
+        if prefix.is_empty() || has_env_reference(prefix) {
+            continue;
+        }
+        let Some(head) = entry.get(..prefix.len()) else {
+            continue;
+        };
+        let rest = &entry[prefix.len()..];
+        if !head.eq_ignore_ascii_case(prefix) || !(rest.is_empty() || rest.starts_with(['\\', '/']))
+        {
+            continue;
+        }
+        let candidate = format!("%{}%{rest}", var.name);
+        if candidate.len() < entry.len()
+            && best
+                .as_ref()
+                .is_none_or(|best| candidate.len() < best.len())
+        {
+            best = Some(candidate);
+        }
+    }
+    best
+}
+
+/// Something wrong with a single entry of a [`PathList`].
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "problem")]
+pub enum PathProblem {
+    /// The same as the entry at `first`, so it is never used.
+    Duplicate { first: usize },
+    /// Nothing between two separators.
+    Empty,
+    /// Has `"` in it. Windows searches the quotes as part of the directory name.
+    Quoted,
+    /// Resolved against whatever the current directory happens to be.
+    Relative,
+    /// On a network share, which every lookup has to wait for.
+    Network,
+    /// References variables that are not defined, so it could not be checked.
+    Unresolved { names: Vec<String> },
+    /// Does not exist.
+    Missing,
+    /// Exists but is a file.
+    NotADirectory,
+    /// In an extension list, but does not look like `.EXE`.
+    NotAnExtension,
+}
+
+impl PathProblem {
+    pub fn severity(&self) -> Severity {
+        match self {
+            PathProblem::Quoted | PathProblem::NotADirectory | PathProblem::NotAnExtension => {
+                Severity::Error
+            }
+            PathProblem::Duplicate { .. }
+            | PathProblem::Relative
+            | PathProblem::Network
+            | PathProblem::Unresolved { .. }
+            | PathProblem::Missing => Severity::Warning,
+            PathProblem::Empty => Severity::Info,
+        }
+    }
+
+    /// Whether [`PathList::fixed`] does something about it. A missing entry
+    /// may be a drive that is not plugged in or a tool about to be installed,
+    /// so it is only removed with `drop_missing`.
+    pub fn is_fixable(&self, drop_missing: bool) -> bool {
+        match self {
+            PathProblem::Relative | PathProblem::Network | PathProblem::Unresolved { .. } => false,
+            PathProblem::Missing => drop_missing,
+            _ => true,
+        }
+    }
+}
+
+impl std::fmt::Display for PathProblem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathProblem::Duplicate { first } => {
+                write!(f, "duplicate of index {first}, which is searched first")
+            }
+            PathProblem::Empty => write!(f, "empty entry"),
+            PathProblem::Quoted => write!(f, "quoted, Windows does not remove the quotes"),
+            PathProblem::Relative => write!(f, "relative to the current directory"),
+            PathProblem::Network => write!(f, "network path, lookups wait on the network"),
+            PathProblem::Unresolved { names } => {
+                let names: Vec<String> = names.iter().map(|name| format!("%{name}%")).collect();
+                write!(f, "{} not defined, so it was not checked", names.join(", "))
+            }
+            PathProblem::Missing => write!(f, "does not exist"),
+            PathProblem::NotADirectory => write!(f, "is a file, not a directory"),
+            PathProblem::NotAnExtension => write!(f, "is not an extension starting with ."),
+        }
+    }
+}
+
+/// A problem with the entry at `index` of a [`PathList`].
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct PathIssue {
+    pub index: usize,
+    pub entry: String,
+    #[serde(flatten)]
+    pub problem: PathProblem,
+}
+
+impl PathIssue {
+    pub fn severity(&self) -> Severity {
+        self.problem.severity()
+    }
+}
+
+impl std::fmt::Display for PathIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: [{}] {}: {}",
+            self.severity(),
+            self.index,
+            self.entry,
+            self.problem
+        )
+    }
+}
+
+/// `.EXE`, but not `.` or `EXE`.
+fn is_extension(entry: &str) -> bool {
+    let entry = entry.trim();
+    entry.len() > 1 && entry.starts_with('.') && !entry.contains(['\\', '/', ' ', '\t', '"'])
+}
+
+/// `\\server\share` or `//server/share`.
+fn is_network_path(path: &str) -> bool {
+    path.starts_with("\\\\") || path.starts_with("//")
+}
+
+/// A path starting at a drive root, like `C:\`, or absolute on this platform.
+/// `\Tools` is relative because it depends on the current drive.
+fn is_absolute_path(path: &str) -> bool {
+    is_drive_path(path) || (cfg!(not(windows)) && std::path::Path::new(path).is_absolute())
+}
+
+/// A path starting at a drive root, like `C:\`.
+fn is_drive_path(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes[2], b'\\' | b'/')
+}
+
+/// The form of an entry used to decide whether two entries are the same
+/// directory: expanded, unquoted, with `\` separators, without a trailing
+/// separator and lowercased.
+pub fn normalize_entry(entry: &str, vars: &VariableSet) -> String {
+    let expanded = expand(entry.trim(), vars).output;
+    let mut normalized = expanded.trim().trim_matches('"').replace('/', "\\");
+    // Keep the separator of a drive root like `C:\`
+    while normalized.ends_with('\\') && !(normalized.len() == 3 && normalized.ends_with(":\\")) {
+        normalized.pop();
+    }
+    normalized.to_lowercase()
+}
+
+/// The kind to write a list back as. An existing `REG_EXPAND_SZ` stays that
+/// way, and a value that gains a `%NAME%` reference becomes one so the
+/// reference keeps working.
+pub fn list_kind(existing: Option<EnvValueKind>, value: &str) -> EnvValueKind {
+    match existing {
+        Some(EnvValueKind::ExpandString) => EnvValueKind::ExpandString,
+        _ if has_env_reference(value) => EnvValueKind::ExpandString,
+        Some(kind) if kind.is_string() => kind,
+        _ => EnvValueKind::String,
+    }
+}
@@ -1,266 +1,537 @@
-use crate::win_strings::utf16_from_bytes;
-use crate::win_strings::MACHINE_ENV_SUB_KEY;
-use eyre::bail;
-use eyre::eyre;
+use crate::win_strings::utf16_bytes_null;
+use crate::win_strings::utf16_units_from_bytes;
+use eyre::Context;
 use serde::Deserialize;
 use serde::Serialize;
-use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
-use windows::Win32::Foundation::ERROR_MORE_DATA;
-use windows::Win32::Foundation::ERROR_NO_MORE_ITEMS;
-use windows::Win32::Foundation::ERROR_SUCCESS;
-use windows::Win32::Foundation::WIN32_ERROR;
-use windows::Win32::System::Environment::ExpandEnvironmentStringsW;
-use windows::Win32::System::Registry::HKEY;
-use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
-use windows::Win32::System::Registry::KEY_QUERY_VALUE;
-use windows::Win32::System::Registry::KEY_READ;
-use windows::Win32::System::Registry::REG_EXPAND_SZ;
-use windows::Win32::System::Registry::REG_SZ;
-use windows::Win32::System::Registry::REG_VALUE_TYPE;
-use windows::Win32::System::Registry::RegCloseKey;
-use windows::Win32::System::Registry::RegEnumValueW;
-use windows::Win32::System::Registry::RegOpenKeyExW;
-use windows::Win32::System::Registry::RegQueryValueExW;
-use windows::core::PCWSTR;
-use windows::core::PWSTR;
-
-
-pub fn list_machine_env_var() -> eyre::Result<Vec<EnvironmentVariable>> {
-    let mut hkey: HKEY = HKEY::default();
-    unsafe {
-        RegOpenKeyExW(
-            HKEY_LOCAL_MACHINE,
-            MACHINE_ENV_SUB_KEY,
-            None,
-            KEY_READ,
-            &mut hkey,
-        )
-        .ok()?;
-    }
 
-    let rtn = try {
-        let mut index = 0;
-        let mut rtn = Vec::new();
-        loop {
-            // We'll keep dynamic buffers that we can expand if needed
-            let mut name_buf = vec![0u16; 256]; // 256 wide chars for the name
-            let mut data_buf = vec![0u8; 1024]; // 1 KiB for data
-            let mut name_len = name_buf.len() as u32; // length in wide chars for the name
-            let mut data_len = data_buf.len() as u32; // length in bytes for the data
-            let mut value_type = REG_VALUE_TYPE(0);
+/// Which environment a variable belongs to.
+#[derive(
+    Debug, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum EnvScope {
+    /// `HKEY_LOCAL_MACHINE`, shared by every user and requires elevation to change.
+    #[default]
+    Machine,
+    /// `HKEY_CURRENT_USER`, the per-user environment.
+    User,
+}
+impl EnvScope {
+    pub const ALL: [EnvScope; 2] = [EnvScope::Machine, EnvScope::User];
 
-            let status = unsafe {
-                RegEnumValueW(
-                    hkey,
-                    index,
-                    Some(PWSTR(name_buf.as_mut_ptr())),
-                    &mut name_len,
-                    None,
-                    Some(&mut value_type.0),
-                    Some(data_buf.as_mut_ptr()),
-                    Some(&mut data_len),
-                )
-            };
-            match status {
-                ERROR_NO_MORE_ITEMS => {
-                    break rtn;
-                }
-                ERROR_MORE_DATA => {
-                    // Our initial buffers weren't large enough, so let's reallocate
-                    // using the exact size reported in `name_len` or `data_len`.
-                    //
-                    // name_len is the size in wide chars (without the null terminator).
-                    // data_len is in bytes.
-                    let mut bigger_name_buf = vec![0u16; name_len as usize];
-                    let mut bigger_data_buf = vec![0u8; data_len as usize];
-
-                    let status = unsafe {
-                        RegEnumValueW(
-                            hkey,
-                            index,
-                            Some(PWSTR(bigger_name_buf.as_mut_ptr())),
-                            &mut name_len,
-                            None,
-                            Some(&mut value_type.0),
-                            Some(bigger_data_buf.as_mut_ptr()),
-                            Some(&mut data_len),
-                        )
-                    };
-                    if status == ERROR_SUCCESS {
-                        rtn.push(process_value(
-                            &bigger_name_buf[..(name_len as usize)],
-                            &bigger_data_buf[..(data_len as usize)],
-                            value_type,
-                        )?);
-                    } else {
-                        eprintln!("Failed to read bigger buffers: 0x{:X}", status.0);
-                    }
-                }
-                ERROR_SUCCESS => {
-                    // We got the data with our initial buffer
-                    rtn.push(process_value(
-                        &name_buf[..(name_len as usize)],
-                        &data_buf[..(data_len as usize)],
-                        value_type,
-                    )?);
-                }
-                x => {
-                    return Err(eyre!("RegEnumValueW error: 0x{:X}", x.0));
-                }
-            }
-            index += 1;
-        }
-    };
-    unsafe {
-        RegCloseKey(hkey).ok()?;
+    /// Whether writing to this scope needs administrator rights.
+    pub fn requires_elevation(self) -> bool {
+        matches!(self, EnvScope::Machine)
     }
-    rtn
 }
-
-/// Retrieve a machine-level environment variable value (if it exists).
-/// Returns `Ok(None)` if the key was not found, or `Ok(Some(value))` otherwise.
-pub fn get_machine_env_var(var_name: &str) -> eyre::Result<Option<String>> {
-    // Windows registry calls typically want a wide (UTF-16) string with a null terminator
-    let wide_name: Vec<u16> = var_name.encode_utf16().chain(std::iter::once(0)).collect();
-
-    unsafe {
-        // Open the environment sub-key with KEY_QUERY_VALUE
-        let mut hkey: HKEY = HKEY::default();
-        RegOpenKeyExW(
-            HKEY_LOCAL_MACHINE,
-            MACHINE_ENV_SUB_KEY,
-            None,
-            KEY_QUERY_VALUE,
-            &mut hkey,
-        )
-        .ok()?;
-
-        // We'll call RegQueryValueExW to get data size first
-        let mut value_type = REG_VALUE_TYPE(0);
-        let mut data_len: u32 = 0;
-        let query_status = RegQueryValueExW(
-            hkey,
-            PCWSTR(wide_name.as_ptr()),
-            None,
-            Some(&mut value_type),
-            None, // pass None to query the size needed
-            Some(&mut data_len),
-        );
-
-        if let Err(e) = query_status.ok() {
-            let code = WIN32_ERROR::from_error(&e).map(|c| c.0).unwrap_or_default();
-            if code == ERROR_FILE_NOT_FOUND.0 {
-                // Key does not exist
-                RegCloseKey(hkey).ok()?;
-                return Ok(None);
-            } else {
-                // Some other error
-                RegCloseKey(hkey).ok()?;
-                return Err(eyre!(
-                    "Failed to query size for '{var_name}', code=0x{code:X}"
-                ));
-            }
-        }
-
-        // If data_len == 0, either the value is empty or something is off
-        // We'll consider that as an empty string
-        if data_len == 0 {
-            RegCloseKey(hkey).ok()?;
-            return Ok(Some(String::new()));
-        }
-
-        // Allocate a buffer for the actual data
-        let mut data_buf = vec![0u8; data_len as usize];
-        RegQueryValueExW(
-            hkey,
-            PCWSTR(wide_name.as_ptr()),
-            None,
-            Some(&mut value_type),
-            Some(data_buf.as_mut_ptr()),
-            Some(&mut data_len),
-        )
-        .ok()?;
-
-        RegCloseKey(hkey).ok()?;
-
-        // We only handle REG_SZ or REG_EXPAND_SZ in this example
-        if value_type != REG_SZ {
-            // If you also expect REG_EXPAND_SZ, you could handle that,
-            // but for simplicity we just show REG_SZ
-            return Ok(Some(utf16_from_bytes(&data_buf)));
+impl std::fmt::Display for EnvScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvScope::Machine => write!(f, "machine"),
+            EnvScope::User => write!(f, "user"),
         }
-        Ok(Some(utf16_from_bytes(&data_buf)))
     }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub struct EnvironmentVariable {
+    pub scope: EnvScope,
     pub key: String,
     pub value: String,
+    /// The value with `%NAME%` references expanded, see
+    /// [`fill_expanded`](crate::env_expand::fill_expanded).
     pub value_expanded: Option<String>,
-    #[serde(skip)]
-    pub kind: REG_VALUE_TYPE,
+    pub kind: EnvValueKind,
+    /// Problems noticed while reading the value, e.g. a kind Windows ignores.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub diagnostics: Vec<String>,
 }
 impl EnvironmentVariable {
+    pub fn new(
+        scope: EnvScope,
+        key: impl Into<String>,
+        value: impl Into<String>,
+        kind: EnvValueKind,
+    ) -> Self {
+        Self {
+            scope,
+            key: key.into(),
+            value: value.into(),
+            value_expanded: None,
+            kind,
+            diagnostics: Vec::new(),
+        }
+    }
+
     pub fn get_value(&self) -> &str {
         self.value_expanded.as_ref().unwrap_or(&self.value)
     }
 }
 
-/// Helper to interpret and print the registry value from raw buffers.
-fn process_value(
-    name_wchars: &[u16],
-    data_bytes: &[u8],
-    value_type: REG_VALUE_TYPE,
-) -> eyre::Result<EnvironmentVariable> {
-    // Convert the name from UTF-16
-    let name_end = name_wchars
+/// The registry value type an environment variable is stored as.
+///
+/// Only the string kinds take part in the environment, but the others do turn
+/// up in the Environment keys, so we need to be able to show and preserve them.
+/// Non-string values are held in [`EnvironmentVariable::value`] as text:
+/// multi-strings one entry per line, integers in decimal, and everything else
+/// as comma separated hex bytes like `.reg` files use.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum EnvValueKind {
+    /// `REG_SZ`, a literal string.
+    #[default]
+    #[serde(rename = "REG_SZ")]
+    String,
+    /// `REG_EXPAND_SZ`, a string whose `%NAME%` references are expanded on use.
+    #[serde(rename = "REG_EXPAND_SZ")]
+    ExpandString,
+    /// `REG_MULTI_SZ`, a list of strings.
+    #[serde(rename = "REG_MULTI_SZ")]
+    MultiString,
+    /// `REG_DWORD`, a little-endian 32-bit integer.
+    #[serde(rename = "REG_DWORD")]
+    Dword,
+    /// `REG_QWORD`, a little-endian 64-bit integer.
+    #[serde(rename = "REG_QWORD")]
+    Qword,
+    /// `REG_BINARY`, arbitrary bytes.
+    #[serde(rename = "REG_BINARY")]
+    Binary,
+    /// Any other registry type, kept as raw bytes.
+    #[serde(rename = "REG_UNKNOWN")]
+    Unknown(u32),
+}
+impl EnvValueKind {
+    const REG_SZ: u32 = 1;
+    const REG_EXPAND_SZ: u32 = 2;
+    const REG_BINARY: u32 = 3;
+    const REG_DWORD: u32 = 4;
+    const REG_MULTI_SZ: u32 = 7;
+    const REG_QWORD: u32 = 11;
+
+    pub fn from_type_code(code: u32) -> Self {
+        match code {
+            Self::REG_SZ => EnvValueKind::String,
+            Self::REG_EXPAND_SZ => EnvValueKind::ExpandString,
+            Self::REG_BINARY => EnvValueKind::Binary,
+            Self::REG_DWORD => EnvValueKind::Dword,
+            Self::REG_MULTI_SZ => EnvValueKind::MultiString,
+            Self::REG_QWORD => EnvValueKind::Qword,
+            other => EnvValueKind::Unknown(other),
+        }
+    }
+
+    pub fn type_code(self) -> u32 {
+        match self {
+            EnvValueKind::String => Self::REG_SZ,
+            EnvValueKind::ExpandString => Self::REG_EXPAND_SZ,
+            EnvValueKind::Binary => Self::REG_BINARY,
+            EnvValueKind::Dword => Self::REG_DWORD,
+            EnvValueKind::MultiString => Self::REG_MULTI_SZ,
+            EnvValueKind::Qword => Self::REG_QWORD,
+            EnvValueKind::Unknown(code) => code,
+        }
+    }
+
+    /// Whether Windows uses values of this kind when building the environment.
+    pub fn is_string(self) -> bool {
+        matches!(self, EnvValueKind::String | EnvValueKind::ExpandString)
+    }
+
+    /// Encode the textual form of a value into the bytes the registry stores.
+    pub fn encode(self, value: &str) -> eyre::Result<Vec<u8>> {
+        Ok(match self {
+            EnvValueKind::String | EnvValueKind::ExpandString => utf16_bytes_null(value),
+            EnvValueKind::MultiString => {
+                let mut bytes: Vec<u8> = value.lines().flat_map(utf16_bytes_null).collect();
+                bytes.extend([0, 0]);
+                bytes
+            }
+            EnvValueKind::Dword => parse_integer::<u32>(value)?.to_le_bytes().to_vec(),
+            EnvValueKind::Qword => parse_integer::<u64>(value)?.to_le_bytes().to_vec(),
+            EnvValueKind::Binary | EnvValueKind::Unknown(_) => parse_hex_bytes(value)?,
+        })
+    }
+}
+impl std::fmt::Display for EnvValueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EnvValueKind::String => write!(f, "REG_SZ"),
+            EnvValueKind::ExpandString => write!(f, "REG_EXPAND_SZ"),
+            EnvValueKind::MultiString => write!(f, "REG_MULTI_SZ"),
+            EnvValueKind::Dword => write!(f, "REG_DWORD"),
+            EnvValueKind::Qword => write!(f, "REG_QWORD"),
+            EnvValueKind::Binary => write!(f, "REG_BINARY"),
+            EnvValueKind::Unknown(code) => write!(f, "registry type {code}"),
+        }
+    }
+}
+
+fn parse_integer<T>(value: &str) -> eyre::Result<T>
+where
+    T: TryFrom<u64>,
+{
+    let value = value.trim();
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse::<u64>(),
+    }
+    .wrap_err_with(|| format!("{value:?} is not an integer"))?;
+    T::try_from(parsed).map_err(|_| eyre::eyre!("{value} is out of range"))
+}
+
+/// Parse `01,02,ab` style hex bytes.
+pub fn parse_hex_bytes(value: &str) -> eyre::Result<Vec<u8>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|byte| !byte.is_empty())
+        .map(|byte| {
+            u8::from_str_radix(byte, 16).wrap_err_with(|| format!("{byte:?} is not a hex byte"))
+        })
+        .collect()
+}
+
+/// Format bytes as `01,02,ab`.
+pub fn format_hex_bytes(bytes: &[u8]) -> String {
+    bytes
         .iter()
-        .position(|&c| c == 0)
-        .unwrap_or(name_wchars.len());
-    let name = String::from_utf16(&name_wchars[..name_end])?;
-
-    match value_type {
-        REG_SZ => {
-            let value = utf16_from_bytes(data_bytes);
-            return Ok(EnvironmentVariable {
-                key: name,
-                value,
-                value_expanded: None,
-                kind: value_type,
-            });
-            // println!("{name} (REG_SZ) = {data_str}");
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Interpret raw registry data as an environment variable.
+///
+/// This never fails: anything unexpected about the data is recorded in
+/// [`EnvironmentVariable::diagnostics`] so one odd value can't break a listing.
+pub fn decode_registry_value(
+    scope: EnvScope,
+    key: impl Into<String>,
+    kind: EnvValueKind,
+    data: &[u8],
+) -> EnvironmentVariable {
+    let mut diagnostics = Vec::new();
+    let value = match kind {
+        EnvValueKind::String | EnvValueKind::ExpandString | EnvValueKind::MultiString => {
+            if !data.len().is_multiple_of(2) {
+                diagnostics.push(format!(
+                    "{kind} data has an odd length of {} bytes",
+                    data.len()
+                ));
+            }
+            let units = utf16_units_from_bytes(data);
+            let mut strings: Vec<String> = units
+                .split(|&unit| unit == 0)
+                .map(|part| {
+                    String::from_utf16(part).unwrap_or_else(|_| {
+                        diagnostics.push("value is not valid UTF-16".to_string());
+                        String::from_utf16_lossy(part)
+                    })
+                })
+                .collect();
+            if kind == EnvValueKind::MultiString {
+                // The list ends with an empty string, i.e. a double null terminator
+                while strings.last().is_some_and(String::is_empty) {
+                    strings.pop();
+                }
+                strings.join("\n")
+            } else {
+                strings.swap_remove(0)
+            }
         }
-        REG_EXPAND_SZ => {
-            let value = utf16_from_bytes(data_bytes);
-            let expanded = expand_env_wstring(&value);
-            // println!("{name} (REG_EXPAND_SZ) = {raw_str}");
-            // println!("                   expanded => {expanded}");
-            return Ok(EnvironmentVariable {
-                key: name,
-                value,
-                value_expanded: Some(expanded),
-                kind: value_type,
-            });
+        EnvValueKind::Dword | EnvValueKind::Qword => {
+            let width = if kind == EnvValueKind::Dword { 4 } else { 8 };
+            if data.len() == width {
+                let mut buf = [0u8; 8];
+                buf[..width].copy_from_slice(data);
+                u64::from_le_bytes(buf).to_string()
+            } else {
+                diagnostics.push(format!(
+                    "{kind} data is {} bytes, expected {width}",
+                    data.len()
+                ));
+                format_hex_bytes(data)
+            }
         }
-        _ => {
-            bail!("{name} = (not a REG_SZ/REG_EXPAND_SZ type: {value_type:?})");
+        EnvValueKind::Binary | EnvValueKind::Unknown(_) => format_hex_bytes(data),
+    };
+    if !kind.is_string() {
+        diagnostics.push(format!(
+            "{kind} is not a string type, so Windows leaves it out of the environment"
+        ));
+    }
+    EnvironmentVariable {
+        diagnostics,
+        ..EnvironmentVariable::new(scope, key, value, kind)
+    }
+}
+
+/// How to pick the value kind when writing a variable.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum KindChoice {
+    /// Keep the kind of the existing string variable, `REG_SZ` otherwise.
+    #[default]
+    Preserve,
+    /// Always write `REG_SZ`.
+    String,
+    /// Always write `REG_EXPAND_SZ`.
+    ExpandString,
+    /// `REG_EXPAND_SZ` when the value references another variable, `REG_SZ` otherwise.
+    Auto,
+}
+impl KindChoice {
+    pub fn resolve(self, existing: Option<EnvValueKind>, value: &str) -> EnvValueKind {
+        match self {
+            // Overwriting an odd non-string value with text turns it into a normal variable
+            KindChoice::Preserve => existing.filter(|kind| kind.is_string()).unwrap_or_default(),
+            KindChoice::String => EnvValueKind::String,
+            KindChoice::ExpandString => EnvValueKind::ExpandString,
+            KindChoice::Auto if has_env_reference(value) => EnvValueKind::ExpandString,
+            KindChoice::Auto => EnvValueKind::String,
         }
     }
 }
 
-/// Expand variables like `%SystemRoot%` in a string
-fn expand_env_wstring(input: &str) -> String {
-    use std::iter;
-    let wchars: Vec<u16> = input.encode_utf16().chain(iter::once(0)).collect();
-    unsafe {
-        let len = ExpandEnvironmentStringsW(PCWSTR(wchars.as_ptr()), None);
-        if len == 0 {
-            return String::new();
+/// Whether `value` contains a `%NAME%` reference.
+pub fn has_env_reference(value: &str) -> bool {
+    !crate::env_expand::references(value, None).is_empty()
+}
+
+#[cfg(windows)]
+pub use registry::get_env_var;
+#[cfg(windows)]
+pub use registry::get_env_var_entry;
+#[cfg(windows)]
+pub use registry::get_machine_env_var;
+#[cfg(windows)]
+pub use registry::list_env_vars;
+#[cfg(windows)]
+pub use registry::list_machine_env_var;
+
+/// Reading environment variables from the Windows registry.
+#[cfg(windows)]
+mod registry {
+    use super::EnvScope;
+    use super::EnvValueKind;
+    use super::EnvironmentVariable;
+    use super::decode_registry_value;
+    use crate::win_strings::env_registry_key;
+    use eyre::eyre;
+    use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+    use windows::Win32::Foundation::ERROR_MORE_DATA;
+    use windows::Win32::Foundation::ERROR_NO_MORE_ITEMS;
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::Foundation::WIN32_ERROR;
+    use windows::Win32::System::Registry::HKEY;
+    use windows::Win32::System::Registry::KEY_QUERY_VALUE;
+    use windows::Win32::System::Registry::KEY_READ;
+    use windows::Win32::System::Registry::REG_VALUE_TYPE;
+    use windows::Win32::System::Registry::RegCloseKey;
+    use windows::Win32::System::Registry::RegEnumValueW;
+    use windows::Win32::System::Registry::RegOpenKeyExW;
+    use windows::Win32::System::Registry::RegQueryValueExW;
+    use windows::core::PCWSTR;
+    use windows::core::PWSTR;
+
+    pub fn list_machine_env_var() -> eyre::Result<Vec<EnvironmentVariable>> {
+        list_env_vars(EnvScope::Machine)
+    }
+
+    pub fn list_env_vars(scope: EnvScope) -> eyre::Result<Vec<EnvironmentVariable>> {
+        let (root, sub_key) = env_registry_key(scope);
+        let mut hkey: HKEY = HKEY::default();
+        unsafe {
+            RegOpenKeyExW(root, sub_key, None, KEY_READ, &mut hkey).ok()?;
+        }
+
+        let rtn = try {
+            let mut index = 0;
+            let mut rtn = Vec::new();
+            loop {
+                // We'll keep dynamic buffers that we can expand if needed
+                let mut name_buf = vec![0u16; 256]; // 256 wide chars for the name
+                let mut data_buf = vec![0u8; 1024]; // 1 KiB for data
+                let mut name_len = name_buf.len() as u32; // length in wide chars for the name
+                let mut data_len = data_buf.len() as u32; // length in bytes for the data
+                let mut value_type = REG_VALUE_TYPE(0);
+
+                let status = unsafe {
+                    RegEnumValueW(
+                        hkey,
+                        index,
+                        Some(PWSTR(name_buf.as_mut_ptr())),
+                        &mut name_len,
+                        None,
+                        Some(&mut value_type.0),
+                        Some(data_buf.as_mut_ptr()),
+                        Some(&mut data_len),
+                    )
+                };
+                match status {
+                    ERROR_NO_MORE_ITEMS => {
+                        break rtn;
+                    }
+                    ERROR_MORE_DATA => {
+                        // Our initial buffers weren't large enough, so let's reallocate
+                        // using the exact size reported in `name_len` or `data_len`.
+                        //
+                        // name_len is the size in wide chars (without the null terminator).
+                        // data_len is in bytes.
+                        let mut bigger_name_buf = vec![0u16; name_len as usize];
+                        let mut bigger_data_buf = vec![0u8; data_len as usize];
+
+                        let status = unsafe {
+                            RegEnumValueW(
+                                hkey,
+                                index,
+                                Some(PWSTR(bigger_name_buf.as_mut_ptr())),
+                                &mut name_len,
+                                None,
+                                Some(&mut value_type.0),
+                                Some(bigger_data_buf.as_mut_ptr()),
+                                Some(&mut data_len),
+                            )
+                        };
+                        if status == ERROR_SUCCESS {
+                            rtn.push(process_value(
+                                scope,
+                                &bigger_name_buf[..(name_len as usize)],
+                                &bigger_data_buf[..(data_len as usize)],
+                                value_type,
+                            ));
+                        } else {
+                            eprintln!("Failed to read bigger buffers: 0x{:X}", status.0);
+                        }
+                    }
+                    ERROR_SUCCESS => {
+                        // We got the data with our initial buffer
+                        rtn.push(process_value(
+                            scope,
+                            &name_buf[..(name_len as usize)],
+                            &data_buf[..(data_len as usize)],
+                            value_type,
+                        ));
+                    }
+                    x => {
+                        return Err(eyre!("RegEnumValueW error: 0x{:X}", x.0));
+                    }
+                }
+                index += 1;
+            }
+        };
+        unsafe {
+            RegCloseKey(hkey).ok()?;
         }
-        let mut buf = vec![0u16; len as usize];
-        ExpandEnvironmentStringsW(PCWSTR(wchars.as_ptr()), Some(&mut buf));
-        let expanded_end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
-        String::from_utf16_lossy(&buf[..expanded_end])
+        rtn
+    }
+
+    /// Retrieve a machine-level environment variable value (if it exists).
+    /// Returns `Ok(None)` if the key was not found, or `Ok(Some(value))` otherwise.
+    pub fn get_machine_env_var(var_name: &str) -> eyre::Result<Option<String>> {
+        get_env_var(EnvScope::Machine, var_name)
+    }
+
+    /// Retrieve an environment variable value from `scope` (if it exists).
+    pub fn get_env_var(scope: EnvScope, var_name: &str) -> eyre::Result<Option<String>> {
+        Ok(get_env_var_entry(scope, var_name)?.map(|var| var.value))
+    }
+
+    /// Retrieve an environment variable from `scope` along with its value kind.
+    /// Returns `Ok(None)` if the key was not found.
+    pub fn get_env_var_entry(
+        scope: EnvScope,
+        var_name: &str,
+    ) -> eyre::Result<Option<EnvironmentVariable>> {
+        let (root, sub_key) = env_registry_key(scope);
+        // Windows registry calls typically want a wide (UTF-16) string with a null terminator
+        let wide_name: Vec<u16> = var_name.encode_utf16().chain(std::iter::once(0)).collect();
+
+        unsafe {
+            // Open the environment sub-key with KEY_QUERY_VALUE
+            let mut hkey: HKEY = HKEY::default();
+            RegOpenKeyExW(root, sub_key, None, KEY_QUERY_VALUE, &mut hkey).ok()?;
+
+            // We'll call RegQueryValueExW to get data size first
+            let mut value_type = REG_VALUE_TYPE(0);
+            let mut data_len: u32 = 0;
+            let query_status = RegQueryValueExW(
+                hkey,
+                PCWSTR(wide_name.as_ptr()),
+                None,
+                Some(&mut value_type),
+                None, // pass None to query the size needed
+                Some(&mut data_len),
+            );
+
+            if let Err(e) = query_status.ok() {
+                let code = WIN32_ERROR::from_error(&e).map(|c| c.0).unwrap_or_default();
+                if code == ERROR_FILE_NOT_FOUND.0 {
+                    // Key does not exist
+                    RegCloseKey(hkey).ok()?;
+                    return Ok(None);
+                } else {
+                    // Some other error
+                    RegCloseKey(hkey).ok()?;
+                    return Err(eyre!(
+                        "Failed to query size for '{var_name}', code=0x{code:X}"
+                    ));
+                }
+            }
+
+            // If data_len == 0, either the value is empty or something is off
+            // We'll consider that as an empty string
+            if data_len == 0 {
+                RegCloseKey(hkey).ok()?;
+                return Ok(Some(process_value(scope, &wide_name, &[], value_type)));
+            }
+
+            // Allocate a buffer for the actual data
+            let mut data_buf = vec![0u8; data_len as usize];
+            RegQueryValueExW(
+                hkey,
+                PCWSTR(wide_name.as_ptr()),
+                None,
+                Some(&mut value_type),
+                Some(data_buf.as_mut_ptr()),
+                Some(&mut data_len),
+            )
+            .ok()?;
+
+            RegCloseKey(hkey).ok()?;
+
+            Ok(Some(process_value(
+                scope,
+                &wide_name,
+                &data_buf[..(data_len as usize)],
+                value_type,
+            )))
+        }
+    }
+
+    impl EnvValueKind {
+        pub fn from_reg(value_type: REG_VALUE_TYPE) -> Self {
+            Self::from_type_code(value_type.0)
+        }
+
+        pub fn to_reg(self) -> REG_VALUE_TYPE {
+            REG_VALUE_TYPE(self.type_code())
+        }
+    }
+
+    /// Helper to interpret the registry value from raw buffers.
+    fn process_value(
+        scope: EnvScope,
+        name_wchars: &[u16],
+        data_bytes: &[u8],
+        value_type: REG_VALUE_TYPE,
+    ) -> EnvironmentVariable {
+        // Convert the name from UTF-16
+        let name_end = name_wchars
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(name_wchars.len());
+        let name = String::from_utf16_lossy(&name_wchars[..name_end]);
+
+        decode_registry_value(scope, name, EnvValueKind::from_reg(value_type), data_bytes)
     }
 }
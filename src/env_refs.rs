@@ -0,0 +1,242 @@
+use crate::env_expand::SpanOrigin;
+use crate::env_expand::VariableSet;
+use crate::env_expand::expand;
+use crate::env_reader::EnvironmentVariable;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+/// Which variables reference which, built from the raw `%NAME%` references in
+/// each value.
+///
+/// Names are case-insensitive, and a name defined in several scopes is one node
+/// whose edges come from every definition.
+#[derive(Debug, Clone, Default)]
+pub struct RefGraph {
+    nodes: BTreeMap<String, RefNode>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct RefNode {
+    definitions: Vec<EnvironmentVariable>,
+    references: BTreeSet<String>,
+    dangling: BTreeSet<String>,
+    dependents: BTreeSet<String>,
+}
+
+/// A `%NAME%` reference to a variable that is not defined anywhere in the graph.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Serialize)]
+pub struct DanglingRef {
+    pub from: String,
+    pub name: String,
+}
+
+impl RefGraph {
+    pub fn build(vars: &[EnvironmentVariable]) -> Self {
+        let set = VariableSet::from_vars(vars);
+        let mut nodes: BTreeMap<String, RefNode> = BTreeMap::new();
+        for var in vars {
+            nodes
+                .entry(var.key.to_uppercase())
+                .or_default()
+                .definitions
+                .push(var.clone());
+        }
+
+        let mut edges = Vec::new();
+        for (id, node) in &mut nodes {
+            for var in &node.definitions {
+                let expansion = expand(&var.value, &set);
+                for span in expansion.substitutions() {
+                    if let SpanOrigin::Variable { name, .. } = &span.origin {
+                        node.references.insert(name.to_uppercase());
+                    }
+                }
+                node.dangling.extend(expansion.unresolved);
+            }
+            edges.extend(node.references.iter().map(|to| (id.clone(), to.clone())));
+        }
+        for (from, to) in edges {
+            if let Some(node) = nodes.get_mut(&to) {
+                node.dependents.insert(from);
+            }
+        }
+        Self { nodes }
+    }
+
+    /// Where `name` is defined, one entry per scope. Empty if it is not defined.
+    pub fn definitions(&self, name: &str) -> &[EnvironmentVariable] {
+        self.nodes
+            .get(&name.to_uppercase())
+            .map(|node| node.definitions.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The display name for a node, as spelled by its first definition.
+    pub fn display_name<'a>(&'a self, id: &'a str) -> &'a str {
+        self.nodes
+            .get(id)
+            .and_then(|node| node.definitions.first())
+            .map(|var| var.key.as_str())
+            .unwrap_or(id)
+    }
+
+    /// The variables that `name` references directly.
+    pub fn references(&self, name: &str) -> Vec<&str> {
+        self.node(name)
+            .map(|node| {
+                node.references
+                    .iter()
+                    .map(|id| self.display_name(id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The undefined names that `name` references.
+    pub fn dangling_references(&self, name: &str) -> Vec<&str> {
+        self.node(name)
+            .map(|node| node.dangling.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    /// The variables that reference `name` directly.
+    pub fn dependents(&self, name: &str) -> Vec<&str> {
+        self.node(name)
+            .map(|node| {
+                node.dependents
+                    .iter()
+                    .map(|id| self.display_name(id))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Every variable that would change if `name` changed, nearest first.
+    pub fn transitive_dependents(&self, name: &str) -> Vec<&str> {
+        let mut seen = BTreeSet::new();
+        let mut order = Vec::new();
+        let mut queue = std::collections::VecDeque::from([name.to_uppercase()]);
+        while let Some(id) = queue.pop_front() {
+            let Some(node) = self.nodes.get(&id) else {
+                continue;
+            };
+            for dependent in &node.dependents {
+                if seen.insert(dependent.clone()) {
+                    order.push(self.display_name(dependent));
+                    queue.push_back(dependent.clone());
+                }
+            }
+        }
+        order
+    }
+
+    /// Every reference to an undefined variable.
+    pub fn dangling(&self) -> Vec<DanglingRef> {
+        self.nodes
+            .values()
+            .flat_map(|node| {
+                let from = node.definitions[0].key.clone();
+                node.dangling.iter().map(move |name| DanglingRef {
+                    from: from.clone(),
+                    name: name.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Groups of variables that reference each other in a loop, including
+    /// variables that reference themselves.
+    ///
+    /// Windows only expands one level so a cycle does not hang anything, but
+    /// it always leaves a literal `%NAME%` behind.
+    pub fn cycles(&self) -> Vec<Vec<&str>> {
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: BTreeMap::new(),
+            low_link: BTreeMap::new(),
+            stack: Vec::new(),
+            on_stack: BTreeSet::new(),
+            components: Vec::new(),
+        };
+        for id in self.nodes.keys() {
+            if !tarjan.index.contains_key(id.as_str()) {
+                tarjan.visit(id);
+            }
+        }
+        tarjan
+            .components
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || self.nodes[component[0]].references.contains(component[0])
+            })
+            .map(|component| {
+                component
+                    .into_iter()
+                    .map(|id| self.display_name(id))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// The cycle containing `name`, if it is part of one.
+    pub fn cycle_containing(&self, name: &str) -> Option<Vec<&str>> {
+        let definitions = self.definitions(name);
+        let display = definitions.first()?.key.as_str();
+        self.cycles()
+            .into_iter()
+            .find(|cycle| cycle.contains(&display))
+    }
+
+    fn node(&self, name: &str) -> Option<&RefNode> {
+        self.nodes.get(&name.to_uppercase())
+    }
+}
+
+/// Tarjan's strongly connected components over the reference edges.
+struct Tarjan<'a> {
+    graph: &'a RefGraph,
+    index: BTreeMap<&'a str, usize>,
+    low_link: BTreeMap<&'a str, usize>,
+    stack: Vec<&'a str>,
+    on_stack: BTreeSet<&'a str>,
+    components: Vec<Vec<&'a str>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, id: &'a str) {
+        let next = self.index.len();
+        self.index.insert(id, next);
+        self.low_link.insert(id, next);
+        self.stack.push(id);
+        self.on_stack.insert(id);
+
+        for to in &self.graph.nodes[id].references {
+            let to = to.as_str();
+            if !self.graph.nodes.contains_key(to) {
+                continue;
+            }
+            if !self.index.contains_key(to) {
+                self.visit(to);
+                let low = self.low_link[id].min(self.low_link[to]);
+                self.low_link.insert(id, low);
+            } else if self.on_stack.contains(to) {
+                let low = self.low_link[id].min(self.index[to]);
+                self.low_link.insert(id, low);
+            }
+        }
+
+        if self.low_link[id] == self.index[id] {
+            let mut component = Vec::new();
+            while let Some(member) = self.stack.pop() {
+                self.on_stack.remove(member);
+                component.push(member);
+                if member == id {
+                    break;
+                }
+            }
+            component.reverse();
+            self.components.push(component);
+        }
+    }
+}
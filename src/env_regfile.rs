@@ -0,0 +1,374 @@
+use crate::env_change::Change;
+use crate::env_change::ChangeSet;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use crate::env_reader::decode_registry_value;
+use crate::env_reader::parse_hex_bytes;
+use crate::env_store::EnvStore;
+use crate::win_strings::utf16_units_from_bytes;
+
+/// The key holding machine variables, as written in `.reg` files.
+pub const MACHINE_REG_PATH: &str =
+    "HKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment";
+
+/// The key holding user variables, as written in `.reg` files.
+pub const USER_REG_PATH: &str = "HKEY_CURRENT_USER\\Environment";
+
+const REGEDIT4_HEADER: &str = "REGEDIT4";
+const REGEDIT5_HEADER: &str = "Windows Registry Editor Version 5.00";
+
+/// Regedit wraps hex data once a line gets this long.
+const HEX_LINE_WIDTH: usize = 76;
+
+pub fn reg_path(scope: EnvScope) -> &'static str {
+    match scope {
+        EnvScope::Machine => MACHINE_REG_PATH,
+        EnvScope::User => USER_REG_PATH,
+    }
+}
+
+/// The two `.reg` dialects regedit reads.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum RegFormat {
+    /// `REGEDIT4`: an ANSI file whose `hex(2):` and `hex(7):` data is single-byte.
+    Regedit4,
+    /// `Windows Registry Editor Version 5.00`: a UTF-16 file whose string data is UTF-16.
+    #[default]
+    Regedit5,
+}
+
+impl RegFormat {
+    fn header(self) -> &'static str {
+        match self {
+            RegFormat::Regedit4 => REGEDIT4_HEADER,
+            RegFormat::Regedit5 => REGEDIT5_HEADER,
+        }
+    }
+
+    /// The bytes regedit expects for string data, including terminators.
+    fn string_bytes(self, kind: EnvValueKind, value: &str) -> eyre::Result<Vec<u8>> {
+        match self {
+            RegFormat::Regedit5 => kind.encode(value),
+            RegFormat::Regedit4 => {
+                let strings: Vec<&str> = match kind {
+                    EnvValueKind::MultiString => value.lines().collect(),
+                    _ => vec![value],
+                };
+                let mut bytes = Vec::new();
+                for string in strings {
+                    if !string.is_ascii() {
+                        eyre::bail!(
+                            "{string:?} is not ASCII, which REGEDIT4 files cannot hold reliably"
+                        );
+                    }
+                    bytes.extend(string.bytes());
+                    bytes.push(0);
+                }
+                if kind == EnvValueKind::MultiString {
+                    bytes.push(0);
+                }
+                Ok(bytes)
+            }
+        }
+    }
+}
+
+/// Render `vars` as a `.reg` file, one section per scope.
+pub fn write_reg(vars: &[EnvironmentVariable], format: RegFormat) -> eyre::Result<String> {
+    let mut out = format!("{}\r\n", format.header());
+    for scope in EnvScope::ALL {
+        let in_scope: Vec<&EnvironmentVariable> =
+            vars.iter().filter(|var| var.scope == scope).collect();
+        if in_scope.is_empty() {
+            continue;
+        }
+        out.push_str(&format!("\r\n[{}]\r\n", reg_path(scope)));
+        for var in in_scope {
+            out.push_str(&reg_value_line(var, format)?);
+            out.push_str("\r\n");
+        }
+    }
+    out.push_str("\r\n");
+    Ok(out)
+}
+
+fn reg_value_line(var: &EnvironmentVariable, format: RegFormat) -> eyre::Result<String> {
+    let name = format!("\"{}\"=", escape_reg_string(&var.key));
+    if var.kind == EnvValueKind::String && (format == RegFormat::Regedit5 || var.value.is_ascii()) {
+        return Ok(format!("{name}\"{}\"", escape_reg_string(&var.value)));
+    }
+    let (prefix, data) = match var.kind {
+        EnvValueKind::Dword => {
+            let bytes = var.kind.encode(&var.value)?;
+            let dword = u32::from_le_bytes(bytes[..4].try_into()?);
+            return Ok(format!("{name}dword:{dword:08x}"));
+        }
+        EnvValueKind::Binary => ("hex:".to_string(), var.kind.encode(&var.value)?),
+        EnvValueKind::String | EnvValueKind::ExpandString | EnvValueKind::MultiString => (
+            format!("hex({:x}):", var.kind.type_code()),
+            format.string_bytes(var.kind, &var.value)?,
+        ),
+        EnvValueKind::Qword | EnvValueKind::Unknown(_) => (
+            format!("hex({:x}):", var.kind.type_code()),
+            var.kind.encode(&var.value)?,
+        ),
+    };
+    let mut line = format!("{name}{prefix}");
+    let mut line_len = line.len();
+    for (i, byte) in data.iter().enumerate() {
+        let last = i + 1 == data.len();
+        let part = if last {
+            format!("{byte:02x}")
+        } else {
+            format!("{byte:02x},")
+        };
+        line.push_str(&part);
+        line_len += part.len();
+        if !last && line_len > HEX_LINE_WIDTH {
+            line.push_str("\\\r\n  ");
+            line_len = 2;
+        }
+    }
+    Ok(line)
+}
+
+fn escape_reg_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Encode a rendered `.reg` file the way regedit writes that format.
+pub fn encode_reg_file(text: &str, format: RegFormat) -> Vec<u8> {
+    match format {
+        RegFormat::Regedit4 => text.as_bytes().to_vec(),
+        RegFormat::Regedit5 => {
+            let mut bytes = vec![0xff, 0xfe];
+            bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+            bytes
+        }
+    }
+}
+
+/// Decode a `.reg` file, which is UTF-16 with a byte order mark when regedit
+/// wrote it, but is often saved as UTF-8 by hand.
+pub fn decode_reg_file(bytes: &[u8]) -> String {
+    match bytes {
+        [0xff, 0xfe, rest @ ..] => String::from_utf16_lossy(&utf16_units_from_bytes(rest)),
+        [0xef, 0xbb, 0xbf, rest @ ..] => String::from_utf8_lossy(rest).into_owned(),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// A value assignment found under one of the environment keys.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RegEntry {
+    Set(EnvironmentVariable),
+    /// `"NAME"=-`
+    Delete {
+        scope: EnvScope,
+        key: String,
+    },
+}
+
+/// The environment values in a `.reg` file. Everything else in it is skipped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RegFile {
+    pub format: RegFormat,
+    pub entries: Vec<RegEntry>,
+    /// Things that were skipped, such as other keys.
+    pub warnings: Vec<String>,
+}
+
+impl RegFile {
+    /// The changes that would apply this file's entries in `scopes` to `store`.
+    ///
+    /// Entries that match what is already there are left out.
+    pub fn plan(&self, store: &dyn EnvStore, scopes: &[EnvScope]) -> eyre::Result<ChangeSet> {
+        let mut changes = ChangeSet::new();
+        for entry in &self.entries {
+            match entry {
+                RegEntry::Set(var) if scopes.contains(&var.scope) => {
+                    changes
+                        .push_set_if_changed(store, var.scope, &var.key, &var.value, var.kind)?;
+                }
+                RegEntry::Delete { scope, key } if scopes.contains(scope) => {
+                    if let Some(previous) = store.get(*scope, key)? {
+                        changes.push(Change::Delete { previous });
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(changes)
+    }
+}
+
+/// Parse the text of a `.reg` file.
+pub fn parse_reg(text: &str) -> eyre::Result<RegFile> {
+    let mut lines = logical_lines(text).into_iter();
+    let format = match lines.next().as_deref().map(str::trim) {
+        Some(REGEDIT4_HEADER) => RegFormat::Regedit4,
+        Some(REGEDIT5_HEADER) => RegFormat::Regedit5,
+        other => eyre::bail!("Not a .reg file, the first line is {other:?}"),
+    };
+
+    let mut file = RegFile {
+        format,
+        entries: Vec::new(),
+        warnings: Vec::new(),
+    };
+    let mut scope = None;
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+            if let Some(deleted) = section.strip_prefix('-') {
+                if scope_for_path(deleted).is_some() {
+                    eyre::bail!("Refusing to delete the whole {deleted} key");
+                }
+                file.warnings
+                    .push(format!("Skipping deletion of [{deleted}]"));
+                scope = None;
+                continue;
+            }
+            scope = scope_for_path(section);
+            if scope.is_none() {
+                file.warnings.push(format!(
+                    "Skipping [{section}], it is not an environment key"
+                ));
+            }
+            continue;
+        }
+        let Some(scope) = scope else {
+            continue;
+        };
+        if line.starts_with('@') {
+            file.warnings
+                .push(format!("Skipping the default value of {}", reg_path(scope)));
+            continue;
+        }
+        let (key, rest) = parse_quoted(line)?;
+        let Some(data) = rest.trim_start().strip_prefix('=') else {
+            eyre::bail!("Expected = after \"{key}\" in {line:?}");
+        };
+        file.entries
+            .push(parse_reg_data(format, scope, key, data.trim())?);
+    }
+    Ok(file)
+}
+
+fn scope_for_path(path: &str) -> Option<EnvScope> {
+    EnvScope::ALL
+        .into_iter()
+        .find(|&scope| reg_path(scope).eq_ignore_ascii_case(path.trim()))
+}
+
+/// Join `\` continuation lines so every value is on one line.
+fn logical_lines(text: &str) -> Vec<String> {
+    let mut rtn = Vec::new();
+    let mut pending = String::new();
+    for line in text.lines() {
+        let line = line.trim_end_matches('\r');
+        match line.trim_end().strip_suffix('\\') {
+            // Hex data is the only place a line can end in a bare backslash
+            Some(start) if !pending.is_empty() || start.contains("=hex") => {
+                pending.push_str(start.trim_start());
+            }
+            _ => {
+                pending.push_str(if pending.is_empty() {
+                    line
+                } else {
+                    line.trim_start()
+                });
+                rtn.push(std::mem::take(&mut pending));
+            }
+        }
+    }
+    if !pending.is_empty() {
+        rtn.push(pending);
+    }
+    rtn
+}
+
+/// Read a `"..."` string with `\\` and `\"` escapes, returning it and what follows.
+fn parse_quoted(s: &str) -> eyre::Result<(String, &str)> {
+    let Some(body) = s.strip_prefix('"') else {
+        eyre::bail!("Expected a quoted string in {s:?}");
+    };
+    let mut value = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &body[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, escaped)) => value.push(escaped),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+    eyre::bail!("Unterminated string in {s:?}")
+}
+
+fn parse_reg_data(
+    format: RegFormat,
+    scope: EnvScope,
+    key: String,
+    data: &str,
+) -> eyre::Result<RegEntry> {
+    if data == "-" {
+        return Ok(RegEntry::Delete { scope, key });
+    }
+    if data.starts_with('"') {
+        let (value, rest) = parse_quoted(data)?;
+        if !rest.trim().is_empty() {
+            eyre::bail!("Unexpected {rest:?} after the value of {key}");
+        }
+        return Ok(RegEntry::Set(EnvironmentVariable::new(
+            scope,
+            key,
+            value,
+            EnvValueKind::String,
+        )));
+    }
+    if let Some(hex) = data.strip_prefix("dword:") {
+        let dword = u32::from_str_radix(hex.trim(), 16)
+            .map_err(|_| eyre::eyre!("{hex:?} is not a valid dword for {key}"))?;
+        return Ok(RegEntry::Set(decode_registry_value(
+            scope,
+            key,
+            EnvValueKind::Dword,
+            &dword.to_le_bytes(),
+        )));
+    }
+    let (kind, hex) = if let Some(hex) = data.strip_prefix("hex:") {
+        (EnvValueKind::Binary, hex)
+    } else if let Some(rest) = data.strip_prefix("hex(") {
+        let Some((code, hex)) = rest.split_once("):") else {
+            eyre::bail!("Malformed hex type in the value of {key}");
+        };
+        let code = u32::from_str_radix(code, 16)
+            .map_err(|_| eyre::eyre!("{code:?} is not a registry type for {key}"))?;
+        (EnvValueKind::from_type_code(code), hex)
+    } else {
+        eyre::bail!("Unsupported data {data:?} for {key}");
+    };
+    let mut bytes = parse_hex_bytes(hex)?;
+    if format == RegFormat::Regedit4
+        && matches!(
+            kind,
+            EnvValueKind::String | EnvValueKind::ExpandString | EnvValueKind::MultiString
+        )
+    {
+        // REGEDIT4 string data is one byte per character
+        bytes = bytes
+            .into_iter()
+            .flat_map(|byte| u16::from(byte).to_le_bytes())
+            .collect();
+    }
+    Ok(RegEntry::Set(decode_registry_value(
+        scope, key, kind, &bytes,
+    )))
+}
@@ -0,0 +1,382 @@
+use crate::env_expand::references;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use crate::env_regfile::reg_path;
+use crate::win_strings::force_quote_windows_arg;
+
+/// `setx` silently truncates longer values.
+pub const SETX_VALUE_LIMIT: usize = 1024;
+
+/// A shell to generate a script for.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Shell {
+    Cmd,
+    PowerShell,
+    Bash,
+    Fish,
+    Nu,
+    /// A `.env` file, as read by [`crate::env_dotenv::parse_dotenv`].
+    Dotenv,
+}
+
+impl Shell {
+    fn comment(self, text: &str) -> String {
+        match self {
+            Shell::Cmd => format!("REM {text}"),
+            _ => format!("# {text}"),
+        }
+    }
+
+    /// Whether `name` can be assigned in this shell at all.
+    fn accepts_name(self, name: &str) -> bool {
+        match self {
+            Shell::Cmd | Shell::PowerShell | Shell::Nu => !name.is_empty(),
+            Shell::Bash | Shell::Dotenv => is_identifier(name),
+            Shell::Fish => !name.is_empty() && name.chars().all(is_word_char),
+        }
+    }
+}
+
+/// A piece of a value: literal text, or a `%NAME%` reference that the shell
+/// should resolve when the script runs.
+enum Segment<'a> {
+    Literal(&'a str),
+    Reference(&'a str),
+}
+
+/// Split a value into literal text and references. Only `REG_EXPAND_SZ`
+/// values have references, a `%` in a `REG_SZ` value is just text.
+fn segments(var: &EnvironmentVariable) -> Vec<Segment<'_>> {
+    let value = var.value.as_str();
+    if var.kind != EnvValueKind::ExpandString {
+        return vec![Segment::Literal(value)];
+    }
+    let mut rtn = Vec::new();
+    let mut pos = 0;
+    for (range, _) in references(value, None) {
+        rtn.push(Segment::Literal(&value[pos..range.start]));
+        rtn.push(Segment::Reference(&value[range.start + 1..range.end - 1]));
+        pos = range.end;
+    }
+    rtn.push(Segment::Literal(&value[pos..]));
+    rtn.retain(|segment| !matches!(segment, Segment::Literal("")));
+    rtn
+}
+
+/// A script that recreates `vars` in `shell`.
+///
+/// By default the script sets variables for the current session, with
+/// `REG_EXPAND_SZ` references resolved by the shell as it runs. With
+/// `persistent`, it writes raw values to the registry instead using `setx` for
+/// cmd or `[Environment]::SetEnvironmentVariable` for PowerShell. Those only
+/// write `REG_SZ`, so `REG_EXPAND_SZ` values go through `reg add` and
+/// `[Microsoft.Win32.Registry]::SetValue` instead.
+pub fn write_script(
+    vars: &[EnvironmentVariable],
+    shell: Shell,
+    persistent: bool,
+) -> eyre::Result<String> {
+    if persistent && !matches!(shell, Shell::Cmd | Shell::PowerShell) {
+        eyre::bail!("Persistent scripts can only be written for cmd and PowerShell");
+    }
+    let mut lines = Vec::new();
+    if shell == Shell::Cmd {
+        lines.push("@echo off".to_string());
+    }
+    lines.push(shell.comment("Generated by env-edit"));
+    for var in vars {
+        if !var.kind.is_string() {
+            lines.push(shell.comment(&format!(
+                "Skipping {}: {} is not part of the environment",
+                var.key, var.kind
+            )));
+            continue;
+        }
+        if !shell.accepts_name(&var.key) {
+            lines.push(shell.comment(&format!(
+                "Skipping {}: not a valid variable name here",
+                var.key
+            )));
+            continue;
+        }
+        let line = match (shell, persistent) {
+            (Shell::Cmd, false) => cmd_set(var)?,
+            (Shell::Cmd, true) => cmd_setx(var)?,
+            (Shell::PowerShell, false) => powershell_set(var),
+            (Shell::PowerShell, true) => powershell_persistent(var),
+            (Shell::Bash, _) => bash_export(var),
+            (Shell::Fish, _) => fish_set(var),
+            (Shell::Nu, _) => nu_set(var),
+            (Shell::Dotenv, _) => dotenv_line(var),
+        };
+        lines.push(line);
+    }
+    let newline = if shell == Shell::Cmd { "\r\n" } else { "\n" };
+    Ok(lines.join(newline) + newline)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| !c.is_ascii_digit()) && name.chars().all(is_word_char)
+}
+
+/// cmd has no way to escape a line break, and a `"` inside a quoted `set`
+/// turns the rest of the line back into live syntax.
+fn check_cmd_value(var: &EnvironmentVariable) -> eyre::Result<()> {
+    if var.value.contains(['\r', '\n', '"']) {
+        eyre::bail!(
+            "{} contains a line break or a double quote, which cmd cannot set safely",
+            var.key
+        );
+    }
+    Ok(())
+}
+
+fn cmd_set(var: &EnvironmentVariable) -> eyre::Result<String> {
+    check_cmd_value(var)?;
+    let mut value = String::new();
+    for segment in segments(var) {
+        match segment {
+            // A literal % has to be doubled in a batch file
+            Segment::Literal(text) => value.push_str(&text.replace('%', "%%")),
+            Segment::Reference(name) => value.push_str(&format!("%{name}%")),
+        }
+    }
+    Ok(format!("set \"{}={value}\"", var.key))
+}
+
+fn cmd_setx(var: &EnvironmentVariable) -> eyre::Result<String> {
+    check_cmd_value(var)?;
+    // setx picks the type from the value, so it writes REG_SZ when there is no `%`
+    if var.kind == EnvValueKind::ExpandString {
+        let line = format!(
+            "reg add {} /v {} /t REG_EXPAND_SZ /d {} /f",
+            force_quote_windows_arg(reg_path(var.scope)),
+            force_quote_windows_arg(&var.key),
+            force_quote_windows_arg(&var.value)
+        );
+        return Ok(line.replace('%', "%%"));
+    }
+    if var.value.encode_utf16().count() > SETX_VALUE_LIMIT {
+        eyre::bail!(
+            "{} is longer than the {SETX_VALUE_LIMIT} characters setx can write",
+            var.key
+        );
+    }
+    let machine = if var.scope == EnvScope::Machine {
+        " /M"
+    } else {
+        ""
+    };
+    let line = format!(
+        "setx {} {}{machine}",
+        force_quote_windows_arg(&var.key),
+        force_quote_windows_arg(&var.value)
+    );
+    Ok(line.replace('%', "%%"))
+}
+
+fn powershell_env(name: &str) -> String {
+    if name.chars().all(is_word_char) {
+        format!("$env:{name}")
+    } else {
+        format!("${{env:{}}}", name.replace('}', "`}"))
+    }
+}
+
+fn powershell_single_quoted(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn powershell_set(var: &EnvironmentVariable) -> String {
+    let value = if var.kind == EnvValueKind::ExpandString {
+        let mut value = String::from("\"");
+        for segment in segments(var) {
+            match segment {
+                Segment::Literal(text) => {
+                    for c in text.chars() {
+                        if matches!(c, '`' | '$' | '"') {
+                            value.push('`');
+                        }
+                        value.push(c);
+                    }
+                }
+                Segment::Reference(name) => {
+                    value.push_str(&format!("${{env:{}}}", name.replace('}', "`}")))
+                }
+            }
+        }
+        value.push('"');
+        value
+    } else {
+        powershell_single_quoted(&var.value)
+    };
+    format!("{} = {value}", powershell_env(&var.key))
+}
+
+fn powershell_persistent(var: &EnvironmentVariable) -> String {
+    // SetEnvironmentVariable always writes REG_SZ, which would stop the
+    // references from expanding
+    if var.kind == EnvValueKind::ExpandString {
+        return format!(
+            "[Microsoft.Win32.Registry]::SetValue({}, {}, {}, [Microsoft.Win32.RegistryValueKind]::ExpandString)",
+            powershell_single_quoted(reg_path(var.scope)),
+            powershell_single_quoted(&var.key),
+            powershell_single_quoted(&var.value)
+        );
+    }
+    let target = match var.scope {
+        EnvScope::Machine => "Machine",
+        EnvScope::User => "User",
+    };
+    format!(
+        "[Environment]::SetEnvironmentVariable({}, {}, '{target}')",
+        powershell_single_quoted(&var.key),
+        powershell_single_quoted(&var.value)
+    )
+}
+
+fn bash_export(var: &EnvironmentVariable) -> String {
+    let value = if var.kind == EnvValueKind::ExpandString {
+        let mut value = String::from("\"");
+        for segment in segments(var) {
+            match segment {
+                Segment::Reference(name) if is_identifier(name) => {
+                    value.push_str(&format!("${{{name}}}"))
+                }
+                Segment::Reference(name) => {
+                    push_escaped(&mut value, &format!("%{name}%"), "\\\"$`")
+                }
+                Segment::Literal(text) => push_escaped(&mut value, text, "\\\"$`"),
+            }
+        }
+        value.push('"');
+        value
+    } else {
+        format!("'{}'", var.value.replace('\'', "'\\''"))
+    };
+    format!("export {}={value}", var.key)
+}
+
+fn dotenv_line(var: &EnvironmentVariable) -> String {
+    let mut value = String::from("\"");
+    for segment in segments(var) {
+        match segment {
+            Segment::Reference(name) if is_identifier(name) => {
+                value.push_str(&format!("${{{name}}}"))
+            }
+            Segment::Reference(name) => push_dotenv_escaped(&mut value, &format!("%{name}%")),
+            Segment::Literal(text) => push_dotenv_escaped(&mut value, text),
+        }
+    }
+    value.push('"');
+    format!("{}={value}", var.key)
+}
+
+fn push_dotenv_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\\' | '"' | '$' => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+}
+
+fn fish_set(var: &EnvironmentVariable) -> String {
+    let value = if var.kind == EnvValueKind::ExpandString {
+        // Fish only expands variables in double quotes up to the end of the
+        // longest name, so references go between the quoted parts instead
+        let mut value = String::new();
+        let mut quoted = false;
+        for segment in segments(var) {
+            match segment {
+                Segment::Reference(name) if name.chars().all(is_word_char) => {
+                    if quoted {
+                        value.push('"');
+                        quoted = false;
+                    }
+                    value.push_str(&format!("${name}"));
+                }
+                segment => {
+                    if !quoted {
+                        value.push('"');
+                        quoted = true;
+                    }
+                    match segment {
+                        Segment::Reference(name) => {
+                            push_escaped(&mut value, &format!("%{name}%"), "\\\"$")
+                        }
+                        Segment::Literal(text) => push_escaped(&mut value, text, "\\\"$"),
+                    }
+                }
+            }
+        }
+        if value.is_empty() {
+            value.push_str("\"\"");
+        } else if quoted {
+            value.push('"');
+        }
+        value
+    } else {
+        let mut value = String::from("'");
+        push_escaped(&mut value, &var.value, "\\'");
+        value.push('\'');
+        value
+    };
+    format!("set -gx {} {value}", var.key)
+}
+
+fn nu_env(name: &str) -> String {
+    if is_identifier(name) {
+        format!("$env.{name}")
+    } else {
+        let mut quoted = String::from("$env.\"");
+        push_escaped(&mut quoted, name, "\\\"");
+        quoted.push('"');
+        quoted
+    }
+}
+
+fn nu_set(var: &EnvironmentVariable) -> String {
+    let value = if var.kind == EnvValueKind::ExpandString {
+        let mut value = String::from("$\"");
+        for segment in segments(var) {
+            match segment {
+                Segment::Reference(name) => value.push_str(&format!("({})", nu_env(name))),
+                Segment::Literal(text) => push_escaped(&mut value, text, "\\\"("),
+            }
+        }
+        value.push('"');
+        value
+    } else if !var.value.contains('\'') {
+        format!("'{}'", var.value)
+    } else {
+        // Raw strings have no escapes, so add hashes until the value cannot end one early
+        let mut hashes = String::from("#");
+        while var.value.contains(&format!("'{hashes}")) {
+            hashes.push('#');
+        }
+        format!("r{hashes}'{}'{hashes}", var.value)
+    };
+    format!("{} = {value}", nu_env(&var.key))
+}
+
+/// Append `text`, putting a backslash before each character in `special`.
+fn push_escaped(out: &mut String, text: &str, special: &str) {
+    for c in text.chars() {
+        if special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::env_change::ChangeSet;
+use crate::env_change::plan_changes;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvironmentVariable;
+use crate::env_store::EnvStore;
+use eyre::Context;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Every variable in a set of scopes at one point in time.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    /// The scopes that were captured. A captured scope with no variables is
+    /// restored as empty, while scopes missing here are left alone.
+    pub scopes: Vec<EnvScope>,
+    pub variables: Vec<EnvironmentVariable>,
+}
+
+impl Snapshot {
+    pub fn capture(store: &dyn EnvStore, scopes: &[EnvScope]) -> eyre::Result<Self> {
+        let mut variables = Vec::new();
+        for &scope in scopes {
+            variables.extend(store.list(scope)?);
+        }
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Ok(Self {
+            version: SNAPSHOT_VERSION,
+            created,
+            scopes: scopes.to_vec(),
+            variables,
+        })
+    }
+
+    pub fn load(path: &Path) -> eyre::Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+        let snapshot: Snapshot = serde_json::from_str(&text)
+            .wrap_err_with(|| format!("Failed to parse {}", path.display()))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            eyre::bail!(
+                "{} has unsupported snapshot version {}",
+                path.display(),
+                snapshot.version
+            );
+        }
+        Ok(snapshot)
+    }
+
+    pub fn save(&self, path: &Path) -> eyre::Result<()> {
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .wrap_err_with(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// The changes that would bring `store` back to this snapshot, limited to
+    /// `scopes` that were also captured.
+    pub fn plan_restore(
+        &self,
+        store: &dyn EnvStore,
+        scopes: &[EnvScope],
+    ) -> eyre::Result<ChangeSet> {
+        let scopes: Vec<EnvScope> = scopes
+            .iter()
+            .copied()
+            .filter(|scope| self.scopes.contains(scope))
+            .collect();
+        let mut current = Vec::new();
+        for &scope in &scopes {
+            current.extend(store.list(scope)?);
+        }
+        Ok(plan_changes(&current, &self.variables, &scopes))
+    }
+}
@@ -0,0 +1,218 @@
+use crate::env_change::Change;
+use crate::env_change::ChangeSet;
+use crate::env_expand::references;
+use crate::env_list::ListSpec;
+use crate::env_path::PathList;
+use crate::env_path::list_kind;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::has_env_reference;
+use crate::env_store::EnvStore;
+use crate::env_validate::utf16_len;
+
+/// Helper variables are named this followed by a number, e.g. `PATH_EXT1`.
+pub const DEFAULT_HELPER_PREFIX: &str = "PATH_EXT";
+
+/// The changes for [`plan_split`] or [`plan_inline`], and what to tell the user about them.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct HelperPlan {
+    pub changes: ChangeSet,
+    pub warnings: Vec<String>,
+}
+
+/// Move a tail of the list variable described by `spec` into new helper
+/// variables so that every raw value is at most `limit` characters, and
+/// reference the helpers from the end of the list as `%PATH_EXT1%;%PATH_EXT2%`,
+/// joined with the list's separator.
+///
+/// Windows builds the environment in one pass, so a helper whose own value has
+/// `%NAME%` references may be substituted before those are expanded. Entries
+/// like that are still moved, with a warning.
+pub fn plan_split(
+    store: &dyn EnvStore,
+    scope: EnvScope,
+    spec: &ListSpec,
+    limit: usize,
+    prefix: &str,
+) -> eyre::Result<HelperPlan> {
+    let key = &spec.name;
+    let Some(var) = store.get(scope, key)? else {
+        eyre::bail!("{key} is not set in the {scope} environment");
+    };
+    let mut plan = HelperPlan::default();
+    if utf16_len(&var.value) <= limit {
+        return Ok(plan);
+    }
+    let original = PathList::parse_as(&var.value, spec);
+    let entries = &original.entries;
+    if let Some(entry) = entries.iter().find(|entry| utf16_len(entry) > limit) {
+        eyre::bail!("{entry} alone is longer than {limit} characters");
+    }
+
+    // Helpers are referenced from the same scope, so their names must be free in both
+    let mut names = Vec::new();
+    let mut number = 1;
+    while names.len() < entries.len() {
+        let name = format!("{prefix}{number}");
+        number += 1;
+        if store.get(EnvScope::Machine, &name)?.is_none()
+            && store.get(EnvScope::User, &name)?.is_none()
+        {
+            names.push(name);
+        }
+    }
+    let Some((head, chunks)) = split_entries(entries, limit, &names) else {
+        eyre::bail!("{key} cannot be split to fit in {limit} characters");
+    };
+
+    let mut kept = original.with_entries(entries[..head].to_vec());
+    for (name, chunk) in names.iter().zip(&chunks) {
+        let value = original.with_entries(chunk.to_vec()).to_value();
+        let kind = if has_env_reference(&value) {
+            for entry in chunk.iter().filter(|entry| has_env_reference(entry)) {
+                plan.warnings.push(format!(
+                    "{entry} moves into {name}, and Windows only expands one level of references when building {key}, so it may not resolve"
+                ));
+            }
+            EnvValueKind::ExpandString
+        } else {
+            EnvValueKind::String
+        };
+        plan.changes.push_set(store, scope, name, &value, kind)?;
+        kept.entries.push(format!("%{name}%"));
+    }
+    let value = kept.to_value();
+    plan.changes.push_set(
+        store,
+        scope,
+        &var.key,
+        &value,
+        list_kind(Some(var.kind), &value),
+    )?;
+    Ok(plan)
+}
+
+/// Pick how many entries stay in the original variable and how the rest are
+/// chunked into helpers, trying as few helpers as possible. `None` if it does
+/// not fit even with one helper per entry.
+fn split_entries<'a>(
+    entries: &'a [String],
+    limit: usize,
+    names: &[String],
+) -> Option<(usize, Vec<&'a [String]>)> {
+    for helpers in 1..=names.len() {
+        // Each reference costs `;%NAME%`, which overcounts by one when nothing else is kept
+        let reserved: usize = names[..helpers]
+            .iter()
+            .map(|name| utf16_len(name) + 3)
+            .sum();
+        if reserved - 1 > limit {
+            return None;
+        }
+        let head = fitting_prefix(entries, limit.saturating_sub(reserved));
+
+        let mut chunks: Vec<&[String]> = Vec::new();
+        let mut start = head;
+        while start < entries.len() {
+            let end = start + fitting_prefix(&entries[start..], limit).max(1);
+            chunks.push(&entries[start..end]);
+            start = end;
+        }
+        if chunks.len() <= helpers {
+            return Some((head, chunks));
+        }
+    }
+    None
+}
+
+/// How many of the first `entries` fit in `limit` characters once joined with `;`.
+fn fitting_prefix(entries: &[String], limit: usize) -> usize {
+    let mut len = 0;
+    for (count, entry) in entries.iter().enumerate() {
+        len += utf16_len(entry) + usize::from(count > 0);
+        if len > limit {
+            return count;
+        }
+    }
+    entries.len()
+}
+
+/// Replace every `%PATH_EXT1%` style entry of the list variable described by
+/// `spec` with the entries of that helper, then delete the helper unless
+/// something else still references it. The inverse of [`plan_split`].
+pub fn plan_inline(
+    store: &dyn EnvStore,
+    scope: EnvScope,
+    spec: &ListSpec,
+    prefix: &str,
+) -> eyre::Result<HelperPlan> {
+    let key = &spec.name;
+    let Some(var) = store.get(scope, key)? else {
+        eyre::bail!("{key} is not set in the {scope} environment");
+    };
+    let mut plan = HelperPlan::default();
+    let original = PathList::parse_as(&var.value, spec);
+    let mut list = original.with_entries(Vec::new());
+    let mut helpers = Vec::new();
+    for entry in original.entries {
+        let name = entry
+            .trim()
+            .strip_prefix('%')
+            .and_then(|rest| rest.strip_suffix('%'))
+            .filter(|name| {
+                name.len() > prefix.len()
+                    && name
+                        .get(..prefix.len())
+                        .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+                    && !name.contains('%')
+            });
+        let helper = match name {
+            Some(name) => store.get(scope, name)?.filter(|var| var.kind.is_string()),
+            None => None,
+        };
+        match helper {
+            Some(helper) => {
+                list.entries
+                    .extend(PathList::parse_as(&helper.value, spec).entries);
+                helpers.push(helper);
+            }
+            None => list.entries.push(entry),
+        }
+    }
+    if helpers.is_empty() {
+        return Ok(plan);
+    }
+
+    let value = list.to_value();
+    plan.changes.push_set(
+        store,
+        scope,
+        &var.key,
+        &value,
+        list_kind(Some(var.kind), &value),
+    )?;
+    let mut others = Vec::new();
+    for other_scope in EnvScope::ALL {
+        others.extend(store.list(other_scope)?.into_iter().filter(|other| {
+            !(other.scope == scope && other.key.eq_ignore_ascii_case(key))
+                && !helpers.iter().any(|helper| {
+                    helper.scope == other.scope && helper.key.eq_ignore_ascii_case(&other.key)
+                })
+        }));
+    }
+    for helper in helpers {
+        let user = others.iter().find(|other| {
+            references(&other.value, None)
+                .iter()
+                .any(|(_, name)| name.eq_ignore_ascii_case(&helper.key))
+        });
+        match user {
+            Some(user) => plan.warnings.push(format!(
+                "Keeping {} because {} {} still references it",
+                helper.key, user.scope, user.key
+            )),
+            None => plan.changes.push(Change::Delete { previous: helper }),
+        }
+    }
+    Ok(plan)
+}
@@ -0,0 +1,297 @@
+use crate::env_change::ChangeSet;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use crate::env_reader::KindChoice;
+use eyre::Context;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Storage backend for environment variables.
+///
+/// Names are compared case-insensitively, as Windows does.
+pub trait EnvStore {
+    /// What this store writes to, e.g. `registry` or the path of a JSON file,
+    /// so backups are only ever restored to the store they came from.
+    fn identity(&self) -> String;
+
+    /// Enumerate every variable in `scope`.
+    fn list(&self, scope: EnvScope) -> eyre::Result<Vec<EnvironmentVariable>>;
+
+    /// Look up a single variable, `Ok(None)` if it is not set.
+    fn get(&self, scope: EnvScope, key: &str) -> eyre::Result<Option<EnvironmentVariable>>;
+
+    /// Create or update a variable.
+    fn set(
+        &mut self,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        kind: EnvValueKind,
+    ) -> eyre::Result<()>;
+
+    /// Create or update a variable, picking its kind with `choice`.
+    /// Returns the kind that was written.
+    fn set_value(
+        &mut self,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        choice: KindChoice,
+    ) -> eyre::Result<EnvValueKind> {
+        let existing = match choice {
+            KindChoice::Preserve => self.get(scope, key)?.map(|var| var.kind),
+            _ => None,
+        };
+        let kind = choice.resolve(existing, value);
+        self.set(scope, key, value, kind)?;
+        Ok(kind)
+    }
+
+    /// Remove a variable, returning whether it existed.
+    fn delete(&mut self, scope: EnvScope, key: &str) -> eyre::Result<bool>;
+
+    /// Notify other processes that the environment changed.
+    fn broadcast_changes(&self) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// Apply every change in order, then broadcast once.
+    fn apply_changes(&mut self, changes: &ChangeSet) -> eyre::Result<()> {
+        changes.write(self)?;
+        if !changes.is_empty() {
+            self.broadcast_changes()?;
+        }
+        Ok(())
+    }
+}
+
+/// The live environment in the Windows registry.
+#[cfg(windows)]
+#[derive(Debug, Default)]
+pub struct RegistryEnvStore;
+
+#[cfg(windows)]
+impl EnvStore for RegistryEnvStore {
+    fn identity(&self) -> String {
+        "registry".to_string()
+    }
+
+    fn list(&self, scope: EnvScope) -> eyre::Result<Vec<EnvironmentVariable>> {
+        crate::env_reader::list_env_vars(scope)
+    }
+
+    fn get(&self, scope: EnvScope, key: &str) -> eyre::Result<Option<EnvironmentVariable>> {
+        crate::env_reader::get_env_var_entry(scope, key)
+    }
+
+    fn set(
+        &mut self,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        kind: EnvValueKind,
+    ) -> eyre::Result<()> {
+        crate::env_writer::write_env_var(scope, key, value, kind)
+    }
+
+    fn delete(&mut self, scope: EnvScope, key: &str) -> eyre::Result<bool> {
+        crate::env_writer::delete_env_var(scope, key)
+    }
+
+    fn broadcast_changes(&self) -> eyre::Result<()> {
+        crate::env_writer::broadcast_changes()
+    }
+}
+
+/// A store that only lives in memory, useful for tests and previews.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MemoryEnvStore {
+    variables: Vec<EnvironmentVariable>,
+}
+
+impl MemoryEnvStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn position(&self, scope: EnvScope, key: &str) -> Option<usize> {
+        self.variables
+            .iter()
+            .position(|var| var.scope == scope && var.key.eq_ignore_ascii_case(key))
+    }
+}
+
+impl EnvStore for MemoryEnvStore {
+    fn identity(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn list(&self, scope: EnvScope) -> eyre::Result<Vec<EnvironmentVariable>> {
+        Ok(self
+            .variables
+            .iter()
+            .filter(|var| var.scope == scope)
+            .cloned()
+            .collect())
+    }
+
+    fn get(&self, scope: EnvScope, key: &str) -> eyre::Result<Option<EnvironmentVariable>> {
+        Ok(self.position(scope, key).map(|i| self.variables[i].clone()))
+    }
+
+    fn set(
+        &mut self,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        kind: EnvValueKind,
+    ) -> eyre::Result<()> {
+        // Reject values the registry could not store either
+        kind.encode(value)?;
+        match self.position(scope, key) {
+            // Like the registry, overwriting keeps the original spelling of the name
+            Some(i) => {
+                let existing = &mut self.variables[i];
+                *existing = EnvironmentVariable::new(scope, existing.key.clone(), value, kind);
+            }
+            None => self
+                .variables
+                .push(EnvironmentVariable::new(scope, key, value, kind)),
+        }
+        Ok(())
+    }
+
+    fn delete(&mut self, scope: EnvScope, key: &str) -> eyre::Result<bool> {
+        match self.position(scope, key) {
+            Some(i) => {
+                self.variables.remove(i);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// On-disk representation used by [`JsonFileEnvStore`].
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonFileContents {
+    version: u32,
+    variables: Vec<JsonFileVariable>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonFileVariable {
+    // Files written before scopes existed only held machine variables
+    #[serde(default)]
+    scope: EnvScope,
+    key: String,
+    value: String,
+    kind: EnvValueKind,
+}
+
+const JSON_FILE_VERSION: u32 = 1;
+
+/// A store persisted to a JSON file, rewritten after every change.
+#[derive(Debug)]
+pub struct JsonFileEnvStore {
+    path: PathBuf,
+    inner: MemoryEnvStore,
+}
+
+impl JsonFileEnvStore {
+    /// Open the store at `path`, starting empty if the file does not exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> eyre::Result<Self> {
+        let path = path.into();
+        let mut inner = MemoryEnvStore::new();
+        if path.exists() {
+            let text = std::fs::read_to_string(&path)
+                .wrap_err_with(|| format!("Failed to read {}", path.display()))?;
+            let contents: JsonFileContents = serde_json::from_str(&text)
+                .wrap_err_with(|| format!("Failed to parse {}", path.display()))?;
+            if contents.version != JSON_FILE_VERSION {
+                eyre::bail!(
+                    "{} has unsupported version {}",
+                    path.display(),
+                    contents.version
+                );
+            }
+            for var in contents.variables {
+                inner.set(var.scope, &var.key, &var.value, var.kind)?;
+            }
+        }
+        Ok(Self { path, inner })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn save(&self) -> eyre::Result<()> {
+        let contents = JsonFileContents {
+            version: JSON_FILE_VERSION,
+            variables: self
+                .inner
+                .variables
+                .iter()
+                .map(|var| JsonFileVariable {
+                    scope: var.scope,
+                    key: var.key.clone(),
+                    value: var.value.clone(),
+                    kind: var.kind,
+                })
+                .collect(),
+        };
+        if let Some(parent) = self.path.parent()
+            && !parent.as_os_str().is_empty()
+        {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&self.path, serde_json::to_string_pretty(&contents)?)
+            .wrap_err_with(|| format!("Failed to write {}", self.path.display()))
+    }
+}
+
+impl EnvStore for JsonFileEnvStore {
+    /// The canonical path, so every spelling of it matches. Only the folder
+    /// is resolved, since the file may not have been written yet.
+    fn identity(&self) -> String {
+        let path = std::path::absolute(&self.path).unwrap_or_else(|_| self.path.clone());
+        let resolved = match (path.parent(), path.file_name()) {
+            (Some(parent), Some(name)) => std::fs::canonicalize(parent)
+                .map(|parent| parent.join(name))
+                .unwrap_or(path),
+            _ => path,
+        };
+        resolved.display().to_string()
+    }
+
+    fn list(&self, scope: EnvScope) -> eyre::Result<Vec<EnvironmentVariable>> {
+        self.inner.list(scope)
+    }
+
+    fn get(&self, scope: EnvScope, key: &str) -> eyre::Result<Option<EnvironmentVariable>> {
+        self.inner.get(scope, key)
+    }
+
+    fn set(
+        &mut self,
+        scope: EnvScope,
+        key: &str,
+        value: &str,
+        kind: EnvValueKind,
+    ) -> eyre::Result<()> {
+        self.inner.set(scope, key, value, kind)?;
+        self.save()
+    }
+
+    fn delete(&mut self, scope: EnvScope, key: &str) -> eyre::Result<bool> {
+        let existed = self.inner.delete(scope, key)?;
+        if existed {
+            self.save()?;
+        }
+        Ok(existed)
+    }
+}
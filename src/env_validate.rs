@@ -0,0 +1,143 @@
+use crate::env_effective::EffectiveEnvironment;
+use crate::env_expand::VariableSet;
+use crate::env_expand::expand;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::EnvironmentVariable;
+use serde::Serialize;
+
+/// The longest value the System Properties environment variable dialog accepts.
+/// Opening a longer value there and pressing OK silently truncates it.
+pub const DIALOG_VALUE_LIMIT: usize = 2047;
+
+/// The longest value a variable can have, in UTF-16 units including the terminating null.
+pub const VALUE_LIMIT: usize = 32_767;
+
+/// The size limit for a whole environment block (`NAME=value\0...\0`) in UTF-16 units.
+///
+/// Newer Windows versions no longer enforce this everywhere, but plenty of
+/// programs and older APIs still fail past it.
+pub const ENV_BLOCK_LIMIT: usize = 32_767;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found while validating, about one variable or the whole environment.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct Issue {
+    pub severity: Severity,
+    /// What the issue is about, e.g. `machine Path`.
+    pub subject: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.subject, self.message)
+    }
+}
+
+pub fn has_errors(issues: &[Issue]) -> bool {
+    issues.iter().any(|issue| issue.severity == Severity::Error)
+}
+
+/// Lengths of a value in UTF-16 units, the way Windows counts them.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub struct ValueLengths {
+    pub raw: usize,
+    pub expanded: usize,
+}
+
+pub fn utf16_len(s: &str) -> usize {
+    s.encode_utf16().count()
+}
+
+/// Measure `var` before and after expanding it against `vars`.
+pub fn value_lengths(var: &EnvironmentVariable, vars: &VariableSet) -> ValueLengths {
+    let raw = utf16_len(&var.value);
+    let expanded = match var.kind {
+        EnvValueKind::ExpandString => utf16_len(&expand(&var.value, vars).output),
+        _ => raw,
+    };
+    ValueLengths { raw, expanded }
+}
+
+/// Length problems with a single variable.
+pub fn check_value(var: &EnvironmentVariable, vars: &VariableSet) -> Vec<Issue> {
+    let lengths = value_lengths(var, vars);
+    let subject = format!("{} {}", var.scope, var.key);
+    let mut issues = Vec::new();
+    if lengths.raw + 1 > VALUE_LIMIT || lengths.expanded + 1 > VALUE_LIMIT {
+        issues.push(Issue {
+            severity: Severity::Error,
+            subject,
+            message: format!(
+                "value is {} characters ({} expanded), over the {} character limit",
+                lengths.raw,
+                lengths.expanded,
+                VALUE_LIMIT - 1
+            ),
+        });
+    } else if lengths.raw > DIALOG_VALUE_LIMIT {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            subject,
+            message: format!(
+                "value is {} characters, editing it in the System Properties dialog will truncate it to {DIALOG_VALUE_LIMIT}",
+                lengths.raw
+            ),
+        });
+    }
+    issues
+}
+
+/// The size of the environment block `vars` would produce, in UTF-16 units.
+///
+/// Scopes are merged the way [`EffectiveEnvironment::simulate`] does, so the
+/// user `Path` counts on top of the machine one.
+pub fn env_block_len(vars: &[EnvironmentVariable]) -> usize {
+    let entries: usize = EffectiveEnvironment::simulate(&[], vars)
+        .iter()
+        .map(|var| utf16_len(&var.name) + 1 + utf16_len(&var.value) + 1)
+        .sum();
+    entries + 1
+}
+
+/// Length problems across a whole environment, such as every variable in
+/// both scopes.
+pub fn check_environment(vars: &[EnvironmentVariable]) -> Vec<Issue> {
+    let set = VariableSet::from_vars(vars);
+    let mut issues: Vec<Issue> = vars
+        .iter()
+        .filter(|var| var.kind.is_string())
+        .flat_map(|var| check_value(var, &set))
+        .collect();
+    issues.extend(check_block(vars));
+    issues
+}
+
+/// An error if the environment block built from `vars` is over [`ENV_BLOCK_LIMIT`].
+pub fn check_block(vars: &[EnvironmentVariable]) -> Option<Issue> {
+    let block_len = env_block_len(vars);
+    (block_len > ENV_BLOCK_LIMIT).then(|| Issue {
+        severity: Severity::Error,
+        subject: "environment".to_string(),
+        message: format!(
+            "environment block would be {block_len} characters, over the {ENV_BLOCK_LIMIT} character limit"
+        ),
+    })
+}
@@ -0,0 +1,182 @@
+use crate::env_effective::EffectiveEnvironment;
+use crate::env_expand::expand;
+use crate::env_path::PATH_VAR;
+use crate::env_path::PathList;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvironmentVariable;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// What Windows uses when `PATHEXT` is not set.
+pub const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD;.VBS;.VBE;.JS;.JSE;.WSF;.WSH;.MSC";
+
+/// One directory searched for commands, with the `Path` entry it came from.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SearchDir {
+    pub scope: EnvScope,
+    /// The position of the entry in its scope's `Path`.
+    pub index: usize,
+    /// The entry as written, before expansion.
+    pub entry: String,
+    pub dir: PathBuf,
+}
+
+/// The directories searched for a command, in the order Windows searches them,
+/// and the extensions tried for a name without one.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SearchPath {
+    pub dirs: Vec<SearchDir>,
+    /// Lowercase extensions including the dot, in `PATHEXT` order.
+    pub extensions: Vec<String>,
+}
+
+/// A file that a command name resolves to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CommandMatch {
+    /// Index into [`SearchPath::dirs`].
+    pub dir: usize,
+    pub path: PathBuf,
+}
+
+impl SearchPath {
+    /// The search path a new process gets from `vars` and the session
+    /// `defaults`: the machine `Path` followed by the user `Path`, with
+    /// references expanded against the [`EffectiveEnvironment`], which also
+    /// supplies `PATHEXT`. Only the `Path` of `scopes` is searched.
+    pub fn from_vars(
+        vars: &[EnvironmentVariable],
+        defaults: &[(String, String)],
+        scopes: &[EnvScope],
+    ) -> Self {
+        let set = EffectiveEnvironment::simulate(defaults, vars).variable_set();
+        let mut dirs = Vec::new();
+        for scope in EnvScope::ALL {
+            if !scopes.contains(&scope) {
+                continue;
+            }
+            let Some(var) = vars
+                .iter()
+                .find(|var| var.scope == scope && var.key.eq_ignore_ascii_case(PATH_VAR))
+            else {
+                continue;
+            };
+            for (index, entry) in PathList::parse(&var.value).entries.into_iter().enumerate() {
+                let expanded = expand(entry.trim(), &set).output;
+                if expanded.trim().is_empty() {
+                    continue;
+                }
+                dirs.push(SearchDir {
+                    scope,
+                    index,
+                    dir: PathBuf::from(expanded.trim()),
+                    entry,
+                });
+            }
+        }
+        let pathext = set
+            .get("PATHEXT")
+            .map(|entry| entry.value.clone())
+            .unwrap_or_else(|| DEFAULT_PATHEXT.to_string());
+        Self {
+            dirs,
+            extensions: parse_pathext(&pathext),
+        }
+    }
+
+    /// Every file `name` resolves to, winner first.
+    ///
+    /// A name ending in one of the extensions is only looked up as written,
+    /// otherwise each extension is tried in order within each directory.
+    /// File names match case-insensitively, as on Windows, on every platform.
+    pub fn which(&self, name: &str) -> Vec<CommandMatch> {
+        let wanted = name.to_lowercase();
+        let candidates: Vec<String> = if self.has_command_extension(&wanted) {
+            vec![wanted]
+        } else {
+            self.extensions
+                .iter()
+                .map(|extension| format!("{wanted}{extension}"))
+                .collect()
+        };
+        let mut matches = Vec::new();
+        for (index, dir) in self.dirs.iter().enumerate() {
+            let files = list_files(&dir.dir);
+            for candidate in &candidates {
+                if let Some(file) = files.get(candidate) {
+                    matches.push(CommandMatch {
+                        dir: index,
+                        path: dir.dir.join(file),
+                    });
+                }
+            }
+        }
+        matches
+    }
+
+    /// Every command name found in more than one directory, with all of its
+    /// matches, winner first.
+    pub fn shadows(&self) -> BTreeMap<String, Vec<CommandMatch>> {
+        let mut commands: BTreeMap<String, Vec<CommandMatch>> = BTreeMap::new();
+        for (index, dir) in self.dirs.iter().enumerate() {
+            let files = list_files(&dir.dir);
+            // Within a directory the PATHEXT order decides, so visit files in that order
+            for extension in &self.extensions {
+                for (lowercase, file) in &files {
+                    let Some(stem) = lowercase.strip_suffix(extension.as_str()) else {
+                        continue;
+                    };
+                    if stem.is_empty() {
+                        continue;
+                    }
+                    commands
+                        .entry(stem.to_string())
+                        .or_default()
+                        .push(CommandMatch {
+                            dir: index,
+                            path: dir.dir.join(file),
+                        });
+                }
+            }
+        }
+        commands.retain(|_, matches| matches.iter().any(|found| found.dir != matches[0].dir));
+        commands
+    }
+
+    fn has_command_extension(&self, name: &str) -> bool {
+        self.extensions
+            .iter()
+            .any(|extension| name.len() > extension.len() && name.ends_with(extension.as_str()))
+    }
+}
+
+/// Split a `PATHEXT` value into lowercase extensions, adding a missing dot.
+pub fn parse_pathext(value: &str) -> Vec<String> {
+    let mut extensions: Vec<String> = Vec::new();
+    for extension in value.split(';').map(str::trim).filter(|e| !e.is_empty()) {
+        let extension = extension.to_lowercase();
+        let extension = if extension.starts_with('.') {
+            extension
+        } else {
+            format!(".{extension}")
+        };
+        if !extensions.contains(&extension) {
+            extensions.push(extension);
+        }
+    }
+    extensions
+}
+
+/// The files directly in `dir`, keyed by lowercase name. A directory that
+/// cannot be read has no files, since Windows skips it too.
+fn list_files(dir: &Path) -> BTreeMap<String, String> {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return BTreeMap::new();
+    };
+    read_dir
+        .flatten()
+        .filter(|entry| entry.path().is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .map(|name| (name.to_lowercase(), name))
+        .collect()
+}
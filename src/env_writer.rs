@@ -1,47 +1,63 @@
 use eyre::Result;
+use windows::Win32::Foundation::ERROR_FILE_NOT_FOUND;
+use windows::Win32::Foundation::ERROR_SUCCESS;
 use windows::Win32::Foundation::WPARAM;
 use windows::Win32::System::Registry::HKEY;
-use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
 use windows::Win32::System::Registry::KEY_SET_VALUE;
-use windows::Win32::System::Registry::REG_SZ;
 use windows::Win32::System::Registry::RegCloseKey;
+use windows::Win32::System::Registry::RegDeleteValueW;
 use windows::Win32::System::Registry::RegOpenKeyExW;
 use windows::Win32::System::Registry::RegSetValueExW;
 use windows::core::*;
 
-use crate::win_strings::MACHINE_ENV_SUB_KEY;
+use crate::env_reader::EnvScope;
+use crate::env_reader::EnvValueKind;
+use crate::env_reader::KindChoice;
+use crate::env_reader::get_env_var_entry;
+use crate::win_strings::env_registry_key;
 
 /// Registry path for machine-level environment variables
 
-/// Create or update a machine-level environment variable to the given string value (REG_SZ).
+/// Create or update a machine-level environment variable to the given string value.
+///
+/// An existing variable keeps its kind, so an expandable `Path` stays `REG_EXPAND_SZ`.
+/// New variables are written as `REG_SZ`.
 ///
 /// * `var_name` = the name of the variable, e.g. "ENV_EDIT_TEST"
 /// * `value` = the new string value
 pub fn set_machine_env_var(var_name: &str, value: &str) -> Result<()> {
-    // Convert name and value to wide strings
+    let existing = get_env_var_entry(EnvScope::Machine, var_name)?.map(|var| var.kind);
+    let kind = KindChoice::Preserve.resolve(existing, value);
+    write_env_var(EnvScope::Machine, var_name, value, kind)?;
+    broadcast_changes()?;
+    Ok(())
+}
+
+/// Create or update an environment variable in `scope` with an explicit value kind.
+///
+/// Unlike [`set_machine_env_var`] this does not broadcast the change, so callers
+/// making several edits can call [`broadcast_changes`] once at the end.
+pub fn write_env_var(
+    scope: EnvScope,
+    var_name: &str,
+    value: &str,
+    kind: EnvValueKind,
+) -> Result<()> {
+    let (root, sub_key) = env_registry_key(scope);
+    // Convert the name to a wide string and the value to the bytes for its kind
     let wide_name: Vec<u16> = var_name.encode_utf16().chain(std::iter::once(0)).collect();
-    let wide_val: Vec<u16> = value.encode_utf16().chain(std::iter::once(0)).collect();
+    let data = kind.encode(value)?;
     unsafe {
         // Open the registry key with KEY_SET_VALUE
         let mut hkey: HKEY = HKEY::default();
-        RegOpenKeyExW(
-            HKEY_LOCAL_MACHINE,
-            MACHINE_ENV_SUB_KEY,
-            None,
-            KEY_SET_VALUE,
-            &mut hkey,
-        )
-        .ok()?;
+        RegOpenKeyExW(root, sub_key, None, KEY_SET_VALUE, &mut hkey).ok()?;
 
-        // We set it as REG_SZ (typical for normal variables).
-        // If you want expansions (e.g. %SystemRoot%), you could use REG_EXPAND_SZ instead.
-        let data = wide_val.align_to::<u8>().1;
         let set_result = RegSetValueExW(
             hkey,
             PCWSTR(wide_name.as_ptr()),
             Some(0),
-            REG_SZ,
-            Some(data),
+            kind.to_reg(),
+            Some(&data),
         )
         .ok();
 
@@ -51,11 +67,34 @@ pub fn set_machine_env_var(var_name: &str, value: &str) -> Result<()> {
         set_result?;
     }
 
-    broadcast_changes()?;
-
     Ok(())
 }
 
+/// Remove an environment variable from `scope`.
+/// Returns `Ok(false)` if the variable did not exist.
+pub fn delete_env_var(scope: EnvScope, var_name: &str) -> Result<bool> {
+    let (root, sub_key) = env_registry_key(scope);
+    let wide_name: Vec<u16> = var_name.encode_utf16().chain(std::iter::once(0)).collect();
+    unsafe {
+        let mut hkey: HKEY = HKEY::default();
+        RegOpenKeyExW(root, sub_key, None, KEY_SET_VALUE, &mut hkey).ok()?;
+
+        let status = RegDeleteValueW(hkey, PCWSTR(wide_name.as_ptr()));
+
+        // want to close even if delete failed
+        RegCloseKey(hkey).ok()?;
+
+        match status {
+            ERROR_SUCCESS => Ok(true),
+            ERROR_FILE_NOT_FOUND => Ok(false),
+            x => Err(eyre::eyre!(
+                "RegDeleteValueW error for '{var_name}': 0x{:X}",
+                x.0
+            )),
+        }
+    }
+}
+
 pub fn broadcast_changes() -> eyre::Result<()> {
     use windows::Win32::Foundation::LPARAM;
     use windows::Win32::UI::WindowsAndMessaging::HWND_BROADCAST;
@@ -1,6 +1,31 @@
+#![feature(peer_credentials_unix_socket)]
 #![feature(try_blocks)]
-pub mod win_elevation;
-pub mod win_strings;
+pub mod console;
+pub mod data_dir;
+pub mod env_backup;
+pub mod env_broker;
+pub mod env_change;
+pub mod env_diff;
+pub mod env_dotenv;
+pub mod env_effective;
+pub mod env_expand;
+pub mod env_list;
+pub mod env_path;
 pub mod env_reader;
+pub mod env_refs;
+pub mod env_regfile;
+pub mod env_script;
+pub mod env_snapshot;
+pub mod env_split;
+pub mod env_store;
+pub mod env_validate;
+pub mod env_which;
+#[cfg(windows)]
 pub mod env_writer;
-pub mod init;
\ No newline at end of file
+pub mod init;
+pub mod relaunch;
+#[cfg(windows)]
+pub mod win_broker;
+#[cfg(windows)]
+pub mod win_elevation;
+pub mod win_strings;
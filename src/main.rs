@@ -1,93 +1,1706 @@
-use env_edit::env_reader::get_machine_env_var;
-use env_edit::env_reader::list_machine_env_var;
-use env_edit::env_writer::set_machine_env_var;
+use env_edit::console::PauseContext;
+use env_edit::env_backup::BackupStore;
+use env_edit::env_broker::Allowlist;
+use env_edit::env_broker::BrokerEnvStore;
+use env_edit::env_change::Change;
+use env_edit::env_change::ChangeSet;
+use env_edit::env_change::plan_rename;
+use env_edit::env_diff::diff;
+use env_edit::env_dotenv::parse_dotenv;
+use env_edit::env_effective::EffectiveEnvironment;
+use env_edit::env_effective::Origin;
+use env_edit::env_effective::process_defaults;
+use env_edit::env_expand::SpanOrigin;
+use env_edit::env_expand::VariableSet;
+use env_edit::env_expand::expand;
+use env_edit::env_expand::fill_expanded;
+use env_edit::env_list::EntryKind;
+use env_edit::env_list::ListSpec;
+use env_edit::env_list::ListSpecs;
+use env_edit::env_path::PATH_VAR;
+use env_edit::env_path::PathList;
+use env_edit::env_path::Position;
+use env_edit::env_path::list_kind;
+use env_edit::env_path::prefix_variables;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvironmentVariable;
+use env_edit::env_reader::KindChoice;
+use env_edit::env_refs::RefGraph;
+use env_edit::env_regfile::RegFormat;
+use env_edit::env_regfile::decode_reg_file;
+use env_edit::env_regfile::encode_reg_file;
+use env_edit::env_regfile::parse_reg;
+use env_edit::env_regfile::write_reg;
+use env_edit::env_script::Shell;
+use env_edit::env_script::write_script;
+use env_edit::env_snapshot::Snapshot;
+use env_edit::env_split::DEFAULT_HELPER_PREFIX;
+use env_edit::env_split::HelperPlan;
+use env_edit::env_split::plan_inline;
+use env_edit::env_split::plan_split;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::JsonFileEnvStore;
+use env_edit::env_validate::DIALOG_VALUE_LIMIT;
+use env_edit::env_validate::Issue;
+use env_edit::env_validate::Severity;
+use env_edit::env_validate::check_environment;
+use env_edit::env_validate::has_errors;
+use env_edit::env_validate::value_lengths;
+use env_edit::env_which::CommandMatch;
+use env_edit::env_which::SearchPath;
 use env_edit::init::init;
-use env_edit::win_elevation::ensure_elevated;
+use eyre::Context;
+use std::path::Path;
+use std::path::PathBuf;
+use tracing::error;
 use tracing::info;
+use tracing::warn;
 
+use clap::Args;
 use clap::Parser;
 use clap::Subcommand;
+use clap::ValueEnum;
 
 #[derive(Parser)]
 #[command(
     name = "env-edit",
     version,
-    about = "Edits machine-level environment variables"
+    about = "Edits machine and user environment variables"
 )]
 struct Cli {
+    /// Which environment to operate on [default: machine, or all for snapshot and restore]
+    #[arg(long, global = true, value_enum)]
+    scope: Option<ScopeArg>,
+    /// Operate on a JSON file instead of the registry (the default on non-Windows platforms)
+    #[arg(long, global = true, value_name = "FILE")]
+    store: Option<PathBuf>,
+    /// Send writes to an `env-edit broker` listening on ENDPOINT instead of making them here
+    #[arg(long, global = true, value_name = "ENDPOINT")]
+    broker: Option<String>,
+    /// Wait for Enter before exiting [default: only in a console window of its own]
+    #[arg(long, global = true)]
+    pause: bool,
+    /// Never wait for Enter before exiting, even with --pause
+    #[arg(long, global = true)]
+    no_pause: bool,
+    /// Set when relaunched as administrator, see `env_edit::relaunch`
+    #[arg(long, global = true, hide = true, value_name = "DIR")]
+    relay_output: Option<PathBuf>,
+    /// Set when relaunched as administrator, see `env_edit::relaunch`
+    #[arg(long, global = true, hide = true, value_name = "DIR")]
+    working_dir: Option<PathBuf>,
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Lists all machine environment variables
+    /// Lists all environment variables
     List,
-    /// Shows a single machine environment variable by name
+    /// Shows a single environment variable by name
     Show {
         #[arg(long)]
         key: String,
     },
-    /// Sets a machine environment variable
+    /// Sets an environment variable
     Set {
         #[arg(long)]
         key: String,
         #[arg(long)]
         value: String,
+        /// Registry value type to write, defaults to the existing variable's type
+        #[arg(long = "type", value_enum)]
+        kind: Option<KindArg>,
+    },
+    /// Removes an environment variable
+    #[command(alias = "delete")]
+    Unset {
+        #[arg(long)]
+        key: String,
+    },
+    /// Renames an environment variable, keeping its value and type
+    Rename {
+        #[arg(long)]
+        from: String,
+        #[arg(long)]
+        to: String,
+    },
+    /// Expands %NAME% references against the variables in the selected scopes
+    Expand {
+        /// Expand the value of this variable
+        #[arg(long, conflicts_with = "text", required_unless_present = "text")]
+        key: Option<String>,
+        /// Expand this text instead of a variable's value
+        #[arg(long)]
+        text: Option<String>,
+        /// Show which variable each part of the output came from
+        #[arg(long)]
+        trace: bool,
+    },
+    /// Shows what a variable references and what references it.
+    /// Without a name, reports every reference cycle and undefined reference.
+    Refs { name: Option<String> },
+    /// Saves every variable in the selected scopes to a JSON file
+    Snapshot {
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+    },
+    /// Changes variables back to how they were in a snapshot or backup
+    Restore {
+        #[arg(
+            value_name = "FILE",
+            required_unless_present_any = ["last", "id"],
+            conflicts_with_all = ["last", "id"]
+        )]
+        snapshot: Option<PathBuf>,
+        /// Undo the most recent change to this store by restoring its backup
+        #[arg(long, conflicts_with = "id")]
+        last: bool,
+        /// Restore the backup with this id, see `backups list`
+        #[arg(long)]
+        id: Option<String>,
+        /// Only show the changes that would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Shows what changed between a snapshot and the live environment, or
+    /// between two snapshots
+    Diff {
+        /// The older snapshot
+        #[arg(
+            value_name = "BEFORE",
+            required_unless_present = "machine_vs_user",
+            conflicts_with = "machine_vs_user"
+        )]
+        before: Option<PathBuf>,
+        /// The newer snapshot, defaults to the live environment
+        #[arg(value_name = "AFTER")]
+        after: Option<PathBuf>,
+        /// Compare live machine variables against user variables instead
+        #[arg(long)]
+        machine_vs_user: bool,
+        #[arg(long, value_enum, default_value_t = DiffFormat::Human)]
+        format: DiffFormat,
+    },
+    /// Writes a script that recreates the selected variables in a shell
+    Export {
+        #[arg(long, value_enum)]
+        format: ShellArg,
+        /// Only export these variables
+        #[arg(long)]
+        key: Vec<String>,
+        /// Write raw values to the registry with setx or reg add (cmd) or
+        /// SetEnvironmentVariable or Registry::SetValue (PowerShell) instead
+        /// of setting them for the session
+        #[arg(long)]
+        persistent: bool,
+        /// Write the script here instead of printing it
+        #[arg(long, value_name = "FILE")]
+        out: Option<PathBuf>,
+    },
+    /// Sets the variables defined in a .env file
+    Import {
+        #[arg(long, value_name = "FILE")]
+        from: PathBuf,
+        /// Only show the changes that would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Shows the environment a newly started program gets, with machine and
+    /// user variables merged and expanded the way Windows does at logon
+    Effective {
+        /// Only show this variable
+        #[arg(long)]
+        key: Option<String>,
+        /// Use this value for a session default such as USERPROFILE instead
+        /// of the one this process has
+        #[arg(long, value_name = "NAME=VALUE", value_parser = parse_define)]
+        define: Vec<(String, String)>,
+        /// Print JSON including where each value came from
+        #[arg(long)]
+        json: bool,
+    },
+    /// Shows which file a command name runs, searching Path with PATHEXT
+    Which {
+        name: String,
+        /// List every match in search order instead of just the winner
+        #[arg(long)]
+        all: bool,
+    },
+    /// Edits the entries of Path or another list variable, such as PATHEXT
+    Path {
+        /// The list variable to work on, see `lists show`
+        #[arg(long, global = true, default_value = PATH_VAR)]
+        key: String,
+        #[command(subcommand)]
+        command: PathCommand,
+    },
+    /// Declares which variables hold lists and how their entries compare
+    Lists {
+        #[command(subcommand)]
+        command: ListsCommand,
+    },
+    /// Exports to or imports from Windows .reg files
+    Reg {
+        #[command(subcommand)]
+        command: RegCommand,
+    },
+    /// Applies change sets sent by an unelevated env-edit, so that only the
+    /// write needs administrator rights
+    Broker {
+        /// The named pipe, or Unix socket path, to listen on
+        #[arg(long)]
+        endpoint: String,
+        /// A variable the broker may change, as SCOPE:NAME such as machine:Path,
+        /// where a trailing * matches any ending
+        #[arg(long = "allow", value_name = "SCOPE:NAME", required = true)]
+        allow: Vec<String>,
+        /// Only answer the process with this id, normally the env-edit that
+        /// launched the broker
+        #[arg(long)]
+        client_pid: Option<u32>,
+        /// Exit after answering one change set
+        #[arg(long)]
+        once: bool,
+    },
+    /// Manages the backups taken before every change
+    Backups {
+        #[command(subcommand)]
+        command: BackupsCommand,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DiffFormat {
+    Human,
+    Unified,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ShellArg {
+    Cmd,
+    Powershell,
+    Bash,
+    Fish,
+    Nu,
+    Dotenv,
+}
+
+impl ShellArg {
+    fn shell(self) -> Shell {
+        match self {
+            ShellArg::Cmd => Shell::Cmd,
+            ShellArg::Powershell => Shell::PowerShell,
+            ShellArg::Bash => Shell::Bash,
+            ShellArg::Fish => Shell::Fish,
+            ShellArg::Nu => Shell::Nu,
+            ShellArg::Dotenv => Shell::Dotenv,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ListsCommand {
+    /// Shows every list variable and how its entries are compared
+    Show,
+    /// Declares a variable as a list, or changes how a list is treated
+    Declare {
+        name: String,
+        #[arg(long, default_value_t = ';')]
+        separator: char,
+        /// What the entries are
+        #[arg(long, value_enum, default_value_t = EntriesArg::Directory)]
+        entries: EntriesArg,
+    },
+    /// Removes a declaration, so a built-in list is treated the default way again
+    Forget { name: String },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum EntriesArg {
+    /// Directories that should exist, compared like paths
+    Directory,
+    /// Files or directories that should exist, compared like paths
+    Path,
+    /// File extensions like .EXE, compared ignoring case
+    Extension,
+    /// Anything, compared exactly
+    Text,
+}
+
+impl EntriesArg {
+    fn kind(self) -> EntryKind {
+        match self {
+            EntriesArg::Directory => EntryKind::Directory,
+            EntriesArg::Path => EntryKind::Path,
+            EntriesArg::Extension => EntryKind::Extension,
+            EntriesArg::Text => EntryKind::Text,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum PathCommand {
+    /// Lists the entries in order
+    List,
+    /// Adds an entry, at the end unless told otherwise
+    Add {
+        entry: String,
+        #[command(flatten)]
+        position: PositionArgs,
+    },
+    /// Removes every entry that is the same as this one
+    Remove { entry: String },
+    /// Moves an entry to a different position
+    Move {
+        entry: String,
+        #[command(flatten)]
+        position: PositionArgs,
+    },
+    /// Removes entries that are the same as one listed earlier
+    Dedupe,
+    /// Lists command names found in more than one Path directory
+    Shadows,
+    /// Shortens Path by dropping duplicate entries and using variables such
+    /// as %ProgramFiles% for common prefixes
+    Compact {
+        /// Also drop entries that do not exist
+        #[arg(long)]
+        drop_missing: bool,
+        /// Only show what would change
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Moves trailing entries into helper variables referenced as %PATH_EXT1%,
+    /// so that no value is longer than the limit
+    Split {
+        /// The longest raw value to leave, by default what the System Properties dialog can edit
+        #[arg(long, default_value_t = DIALOG_VALUE_LIMIT)]
+        limit: usize,
+        /// Name helpers with this followed by a number
+        #[arg(long, default_value = DEFAULT_HELPER_PREFIX)]
+        prefix: String,
+        /// Only show the changes that would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Replaces %PATH_EXT1% style entries with the helper's entries and
+    /// removes the helper, undoing split
+    Inline {
+        /// Only inline helpers named this followed by something
+        #[arg(long, default_value = DEFAULT_HELPER_PREFIX)]
+        prefix: String,
+        /// Only show the changes that would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Reports duplicate, missing, quoted, relative and other problem entries
+    Lint {
+        /// Remove or unquote the entries that can be fixed
+        #[arg(long)]
+        fix: bool,
+        /// With --fix, also remove entries that do not exist
+        #[arg(long, requires = "fix")]
+        drop_missing: bool,
+        /// With --fix, only show the changes that would be made
+        #[arg(long, requires = "fix")]
+        dry_run: bool,
+    },
+}
+
+/// Where to put an entry. Directories match case-insensitively, ignoring
+/// trailing slashes and after expanding %NAME% references.
+#[derive(Args)]
+#[group(multiple = false)]
+struct PositionArgs {
+    /// Put it first
+    #[arg(long)]
+    prepend: bool,
+    /// Put it last
+    #[arg(long)]
+    append: bool,
+    /// Put it right before this entry
+    #[arg(long, value_name = "ENTRY")]
+    before: Option<String>,
+    /// Put it at this position, counting from 0
+    #[arg(long)]
+    index: Option<usize>,
+}
+
+impl PositionArgs {
+    fn position(self) -> Option<Position> {
+        match self {
+            PositionArgs { prepend: true, .. } => Some(Position::Prepend),
+            PositionArgs { append: true, .. } => Some(Position::Append),
+            PositionArgs {
+                before: Some(anchor),
+                ..
+            } => Some(Position::Before(anchor)),
+            PositionArgs {
+                index: Some(index), ..
+            } => Some(Position::Index(index)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum RegCommand {
+    /// Writes the variables in the selected scopes to a .reg file
+    Export {
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+        /// Write an older REGEDIT4 file instead of a version 5.00 one
+        #[arg(long)]
+        regedit4: bool,
+    },
+    /// Applies the environment values in a .reg file, ignoring any other keys
+    Import {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        /// Only show the changes that would be made
+        #[arg(long)]
+        dry_run: bool,
     },
 }
 
+#[derive(Subcommand)]
+enum BackupsCommand {
+    /// Lists the backups of the current store, oldest first
+    List,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ScopeArg {
+    User,
+    Machine,
+    All,
+}
+
+impl ScopeArg {
+    fn scopes(self) -> Vec<EnvScope> {
+        match self {
+            ScopeArg::User => vec![EnvScope::User],
+            ScopeArg::Machine => vec![EnvScope::Machine],
+            ScopeArg::All => EnvScope::ALL.to_vec(),
+        }
+    }
+
+    /// The scope to write to, rejecting `all`.
+    fn single(self) -> eyre::Result<EnvScope> {
+        match self {
+            ScopeArg::User => Ok(EnvScope::User),
+            ScopeArg::Machine => Ok(EnvScope::Machine),
+            ScopeArg::All => eyre::bail!("--scope all can only be used when reading"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum KindArg {
+    /// REG_SZ
+    Sz,
+    /// REG_EXPAND_SZ
+    Expand,
+    /// REG_EXPAND_SZ when the value contains a %NAME% reference
+    Auto,
+}
+
+impl KindArg {
+    /// Leaving `--type` off keeps the existing variable's type.
+    fn choice(kind: Option<KindArg>) -> KindChoice {
+        match kind {
+            None => KindChoice::Preserve,
+            Some(KindArg::Sz) => KindChoice::String,
+            Some(KindArg::Expand) => KindChoice::ExpandString,
+            Some(KindArg::Auto) => KindChoice::Auto,
+        }
+    }
+}
+
+impl Cli {
+    fn scope(&self) -> ScopeArg {
+        match (self.scope, &self.command) {
+            (Some(scope), _) => scope,
+            (
+                None,
+                Commands::Snapshot { .. }
+                | Commands::Restore { .. }
+                | Commands::Diff { .. }
+                | Commands::Reg { .. }
+                | Commands::Export { .. }
+                | Commands::Which { .. }
+                | Commands::Path {
+                    command: PathCommand::Shadows,
+                    ..
+                },
+            ) => ScopeArg::All,
+            (None, _) => ScopeArg::Machine,
+        }
+    }
+
+    /// `Some` if `--pause` or `--no-pause` was given.
+    fn pause_choice(&self) -> Option<bool> {
+        match (self.pause, self.no_pause) {
+            (_, true) => Some(false),
+            (true, false) => Some(true),
+            (false, false) => None,
+        }
+    }
+
+    /// Whether running this command against the registry needs administrator rights.
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn requires_elevation(&self) -> bool {
+        match &self.command {
+            // read-only, so we can run without admin rights
+            Commands::List
+            | Commands::Show { .. }
+            | Commands::Expand { .. }
+            | Commands::Refs { .. }
+            | Commands::Snapshot { .. }
+            | Commands::Backups { .. }
+            | Commands::Diff { .. }
+            | Commands::Export { .. }
+            | Commands::Which { .. }
+            | Commands::Effective { .. }
+            | Commands::Lists { .. }
+            | Commands::Import { dry_run: true, .. }
+            | Commands::Path {
+                command:
+                    PathCommand::List
+                    | PathCommand::Shadows
+                    | PathCommand::Compact { dry_run: true, .. }
+                    | PathCommand::Split { dry_run: true, .. }
+                    | PathCommand::Inline { dry_run: true, .. }
+                    | PathCommand::Lint { fix: false, .. }
+                    | PathCommand::Lint { dry_run: true, .. },
+                ..
+            }
+            | Commands::Reg {
+                command: RegCommand::Export { .. } | RegCommand::Import { dry_run: true, .. },
+            }
+            | Commands::Restore { dry_run: true, .. } => false,
+            Commands::Set { .. }
+            | Commands::Unset { .. }
+            | Commands::Rename { .. }
+            | Commands::Restore { .. }
+            | Commands::Reg { .. }
+            | Commands::Import { .. }
+            | Commands::Path { .. } => self
+                .scope()
+                .scopes()
+                .into_iter()
+                .any(EnvScope::requires_elevation),
+            // a broker can be sent changes to any scope
+            Commands::Broker { .. } => true,
+        }
+    }
+}
+
 fn main() -> eyre::Result<()> {
     init()?;
 
     // Parse CLI
     let cli = Cli::parse();
+    if let Some(dir) = &cli.working_dir {
+        std::env::set_current_dir(dir)
+            .wrap_err_with(|| format!("Failed to change directory to {}", dir.display()))?;
+    }
+    #[cfg(windows)]
+    if let Some(dir) = &cli.relay_output {
+        env_edit::win_elevation::redirect_output(dir)?;
+    }
+    // Nobody can see the console of a relaunched process or a broker
+    let unattended = cli.relay_output.is_some() || matches!(cli.command, Commands::Broker { .. });
+    let pause = PauseContext::detect(unattended).should_pause(cli.pause_choice());
 
-    // We only need elevation if we plan to modify registry
-    match &cli.command {
-        Commands::List | Commands::Show { .. } => {
-            // read-only, so we can run without admin rights
-        }
-        Commands::Set { .. } => {
-            // ensure elevated
-            ensure_elevated()?;
-        }
-    };
+    let mut store = open_store(&cli)?;
 
+    let scope = cli.scope();
+    let scopes = scope.scopes();
     match cli.command {
-        Commands::List => cmd_list()?,
-        Commands::Show { key } => cmd_show(&key)?,
-        Commands::Set { key, value } => cmd_set(&key, &value)?,
+        Commands::List => cmd_list(store.as_ref(), &scopes)?,
+        Commands::Show { key } => cmd_show(store.as_ref(), &scopes, &key)?,
+        Commands::Set { key, value, kind } => cmd_set(
+            store.as_mut(),
+            scope.single()?,
+            &key,
+            &value,
+            KindArg::choice(kind),
+        )?,
+        Commands::Unset { key } => cmd_unset(store.as_mut(), scope.single()?, &key)?,
+        Commands::Rename { from, to } => cmd_rename(store.as_mut(), scope.single()?, &from, &to)?,
+        Commands::Expand { key, text, trace } => cmd_expand(
+            store.as_ref(),
+            &scopes,
+            key.as_deref(),
+            text.as_deref(),
+            trace,
+        )?,
+        Commands::Refs { name } => cmd_refs(store.as_ref(), &scopes, name.as_deref())?,
+        Commands::Snapshot { out } => cmd_snapshot(store.as_ref(), &scopes, &out)?,
+        Commands::Restore {
+            snapshot,
+            last,
+            id,
+            dry_run,
+        } => {
+            let source = match (snapshot, last, id) {
+                (Some(path), _, _) => RestoreSource::Snapshot(path),
+                (None, _, Some(id)) => RestoreSource::Backup(Some(id)),
+                (None, _, None) => RestoreSource::Backup(None),
+            };
+            cmd_restore(store.as_mut(), &scopes, source, dry_run)?
+        }
+        Commands::Diff {
+            before,
+            after,
+            machine_vs_user,
+            format,
+        } => {
+            let source = match (before, machine_vs_user) {
+                (_, true) => DiffSource::MachineVsUser,
+                (Some(before), false) => DiffSource::Snapshots(before, after),
+                (None, false) => unreachable!("clap requires BEFORE or --machine-vs-user"),
+            };
+            cmd_diff(store.as_ref(), &scopes, source, format)?
+        }
+        Commands::Export {
+            format,
+            key,
+            persistent,
+            out,
+        } => cmd_export(
+            store.as_ref(),
+            &scopes,
+            format.shell(),
+            &key,
+            persistent,
+            out.as_deref(),
+        )?,
+        Commands::Import { from, dry_run } => {
+            cmd_import(store.as_mut(), scope.single()?, &from, dry_run)?
+        }
+        Commands::Effective { key, define, json } => {
+            cmd_effective(store.as_ref(), key.as_deref(), define, json)?
+        }
+        Commands::Which { name, all } => cmd_which(store.as_ref(), &scopes, &name, all)?,
+        Commands::Path { key, command } => {
+            let lists = ListSpecs::open_default()?;
+            if lists.get(&key).is_none() {
+                info!("{key} is not a declared list, treating it as ;-separated text");
+            }
+            let spec = lists.spec_for(&key);
+            match command {
+                PathCommand::List => cmd_path_list(store.as_ref(), &scopes, &spec)?,
+                PathCommand::Shadows => {
+                    if !key.eq_ignore_ascii_case(PATH_VAR) {
+                        eyre::bail!("Only {PATH_VAR} is searched for commands");
+                    }
+                    cmd_path_shadows(store.as_ref(), &scopes)?
+                }
+                PathCommand::Add { entry, position } => cmd_path_add(
+                    store.as_mut(),
+                    scope.single()?,
+                    &spec,
+                    &entry,
+                    position.position().unwrap_or(Position::Append),
+                )?,
+                PathCommand::Remove { entry } => {
+                    cmd_path_remove(store.as_mut(), scope.single()?, &spec, &entry)?
+                }
+                PathCommand::Move { entry, position } => {
+                    let Some(position) = position.position() else {
+                        eyre::bail!(
+                            "Say where to move it with --prepend, --append, --before or --index"
+                        );
+                    };
+                    cmd_path_move(store.as_mut(), scope.single()?, &spec, &entry, position)?
+                }
+                PathCommand::Dedupe => cmd_path_dedupe(store.as_mut(), scope.single()?, &spec)?,
+                PathCommand::Split {
+                    limit,
+                    prefix,
+                    dry_run,
+                } => {
+                    let plan = plan_split(store.as_ref(), scope.single()?, &spec, limit, &prefix)?;
+                    let description = format!("path split {}", spec.name);
+                    apply_helper_plan(store.as_mut(), plan, &description, dry_run)?
+                }
+                PathCommand::Inline { prefix, dry_run } => {
+                    let plan = plan_inline(store.as_ref(), scope.single()?, &spec, &prefix)?;
+                    let description = format!("path inline {}", spec.name);
+                    apply_helper_plan(store.as_mut(), plan, &description, dry_run)?
+                }
+                PathCommand::Compact {
+                    drop_missing,
+                    dry_run,
+                } => cmd_path_compact(
+                    store.as_mut(),
+                    scope.single()?,
+                    &spec,
+                    drop_missing,
+                    dry_run,
+                )?,
+                PathCommand::Lint {
+                    fix,
+                    drop_missing,
+                    dry_run,
+                } => cmd_path_lint(store.as_mut(), &scopes, &spec, fix, drop_missing, dry_run)?,
+            }
+        }
+        Commands::Lists { command } => cmd_lists(command)?,
+        Commands::Reg {
+            command: RegCommand::Export { out, regedit4 },
+        } => cmd_reg_export(store.as_ref(), &scopes, &out, regedit4)?,
+        Commands::Reg {
+            command: RegCommand::Import { file, dry_run },
+        } => cmd_reg_import(store.as_mut(), &scopes, &file, dry_run)?,
+        Commands::Backups {
+            command: BackupsCommand::List,
+        } => cmd_backups_list(store.as_ref())?,
+        Commands::Broker {
+            endpoint,
+            allow,
+            client_pid,
+            once,
+        } => cmd_broker(
+            store.as_mut(),
+            &endpoint,
+            &Allowlist::parse(allow)?,
+            client_pid,
+            once,
+        )?,
     }
 
     info!("Done!");
-    wait_for_enter();
+    if pause {
+        wait_for_enter();
+    }
     Ok(())
 }
 
-fn cmd_list() -> eyre::Result<()> {
-    let environment_variables = list_machine_env_var()?;
+#[cfg(windows)]
+fn open_store(cli: &Cli) -> eyre::Result<Box<dyn EnvStore>> {
+    use env_edit::env_store::RegistryEnvStore;
+    use env_edit::win_broker::launch_broker;
+    use env_edit::win_elevation::ensure_elevated;
+    use env_edit::win_elevation::is_elevated;
+
+    let store: Box<dyn EnvStore> = match &cli.store {
+        Some(path) => Box::new(JsonFileEnvStore::open(path)?),
+        None => Box::new(RegistryEnvStore),
+    };
+    if let Some(endpoint) = &cli.broker {
+        return Ok(Box::new(BrokerEnvStore::new(store, endpoint)));
+    }
+
+    // We only need elevation if we plan to modify the machine registry
+    if cli.store.is_some() || !cli.requires_elevation() || is_elevated() {
+        return Ok(store);
+    }
+    if let Commands::Broker { .. } = cli.command {
+        ensure_elevated()?;
+        return Ok(store);
+    }
+    // Stay unelevated and hand only the write to an elevated broker
+    let endpoint = format!("env-edit-broker-{}", std::process::id());
+    Ok(Box::new(BrokerEnvStore::launching(
+        store,
+        &endpoint,
+        launch_broker,
+    )))
+}
+
+#[cfg(not(windows))]
+fn open_store(cli: &Cli) -> eyre::Result<Box<dyn EnvStore>> {
+    // There is no registry here, so fall back to a file
+    let path = match &cli.store {
+        Some(path) => path.clone(),
+        None => env_edit::data_dir::default_store_path()?,
+    };
+    let store = Box::new(JsonFileEnvStore::open(path)?);
+    match &cli.broker {
+        Some(endpoint) => Ok(Box::new(BrokerEnvStore::new(store, endpoint))),
+        None => Ok(store),
+    }
+}
+
+/// Every variable in `scopes`, in the order given.
+fn list_scopes(
+    store: &dyn EnvStore,
+    scopes: &[EnvScope],
+) -> eyre::Result<Vec<EnvironmentVariable>> {
+    let mut rtn = Vec::new();
+    for &scope in scopes {
+        rtn.extend(store.list(scope)?);
+    }
+    Ok(rtn)
+}
+
+/// The values a new process would see, for expanding entries the way it would.
+fn effective_vars(all_vars: &[EnvironmentVariable]) -> VariableSet {
+    EffectiveEnvironment::simulate(&process_defaults(), all_vars).variable_set()
+}
+
+fn cmd_list(store: &dyn EnvStore, scopes: &[EnvScope]) -> eyre::Result<()> {
+    let mut environment_variables = list_scopes(store, scopes)?;
+    fill_expanded(&mut environment_variables);
+    for var in &environment_variables {
+        for diagnostic in &var.diagnostics {
+            warn!("{} {}: {diagnostic}", var.scope, var.key);
+        }
+    }
+    // Listing should always work, so even errors are only reported here
+    for issue in check_environment(&environment_variables) {
+        warn!("{issue}");
+    }
     let dump = serde_json::to_string_pretty(&environment_variables)?;
     println!("{dump}");
     Ok(())
 }
 
-fn cmd_show(key_name: &str) -> eyre::Result<()> {
-    match get_machine_env_var(key_name)? {
-        Some(value) => {
-            println!("{} = {}", key_name, value);
+fn cmd_show(store: &dyn EnvStore, scopes: &[EnvScope], key_name: &str) -> eyre::Result<()> {
+    for &scope in scopes {
+        // Only label the output when it could be ambiguous
+        let prefix = if scopes.len() > 1 {
+            format!("[{scope}] ")
+        } else {
+            String::new()
+        };
+        match store.get(scope, key_name)? {
+            Some(var) => {
+                println!("{prefix}{} = {}", key_name, var.value);
+            }
+            None => {
+                println!("{prefix}{} is not set.", key_name);
+            }
         }
-        None => {
-            println!("{} is not set.", key_name);
+    }
+    Ok(())
+}
+
+fn cmd_set(
+    store: &mut dyn EnvStore,
+    scope: EnvScope,
+    key_name: &str,
+    value: &str,
+    kind: KindChoice,
+) -> eyre::Result<()> {
+    let existing = match kind {
+        KindChoice::Preserve => store.get(scope, key_name)?.map(|var| var.kind),
+        _ => None,
+    };
+    let kind = kind.resolve(existing, value);
+    let mut changes = ChangeSet::new();
+    changes.push_set(store, scope, key_name, value, kind)?;
+    report_issues(&changes.validate(&list_scopes(store, &EnvScope::ALL)?))?;
+
+    // Because we've already done ensure_elevated(), we have the rights we need by now
+    apply_changes(store, &changes, &format!("set {scope} {key_name}"))?;
+    info!("Set {scope} {key_name} to {value} ({kind})");
+    Ok(())
+}
+
+fn cmd_unset(store: &mut dyn EnvStore, scope: EnvScope, key_name: &str) -> eyre::Result<()> {
+    let Some(previous) = store.get(scope, key_name)? else {
+        warn!("{key_name} is not set in the {scope} environment");
+        return Ok(());
+    };
+    let mut changes = ChangeSet::new();
+    changes.push(Change::Delete { previous });
+    apply_changes(store, &changes, &format!("unset {scope} {key_name}"))?;
+    info!("Removed {scope} {key_name}");
+    Ok(())
+}
+
+fn cmd_rename(store: &mut dyn EnvStore, scope: EnvScope, from: &str, to: &str) -> eyre::Result<()> {
+    let changes = plan_rename(store, scope, from, to)?;
+    apply_changes(store, &changes, &format!("rename {scope} {from} to {to}"))?;
+    info!("Renamed {scope} {from} to {to}");
+    Ok(())
+}
+
+fn cmd_expand(
+    store: &dyn EnvStore,
+    scopes: &[EnvScope],
+    key: Option<&str>,
+    text: Option<&str>,
+    trace: bool,
+) -> eyre::Result<()> {
+    let vars = VariableSet::from_vars(&list_scopes(store, scopes)?);
+    let input = match (key, text) {
+        (Some(key), _) => match vars.get(key) {
+            Some(entry) => entry.value.clone(),
+            None => eyre::bail!("{key} is not set"),
+        },
+        (None, Some(text)) => text.to_string(),
+        (None, None) => unreachable!("clap requires --key or --text"),
+    };
+
+    let expansion = expand(&input, &vars);
+    println!("{}", expansion.output);
+    if trace {
+        for span in expansion.substitutions() {
+            if let SpanOrigin::Variable { name, scope } = &span.origin {
+                let scope = scope.map(|scope| format!("{scope} ")).unwrap_or_default();
+                println!(
+                    "  {} -> {:?} from {scope}{name}",
+                    &input[span.source.clone()],
+                    &expansion.output[span.output.clone()],
+                );
+            }
+        }
+    }
+    for name in &expansion.unresolved {
+        warn!("%{name}% is not defined, leaving it as-is");
+    }
+    Ok(())
+}
+
+fn cmd_refs(store: &dyn EnvStore, scopes: &[EnvScope], name: Option<&str>) -> eyre::Result<()> {
+    let graph = RefGraph::build(&list_scopes(store, scopes)?);
+    let Some(name) = name else {
+        for cycle in graph.cycles() {
+            println!("cycle: {} -> {}", cycle.join(" -> "), cycle[0]);
+        }
+        for dangling in graph.dangling() {
+            println!(
+                "undefined: {} references %{}%",
+                dangling.from, dangling.name
+            );
+        }
+        return Ok(());
+    };
+
+    let definitions = graph.definitions(name);
+    if definitions.is_empty() {
+        eyre::bail!("{name} is not set");
+    }
+    for var in definitions {
+        println!("{} ({}) = {}", var.key, var.scope, var.value);
+    }
+    println!("references:");
+    for reference in graph.references(name) {
+        let scopes = graph
+            .definitions(reference)
+            .iter()
+            .map(|var| var.scope.to_string())
+            .collect::<Vec<_>>();
+        println!("  {reference} ({})", scopes.join(", "));
+    }
+    for reference in graph.dangling_references(name) {
+        println!("  {reference} (undefined)");
+    }
+    println!("referenced by:");
+    for dependent in graph.dependents(name) {
+        println!("  {dependent}");
+    }
+    if let Some(cycle) = graph.cycle_containing(name) {
+        warn!(
+            "{name} is part of a reference cycle: {} -> {}",
+            cycle.join(" -> "),
+            cycle[0]
+        );
+    }
+    Ok(())
+}
+
+fn cmd_snapshot(store: &dyn EnvStore, scopes: &[EnvScope], out: &Path) -> eyre::Result<()> {
+    let snapshot = Snapshot::capture(store, scopes)?;
+    snapshot.save(out)?;
+    info!(
+        "Saved {} variables to {}",
+        snapshot.variables.len(),
+        out.display()
+    );
+    Ok(())
+}
+
+/// Where `restore` gets the state to go back to.
+enum RestoreSource {
+    Snapshot(PathBuf),
+    /// A backup id, or the latest backup.
+    Backup(Option<String>),
+}
+
+fn cmd_restore(
+    store: &mut dyn EnvStore,
+    scopes: &[EnvScope],
+    source: RestoreSource,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let (changes, description) = match source {
+        RestoreSource::Snapshot(path) => {
+            let snapshot = Snapshot::load(&path)?;
+            let changes = snapshot.plan_restore(store, scopes)?;
+            (changes, format!("restore snapshot {}", path.display()))
+        }
+        RestoreSource::Backup(id) => {
+            let backups = BackupStore::open_default(store)?;
+            let backup = match id {
+                Some(id) => backups.get(&id)?,
+                None => match backups.latest()? {
+                    Some(backup) => backup,
+                    None => eyre::bail!("There are no backups yet"),
+                },
+            };
+            info!("Restoring backup {} ({})", backup.id, backup.description);
+            let changes = backup.plan_restore(store, scopes)?;
+            (changes, format!("restore backup {}", backup.id))
+        }
+    };
+    print!("{changes}");
+    if changes.is_empty() || dry_run {
+        return Ok(());
+    }
+    report_issues(&changes.validate(&list_scopes(store, &EnvScope::ALL)?))?;
+    apply_changes(store, &changes, &description)?;
+    info!("Applied {} changes", changes.len());
+    Ok(())
+}
+
+/// What `diff` compares.
+enum DiffSource {
+    /// A snapshot against another snapshot, or the live environment if there is no second.
+    Snapshots(PathBuf, Option<PathBuf>),
+    MachineVsUser,
+}
+
+fn cmd_diff(
+    store: &dyn EnvStore,
+    scopes: &[EnvScope],
+    source: DiffSource,
+    format: DiffFormat,
+) -> eyre::Result<()> {
+    let in_scopes = |vars: Vec<EnvironmentVariable>| -> Vec<EnvironmentVariable> {
+        vars.into_iter()
+            .filter(|var| scopes.contains(&var.scope))
+            .collect()
+    };
+    let (changes, labels) = match source {
+        DiffSource::Snapshots(before, after) => {
+            let before_snapshot = Snapshot::load(&before)?;
+            let (after_vars, after_label) = match after {
+                Some(after) => (
+                    Snapshot::load(&after)?.variables,
+                    after.display().to_string(),
+                ),
+                None => (
+                    list_scopes(store, &before_snapshot.scopes)?,
+                    "live".to_string(),
+                ),
+            };
+            let changes = diff(
+                &in_scopes(before_snapshot.variables),
+                &in_scopes(after_vars),
+                true,
+            );
+            (changes, (before.display().to_string(), after_label))
+        }
+        DiffSource::MachineVsUser => {
+            let changes = diff(
+                &store.list(EnvScope::Machine)?,
+                &store.list(EnvScope::User)?,
+                false,
+            );
+            (changes, ("machine".to_string(), "user".to_string()))
+        }
+    };
+    match format {
+        DiffFormat::Human => print!("{}", changes.to_human()),
+        DiffFormat::Unified => print!("{}", changes.to_unified(&labels.0, &labels.1)),
+        DiffFormat::Json => println!("{}", serde_json::to_string_pretty(&changes)?),
+    }
+    Ok(())
+}
+
+fn cmd_export(
+    store: &dyn EnvStore,
+    scopes: &[EnvScope],
+    shell: Shell,
+    keys: &[String],
+    persistent: bool,
+    out: Option<&Path>,
+) -> eyre::Result<()> {
+    let mut vars = list_scopes(store, scopes)?;
+    if !keys.is_empty() {
+        vars.retain(|var| keys.iter().any(|key| key.eq_ignore_ascii_case(&var.key)));
+        for key in keys {
+            if !vars.iter().any(|var| var.key.eq_ignore_ascii_case(key)) {
+                warn!("{key} is not set");
+            }
+        }
+    }
+    let script = write_script(&vars, shell, persistent)?;
+    match out {
+        Some(out) => {
+            std::fs::write(out, script)
+                .wrap_err_with(|| format!("Failed to write {}", out.display()))?;
+            info!("Exported {} variables to {}", vars.len(), out.display());
+        }
+        None => print!("{script}"),
+    }
+    Ok(())
+}
+
+fn cmd_import(
+    store: &mut dyn EnvStore,
+    scope: EnvScope,
+    from: &Path,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let text = std::fs::read_to_string(from)
+        .wrap_err_with(|| format!("Failed to read {}", from.display()))?;
+    let all_vars = list_scopes(store, &EnvScope::ALL)?;
+    let dotenv = parse_dotenv(&text, &VariableSet::from_vars(&all_vars))
+        .wrap_err_with(|| format!("Failed to parse {}", from.display()))?;
+    for warning in &dotenv.warnings {
+        warn!("{warning}");
+    }
+    let changes = dotenv.plan(store, scope)?;
+    print!("{changes}");
+    if changes.is_empty() || dry_run {
+        return Ok(());
+    }
+    report_issues(&changes.validate(&all_vars))?;
+    apply_changes(store, &changes, &format!("import {}", from.display()))?;
+    info!("Applied {} changes", changes.len());
+    Ok(())
+}
+
+fn cmd_path_list(store: &dyn EnvStore, scopes: &[EnvScope], spec: &ListSpec) -> eyre::Result<()> {
+    for &scope in scopes {
+        if scopes.len() > 1 {
+            println!("[{scope}]");
+        }
+        let Some(var) = store.get(scope, &spec.name)? else {
+            continue;
+        };
+        for (index, entry) in PathList::parse_as(&var.value, spec)
+            .entries
+            .iter()
+            .enumerate()
+        {
+            println!("{index:>3}  {entry}");
+        }
+    }
+    Ok(())
+}
+
+fn parse_define(text: &str) -> Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, found {text:?}")),
+    }
+}
+
+fn cmd_effective(
+    store: &dyn EnvStore,
+    key: Option<&str>,
+    define: Vec<(String, String)>,
+    json: bool,
+) -> eyre::Result<()> {
+    let mut defaults = process_defaults();
+    defaults.retain(|(name, _)| {
+        !define
+            .iter()
+            .any(|(defined, _)| defined.eq_ignore_ascii_case(name))
+    });
+    defaults.extend(define);
+    let env = EffectiveEnvironment::simulate(&defaults, &list_scopes(store, &EnvScope::ALL)?);
+    let vars: Vec<_> = match key {
+        Some(key) => {
+            let Some(var) = env.get(key) else {
+                eyre::bail!("{key} is not in the effective environment");
+            };
+            vec![var]
+        }
+        None => env.iter().collect(),
+    };
+    for var in &vars {
+        for name in &var.unresolved {
+            warn!("{}: %{name}% is not defined when it is expanded", var.name);
+        }
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&vars)?);
+    } else {
+        for var in vars {
+            println!("{}={}", var.name, var.value);
+        }
+    }
+    Ok(())
+}
+
+fn cmd_which(store: &dyn EnvStore, scopes: &[EnvScope], name: &str, all: bool) -> eyre::Result<()> {
+    if name.contains(['\\', '/']) {
+        eyre::bail!("{name} is a path, not a command name");
+    }
+    let search = SearchPath::from_vars(
+        &list_scopes(store, &EnvScope::ALL)?,
+        &process_defaults(),
+        scopes,
+    );
+    let matches = search.which(name);
+    if matches.is_empty() {
+        eyre::bail!("{name} was not found in {PATH_VAR}");
+    }
+    let shown = if all { &matches[..] } else { &matches[..1] };
+    for (i, found) in shown.iter().enumerate() {
+        let marker = if all && i == 0 {
+            "* "
+        } else if all {
+            "  "
+        } else {
+            ""
+        };
+        println!("{marker}{}", describe_match(&search, found));
+    }
+    Ok(())
+}
+
+fn cmd_path_shadows(store: &dyn EnvStore, scopes: &[EnvScope]) -> eyre::Result<()> {
+    let search = SearchPath::from_vars(
+        &list_scopes(store, &EnvScope::ALL)?,
+        &process_defaults(),
+        scopes,
+    );
+    let shadows = search.shadows();
+    if shadows.is_empty() {
+        info!("No command is found in more than one {PATH_VAR} directory");
+    }
+    for (name, matches) in shadows {
+        println!("{name}");
+        for (i, found) in matches.iter().enumerate() {
+            let marker = if i == 0 { "*" } else { " " };
+            println!("  {marker} {}", describe_match(&search, found));
         }
     }
     Ok(())
 }
 
-fn cmd_set(key_name: &str, value: &str) -> eyre::Result<()> {
-    // Because we've already done ensure_elevated(), we are definitely admin by now
-    set_machine_env_var(key_name, value)?;
-    info!("Set {key_name} to {value}");
+/// `C:\Python\python.exe (machine Path[3] %PYTHON_HOME%)`, leaving out the
+/// entry when it is the same as the directory.
+fn describe_match(search: &SearchPath, found: &CommandMatch) -> String {
+    let dir = &search.dirs[found.dir];
+    let mut source = format!("{} {PATH_VAR}[{}]", dir.scope, dir.index);
+    if Path::new(dir.entry.trim()) != dir.dir {
+        source.push_str(&format!(" {}", dir.entry));
+    }
+    format!("{} ({source})", found.path.display())
+}
+
+/// Apply `edit` to the list variable `spec` in `scope` and write it back if it changed.
+fn edit_path(
+    store: &mut dyn EnvStore,
+    scope: EnvScope,
+    spec: &ListSpec,
+    description: &str,
+    edit: impl FnOnce(&mut PathList, &VariableSet) -> eyre::Result<()>,
+) -> eyre::Result<()> {
+    let all_vars = list_scopes(store, &EnvScope::ALL)?;
+    let vars = effective_vars(&all_vars);
+    let existing = store.get(scope, &spec.name)?;
+    let value = existing.as_ref().map_or("", |var| var.value.as_str());
+    let mut list = PathList::parse_as(value, spec);
+    let before = list.clone();
+    edit(&mut list, &vars)?;
+    let name = &spec.name;
+    if list == before {
+        info!("{scope} {name} is unchanged");
+        return Ok(());
+    }
+
+    let value = list.to_value();
+    let existing_kind = existing.as_ref().map(|var| var.kind);
+    let kind = list_kind(existing_kind, &value);
+    if existing_kind.is_some_and(|existing| existing != kind) {
+        info!("Changing {scope} {name} to {kind} so its %NAME% references expand");
+    }
+    let key = existing
+        .as_ref()
+        .map_or(name.as_str(), |var| var.key.as_str());
+    let mut changes = ChangeSet::new();
+    changes.push_set(store, scope, key, &value, kind)?;
+    report_issues(&changes.validate(&all_vars))?;
+    apply_changes(store, &changes, description)
+}
+
+fn cmd_path_add(
+    store: &mut dyn EnvStore,
+    scope: EnvScope,
+    spec: &ListSpec,
+    entry: &str,
+    position: Position,
+) -> eyre::Result<()> {
+    let name = &spec.name;
+    edit_path(
+        store,
+        scope,
+        spec,
+        &format!("path add {scope} {name} {entry}"),
+        |list, vars| {
+            let index = list.insert(entry, &position, vars)?;
+            info!("Added {entry} to {scope} {name} at index {index}");
+            Ok(())
+        },
+    )
+}
+
+fn cmd_path_remove(
+    store: &mut dyn EnvStore,
+    scope: EnvScope,
+    spec: &ListSpec,
+    entry: &str,
+) -> eyre::Result<()> {
+    let name = &spec.name;
+    edit_path(
+        store,
+        scope,
+        spec,
+        &format!("path remove {scope} {name} {entry}"),
+        |list, vars| {
+            let removed = list.remove(entry, vars);
+            if removed.is_empty() {
+                eyre::bail!("{entry} is not in the {scope} {name}");
+            }
+            for removed in removed {
+                info!("Removed {removed} from {scope} {name}");
+            }
+            Ok(())
+        },
+    )
+}
+
+fn cmd_path_move(
+    store: &mut dyn EnvStore,
+    scope: EnvScope,
+    spec: &ListSpec,
+    entry: &str,
+    position: Position,
+) -> eyre::Result<()> {
+    let name = &spec.name;
+    edit_path(
+        store,
+        scope,
+        spec,
+        &format!("path move {scope} {name} {entry}"),
+        |list, vars| {
+            let index = list.move_entry(entry, &position, vars)?;
+            info!("Moved {entry} to index {index} in {scope} {name}");
+            Ok(())
+        },
+    )
+}
+
+fn cmd_path_dedupe(store: &mut dyn EnvStore, scope: EnvScope, spec: &ListSpec) -> eyre::Result<()> {
+    let name = &spec.name;
+    edit_path(
+        store,
+        scope,
+        spec,
+        &format!("path dedupe {scope} {name}"),
+        |list, vars| {
+            for removed in list.dedupe(vars) {
+                info!("Removed duplicate {removed} from {scope} {name}");
+            }
+            Ok(())
+        },
+    )
+}
+
+fn cmd_path_compact(
+    store: &mut dyn EnvStore,
+    scope: EnvScope,
+    spec: &ListSpec,
+    drop_missing: bool,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let name = &spec.name;
+    if !matches!(spec.entries, EntryKind::Directory | EntryKind::Path) {
+        eyre::bail!(
+            "{name} holds {} entries, only paths can be compacted",
+            spec.entries
+        );
+    }
+    let all_vars = list_scopes(store, &EnvScope::ALL)?;
+    let effective = EffectiveEnvironment::simulate(&process_defaults(), &all_vars);
+    let vars = effective.variable_set();
+    let Some(var) = store.get(scope, name)? else {
+        eyre::bail!("There is no {scope} {name}");
+    };
+
+    // A machine list can only rely on machine variables, or ones Windows always sets
+    let mut prefixes = VariableSet::new();
+    for name in prefix_variables(scope) {
+        if let Some(defined) = effective.get(name)
+            && (scope == EnvScope::User || defined.origin != Origin::User)
+            && defined.unresolved.is_empty()
+        {
+            prefixes.insert(name, &defined.value, None);
+        }
+    }
+
+    let compaction = PathList::parse_as(&var.value, spec).compact(&vars, &prefixes, drop_missing);
+    for issue in &compaction.removed {
+        println!("- [{}] {}: {}", issue.index, issue.entry, issue.problem);
+    }
+    for (before, after) in &compaction.substituted {
+        println!("~ {before} -> {after}");
+    }
+    let value = compaction.list.to_value();
+    let kind = list_kind(Some(var.kind), &value);
+    let after = EnvironmentVariable::new(scope, &var.key, &value, kind);
+    let (before_len, after_len) = (value_lengths(&var, &vars), value_lengths(&after, &vars));
+    println!(
+        "Raw length {} -> {}, expanded length {} -> {}",
+        before_len.raw, after_len.raw, before_len.expanded, after_len.expanded
+    );
+    if after_len.raw > DIALOG_VALUE_LIMIT {
+        warn!(
+            "{scope} {name} is still over the {DIALOG_VALUE_LIMIT} characters the System Properties dialog can edit"
+        );
+    }
+    if var.kind != kind {
+        info!("Changing {scope} {name} to {kind} so its %NAME% references expand");
+    }
+
+    let mut changes = ChangeSet::new();
+    changes.push_set_if_changed(store, scope, &var.key, &value, kind)?;
+    if changes.is_empty() {
+        info!("{scope} {name} is already as short as it gets");
+        return Ok(());
+    }
+    if dry_run {
+        return Ok(());
+    }
+    report_issues(&changes.validate(&all_vars))?;
+    apply_changes(store, &changes, &format!("path compact {scope} {name}"))
+}
+
+fn apply_helper_plan(
+    store: &mut dyn EnvStore,
+    plan: HelperPlan,
+    description: &str,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    for warning in &plan.warnings {
+        warn!("{warning}");
+    }
+    let changes = plan.changes;
+    if changes.is_empty() {
+        info!("Nothing to do");
+        return Ok(());
+    }
+    if dry_run {
+        print!("{changes}");
+        return Ok(());
+    }
+    report_issues(&changes.validate(&list_scopes(store, &EnvScope::ALL)?))?;
+    apply_changes(store, &changes, description)?;
+    info!("Applied {} changes", changes.len());
+    Ok(())
+}
+
+fn cmd_path_lint(
+    store: &mut dyn EnvStore,
+    scopes: &[EnvScope],
+    spec: &ListSpec,
+    fix: bool,
+    drop_missing: bool,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let all_vars = list_scopes(store, &EnvScope::ALL)?;
+    let vars = effective_vars(&all_vars);
+    let mut issues = Vec::new();
+    let mut changes = ChangeSet::new();
+    for &scope in scopes {
+        let Some(var) = store.get(scope, &spec.name)? else {
+            continue;
+        };
+        let list = PathList::parse_as(&var.value, spec);
+        let found = list.lint(&vars);
+        for issue in &found {
+            println!("{scope} {} {issue}", spec.name);
+        }
+        if fix {
+            let value = list.fixed(&found, drop_missing).to_value();
+            let kind = list_kind(Some(var.kind), &value);
+            changes.push_set_if_changed(store, scope, &var.key, &value, kind)?;
+        }
+        issues.extend(found);
+    }
+    if issues.is_empty() {
+        info!("No problems found");
+    }
+
+    if !fix {
+        if issues
+            .iter()
+            .any(|issue| issue.severity() == Severity::Error)
+        {
+            eyre::bail!("Found {} problems, including errors", issues.len());
+        }
+        return Ok(());
+    }
+    let unfixable = issues
+        .iter()
+        .filter(|issue| !issue.problem.is_fixable(drop_missing))
+        .count();
+    if unfixable > 0 {
+        warn!("{unfixable} problems have to be fixed by hand");
+    }
+    if dry_run {
+        print!("{changes}");
+        return Ok(());
+    }
+    report_issues(&changes.validate(&all_vars))?;
+    apply_changes(store, &changes, &format!("path lint --fix {}", spec.name))?;
+    info!("Applied {} changes", changes.len());
+    Ok(())
+}
+
+fn cmd_broker(
+    store: &mut dyn EnvStore,
+    endpoint: &str,
+    allowlist: &Allowlist,
+    client_pid: Option<u32>,
+    once: bool,
+) -> eyre::Result<()> {
+    info!("Listening for change sets on {endpoint}");
+    #[cfg(windows)]
+    env_edit::win_broker::serve(endpoint, store, allowlist, client_pid, once)?;
+    #[cfg(unix)]
+    env_edit::env_broker::serve(endpoint, store, allowlist, client_pid, once)?;
+    Ok(())
+}
+
+fn cmd_lists(command: ListsCommand) -> eyre::Result<()> {
+    let mut lists = ListSpecs::open_default()?;
+    match command {
+        ListsCommand::Show => {
+            for (spec, declared) in lists.all() {
+                let origin = if declared { "declared" } else { "built in" };
+                println!(
+                    "{}  separator {:?}, {} entries ({origin})",
+                    spec.name, spec.separator, spec.entries
+                );
+            }
+            if let Some(path) = lists.path() {
+                println!("Declarations are saved in {}", path.display());
+            }
+        }
+        ListsCommand::Declare {
+            name,
+            separator,
+            entries,
+        } => {
+            lists.declare(ListSpec::new(&name, separator, entries.kind()))?;
+            lists.save()?;
+            info!("Declared {name} as a list of {} entries", entries.kind());
+        }
+        ListsCommand::Forget { name } => {
+            if !lists.forget(&name) {
+                eyre::bail!("{name} was never declared");
+            }
+            lists.save()?;
+            info!("Forgot the declaration of {name}");
+        }
+    }
+    Ok(())
+}
+
+fn cmd_reg_export(
+    store: &dyn EnvStore,
+    scopes: &[EnvScope],
+    out: &Path,
+    regedit4: bool,
+) -> eyre::Result<()> {
+    let format = if regedit4 {
+        RegFormat::Regedit4
+    } else {
+        RegFormat::Regedit5
+    };
+    let vars = list_scopes(store, scopes)?;
+    let text = write_reg(&vars, format)?;
+    std::fs::write(out, encode_reg_file(&text, format))
+        .wrap_err_with(|| format!("Failed to write {}", out.display()))?;
+    info!("Exported {} variables to {}", vars.len(), out.display());
+    Ok(())
+}
+
+fn cmd_reg_import(
+    store: &mut dyn EnvStore,
+    scopes: &[EnvScope],
+    file: &Path,
+    dry_run: bool,
+) -> eyre::Result<()> {
+    let bytes =
+        std::fs::read(file).wrap_err_with(|| format!("Failed to read {}", file.display()))?;
+    let reg = parse_reg(&decode_reg_file(&bytes))?;
+    for warning in &reg.warnings {
+        warn!("{warning}");
+    }
+    let changes = reg.plan(store, scopes)?;
+    print!("{changes}");
+    if changes.is_empty() || dry_run {
+        return Ok(());
+    }
+    report_issues(&changes.validate(&list_scopes(store, &EnvScope::ALL)?))?;
+    apply_changes(store, &changes, &format!("import {}", file.display()))?;
+    info!("Applied {} changes", changes.len());
+    Ok(())
+}
+
+fn cmd_backups_list(store: &dyn EnvStore) -> eyre::Result<()> {
+    for backup in BackupStore::open_default(store)?.list()? {
+        let keys = backup
+            .entries
+            .iter()
+            .map(|entry| format!("{} {}", entry.scope, entry.key))
+            .collect::<Vec<_>>();
+        println!(
+            "{}  {}  [{}]",
+            backup.id,
+            backup.description,
+            keys.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Back up everything `changes` is about to overwrite, then apply it.
+///
+/// Every command that writes goes through here, so `restore --last` can
+/// always undo it.
+fn apply_changes(
+    store: &mut dyn EnvStore,
+    changes: &ChangeSet,
+    description: &str,
+) -> eyre::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let id = BackupStore::open_default(store)?.record(changes, description)?;
+    changes.apply(store)?;
+    info!("Saved the previous values as backup {id}");
+    Ok(())
+}
+
+/// Log validation issues, failing if any of them are errors.
+fn report_issues(issues: &[Issue]) -> eyre::Result<()> {
+    for issue in issues {
+        match issue.severity {
+            Severity::Error => error!("{issue}"),
+            Severity::Warning => warn!("{issue}"),
+            Severity::Info => info!("{issue}"),
+        }
+    }
+    if has_errors(issues) {
+        eyre::bail!("Refusing to continue because of the errors above");
+    }
     Ok(())
 }
 
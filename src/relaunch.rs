@@ -0,0 +1,73 @@
+use crate::win_strings::quote_windows_arg;
+use eyre::Context;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Tells a relaunched process to write its output into this directory instead
+/// of its own console, which the original process never sees.
+pub const RELAY_OUTPUT_ARG: &str = "--relay-output";
+
+/// Tells a relaunched process which directory to resolve relative paths from,
+/// since an elevated process starts in `System32` whatever it is asked for.
+pub const WORKING_DIR_ARG: &str = "--working-dir";
+
+/// Where a relaunched process writes what it would have printed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RelayFiles {
+    pub stdout: PathBuf,
+    pub stderr: PathBuf,
+}
+
+impl RelayFiles {
+    pub fn in_dir(dir: &Path) -> Self {
+        Self {
+            stdout: dir.join("stdout.txt"),
+            stderr: dir.join("stderr.txt"),
+        }
+    }
+
+    /// Copy what the relaunched process wrote to our own stdout and stderr.
+    /// A file that was never created relays nothing.
+    pub fn relay(&self) -> eyre::Result<()> {
+        relay_file(&self.stdout, &mut std::io::stdout())?;
+        relay_file(&self.stderr, &mut std::io::stderr())
+    }
+}
+
+fn relay_file(path: &Path, out: &mut impl Write) -> eyre::Result<()> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => {
+            return Err(error).wrap_err_with(|| format!("Failed to read {}", path.display()));
+        }
+    };
+    out.write_all(&bytes)?;
+    out.flush()?;
+    Ok(())
+}
+
+/// A fresh directory for a relaunched process to relay its output through.
+pub fn relay_dir() -> eyre::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("env-edit-relay-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir)
+        .wrap_err_with(|| format!("Failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// The parameters to relaunch ourselves with: the original `args`, without
+/// the program name, after the arguments that relay output through
+/// `relay_dir` and restore `working_dir`. Each argument is quoted so the new
+/// process parses exactly the same arguments back out.
+pub fn relaunch_command_line(args: &[String], relay_dir: &Path, working_dir: &Path) -> String {
+    let relay_dir = relay_dir.to_string_lossy();
+    let working_dir = working_dir.to_string_lossy();
+    [RELAY_OUTPUT_ARG, &relay_dir, WORKING_DIR_ARG, &working_dir]
+        .into_iter()
+        .chain(args.iter().map(String::as_str))
+        .map(quote_windows_arg)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,187 @@
+use crate::env_broker::Allowlist;
+use crate::env_broker::broker_command_line;
+use crate::env_broker::pipe_path;
+use crate::env_broker::serve_connection;
+use crate::env_store::EnvStore;
+use crate::win_elevation::start_elevated;
+use crate::win_strings::to_wide_null;
+use eyre::Context;
+use std::fs::File;
+use std::io::BufReader;
+use std::os::windows::io::AsRawHandle;
+use std::os::windows::io::FromRawHandle;
+use tracing::info;
+use tracing::warn;
+use windows::Win32::Foundation::CloseHandle;
+use windows::Win32::Foundation::ERROR_PIPE_CONNECTED;
+use windows::Win32::Foundation::HANDLE;
+use windows::Win32::Foundation::HLOCAL;
+use windows::Win32::Foundation::LocalFree;
+use windows::Win32::Security::Authorization::ConvertSidToStringSidW;
+use windows::Win32::Security::Authorization::ConvertStringSecurityDescriptorToSecurityDescriptorW;
+use windows::Win32::Security::Authorization::SDDL_REVISION_1;
+use windows::Win32::Security::GetTokenInformation;
+use windows::Win32::Security::PSECURITY_DESCRIPTOR;
+use windows::Win32::Security::SECURITY_ATTRIBUTES;
+use windows::Win32::Security::TOKEN_QUERY;
+use windows::Win32::Security::TOKEN_USER;
+use windows::Win32::Security::TokenUser;
+use windows::Win32::Storage::FileSystem::FILE_FLAG_FIRST_PIPE_INSTANCE;
+use windows::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+use windows::Win32::System::Pipes::ConnectNamedPipe;
+use windows::Win32::System::Pipes::CreateNamedPipeW;
+use windows::Win32::System::Pipes::GetNamedPipeClientProcessId;
+use windows::Win32::System::Pipes::PIPE_READMODE_BYTE;
+use windows::Win32::System::Pipes::PIPE_REJECT_REMOTE_CLIENTS;
+use windows::Win32::System::Pipes::PIPE_TYPE_BYTE;
+use windows::Win32::System::Pipes::PIPE_WAIT;
+use windows::Win32::System::Threading::GetCurrentProcess;
+use windows::Win32::System::Threading::OpenProcess;
+use windows::Win32::System::Threading::OpenProcessToken;
+use windows::Win32::System::Threading::PROCESS_QUERY_LIMITED_INFORMATION;
+use windows::core::PCWSTR;
+use windows::core::PWSTR;
+
+/// Full control for SYSTEM and administrators, read and write for the user
+/// who runs the unelevated front-end. The default for an elevated process
+/// would only let administrators write.
+fn pipe_sddl(user_sid: &str) -> String {
+    format!("D:(A;;GA;;;SY)(A;;GA;;;BA)(A;;GRGW;;;{user_sid})")
+}
+
+/// Serve change sets on the named pipe `endpoint`, one per connection, until
+/// the first one when `once` is set. Connections from any process but
+/// `client_pid` are refused, and only its user may open the pipe.
+pub fn serve(
+    endpoint: &str,
+    store: &mut dyn EnvStore,
+    allowlist: &Allowlist,
+    client_pid: Option<u32>,
+    once: bool,
+) -> eyre::Result<()> {
+    let sddl = pipe_sddl(&user_sid(client_pid)?);
+    let mut first = true;
+    loop {
+        let pipe = create_pipe(endpoint, &sddl, first)?;
+        first = false;
+        if let Err(error) = unsafe { ConnectNamedPipe(pipe, None) }
+            && error.code() != ERROR_PIPE_CONNECTED.to_hresult()
+        {
+            let _ = unsafe { CloseHandle(pipe) };
+            return Err(error).wrap_err("Failed to wait for a connection to the broker");
+        }
+        // The file closes the pipe when dropped
+        let pipe = unsafe { File::from_raw_handle(pipe.0) };
+        if let Some(client_pid) = client_pid {
+            let mut pid = 0;
+            let found =
+                unsafe { GetNamedPipeClientProcessId(HANDLE(pipe.as_raw_handle()), &mut pid) };
+            if found.is_err() || pid != client_pid {
+                warn!(
+                    "Refusing a connection from process {pid}, only {client_pid} may send changes"
+                );
+                continue;
+            }
+        }
+        let reader = BufReader::new(pipe.try_clone()?);
+        match serve_connection(store, allowlist, reader, &pipe) {
+            Ok(response) => info!("Answered a change set: {response:?}"),
+            Err(error) => warn!("Failed to answer a change set: {error:#}"),
+        }
+        // Wait for the response to be read before closing the pipe
+        let _ = pipe.sync_all();
+        if once {
+            return Ok(());
+        }
+    }
+}
+
+fn create_pipe(endpoint: &str, sddl: &str, first: bool) -> eyre::Result<HANDLE> {
+    let sddl = to_wide_null(sddl);
+    let name = to_wide_null(&pipe_path(endpoint));
+    let mut descriptor = PSECURITY_DESCRIPTOR::default();
+    unsafe {
+        ConvertStringSecurityDescriptorToSecurityDescriptorW(
+            PCWSTR(sddl.as_ptr()),
+            SDDL_REVISION_1,
+            &mut descriptor,
+            None,
+        )
+        .wrap_err("Failed to build the security descriptor for the broker pipe")?;
+        let attributes = SECURITY_ATTRIBUTES {
+            nLength: size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: descriptor.0,
+            bInheritHandle: false.into(),
+        };
+        // Refuse to start if someone else already owns the name
+        let mut open_mode = PIPE_ACCESS_DUPLEX;
+        if first {
+            open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+        }
+        let pipe = CreateNamedPipeW(
+            PCWSTR(name.as_ptr()),
+            open_mode,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            64 * 1024,
+            64 * 1024,
+            0,
+            Some(&attributes),
+        );
+        let _ = LocalFree(Some(HLOCAL(descriptor.0)));
+        if pipe.is_invalid() {
+            return Err(windows::core::Error::from_win32())
+                .wrap_err_with(|| format!("Failed to create the pipe {}", pipe_path(endpoint)));
+        }
+        Ok(pipe)
+    }
+}
+
+/// The string form of the SID of the user running `pid`, or this process.
+fn user_sid(pid: Option<u32>) -> eyre::Result<String> {
+    unsafe {
+        let process = match pid {
+            Some(pid) => OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid)
+                .wrap_err_with(|| format!("Failed to open process {pid}"))?,
+            None => GetCurrentProcess(),
+        };
+        let mut token = HANDLE::default();
+        let opened = OpenProcessToken(process, TOKEN_QUERY, &mut token);
+        if pid.is_some() {
+            let _ = CloseHandle(process);
+        }
+        opened.wrap_err("Failed to open the process token")?;
+
+        let mut length = 0;
+        let _ = GetTokenInformation(token, TokenUser, None, 0, &mut length);
+        // u64 keeps the buffer aligned for TOKEN_USER
+        let mut buffer = vec![0u64; (length as usize).div_ceil(size_of::<u64>())];
+        let read = GetTokenInformation(
+            token,
+            TokenUser,
+            Some(buffer.as_mut_ptr().cast()),
+            length,
+            &mut length,
+        );
+        let _ = CloseHandle(token);
+        read.wrap_err("Failed to read the user from the process token")?;
+
+        let user = &*buffer.as_ptr().cast::<TOKEN_USER>();
+        let mut sid = PWSTR::null();
+        ConvertSidToStringSidW(user.User.Sid, &mut sid)
+            .wrap_err("Failed to convert the user SID to a string")?;
+        let text = sid.to_string();
+        let _ = LocalFree(Some(HLOCAL(sid.0.cast())));
+        Ok(text?)
+    }
+}
+
+/// Start an elevated broker on `endpoint` that applies one change set within
+/// `allowlist` from this process, without waiting for it.
+pub fn launch_broker(endpoint: &str, allowlist: &Allowlist) -> eyre::Result<()> {
+    info!("Starting an elevated broker to apply the changes");
+    let command_line = broker_command_line(endpoint, std::process::id(), allowlist);
+    let process = start_elevated(&command_line)?;
+    let _ = unsafe { CloseHandle(process) };
+    Ok(())
+}
@@ -1,20 +1,35 @@
-use eyre::eyre;
+use crate::relaunch::RelayFiles;
+use crate::relaunch::relaunch_command_line;
+use crate::relaunch::relay_dir;
+use eyre::Context;
 use std::env;
 use std::mem::size_of;
+use std::os::windows::io::IntoRawHandle;
+use std::path::Path;
 use tracing::debug;
 use tracing::info;
 use tracing::warn;
+use windows::Win32::Foundation::CloseHandle;
 use windows::Win32::Foundation::GetLastError;
 use windows::Win32::Foundation::HANDLE;
-use windows::Win32::Foundation::HINSTANCE;
+use windows::Win32::Foundation::WAIT_FAILED;
 use windows::Win32::Security::GetTokenInformation;
 use windows::Win32::Security::TOKEN_ELEVATION;
 use windows::Win32::Security::TOKEN_QUERY;
 use windows::Win32::Security::TokenElevation;
+use windows::Win32::System::Console::STD_ERROR_HANDLE;
+use windows::Win32::System::Console::STD_OUTPUT_HANDLE;
+use windows::Win32::System::Console::SetStdHandle;
 use windows::Win32::System::Threading::GetCurrentProcess;
+use windows::Win32::System::Threading::GetExitCodeProcess;
+use windows::Win32::System::Threading::INFINITE;
 use windows::Win32::System::Threading::OpenProcessToken;
-use windows::Win32::UI::Shell::ShellExecuteW;
-use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+use windows::Win32::System::Threading::WaitForSingleObject;
+use windows::Win32::UI::Shell::SEE_MASK_NOASYNC;
+use windows::Win32::UI::Shell::SEE_MASK_NOCLOSEPROCESS;
+use windows::Win32::UI::Shell::SHELLEXECUTEINFOW;
+use windows::Win32::UI::Shell::ShellExecuteExW;
+use windows::Win32::UI::WindowsAndMessaging::SW_HIDE;
 use windows::core::PCWSTR;
 
 use crate::win_strings::to_wide_null;
@@ -50,57 +65,100 @@ pub fn is_elevated() -> bool {
     }
 }
 
-/// Relaunches the current executable with administrative privileges.
-pub fn relaunch_as_admin() -> Result<HINSTANCE, windows::core::Error> {
-    // Get the path to the current executable
-    let exe_path = env::current_exe().expect("Failed to get current executable path");
-    let exe_path_str = exe_path.to_string_lossy();
+/// Relaunches the current executable with administrative privileges and the
+/// same arguments, waits for it, relays its output, and returns its exit code.
+pub fn relaunch_as_admin() -> eyre::Result<u32> {
+    let args: Vec<String> = env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let relay_dir = relay_dir()?;
+    let working_dir = env::current_dir().wrap_err("Failed to get current directory")?;
+    let params = relaunch_command_line(&args, &relay_dir, &working_dir);
+
+    let process = start_elevated(&params)?;
+    let exit_code = unsafe {
+        let waited = WaitForSingleObject(process, INFINITE);
+        let mut exit_code = 1;
+        let got_exit_code = GetExitCodeProcess(process, &mut exit_code);
+        let _ = CloseHandle(process);
+        if waited == WAIT_FAILED {
+            return Err(windows::core::Error::from_win32())
+                .wrap_err("Failed to wait for the elevated process");
+        }
+        got_exit_code.wrap_err("Failed to get the exit code of the elevated process")?;
+        exit_code
+    };
+
+    let relayed = RelayFiles::in_dir(&relay_dir).relay();
+    let _ = std::fs::remove_dir_all(&relay_dir);
+    relayed?;
+    Ok(exit_code)
+}
+
+/// Start the current executable with administrative privileges and the
+/// already quoted `params`, without waiting for it. The caller closes the
+/// returned process handle.
+pub fn start_elevated(params: &str) -> eyre::Result<HANDLE> {
+    let exe_path = env::current_exe().wrap_err("Failed to get current executable path")?;
+    debug!(
+        "Starting {} {} as administrator",
+        exe_path.display(),
+        params
+    );
 
     // Convert strings to wide strings
     let operation = to_wide_null("runas");
-    let file = to_wide_null(&exe_path_str);
-    let params = to_wide_null(""); // No parameters
-    let dir = to_wide_null(""); // Current directory
+    let file = to_wide_null(&exe_path.to_string_lossy());
+    let params = to_wide_null(params);
 
-    // Call ShellExecuteW
-    let result = unsafe {
-        ShellExecuteW(
-            None,
-            PCWSTR(operation.as_ptr()),
-            PCWSTR(file.as_ptr()),
-            PCWSTR(params.as_ptr()),
-            PCWSTR(dir.as_ptr()),
-            SW_SHOWNORMAL,
-        )
+    // Output is relayed or answered over IPC, so the elevated console never needs to be seen
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
+        fMask: SEE_MASK_NOCLOSEPROCESS | SEE_MASK_NOASYNC,
+        lpVerb: PCWSTR(operation.as_ptr()),
+        lpFile: PCWSTR(file.as_ptr()),
+        lpParameters: PCWSTR(params.as_ptr()),
+        nShow: SW_HIDE.0,
+        ..Default::default()
     };
+    unsafe {
+        ShellExecuteExW(&mut info).wrap_err("Failed to start as administrator")?;
+    }
+    if info.hProcess.is_invalid() {
+        eyre::bail!("Started as administrator, but got no process handle");
+    }
+    Ok(info.hProcess)
+}
 
-    // Check if the operation was successful
-    if result.0 as usize > 32 {
-        Ok(result)
-    } else {
-        Err(windows::core::Error::from_win32())
+/// In a process started by [`relaunch_as_admin`], send stdout and stderr to
+/// the files the original process relays.
+pub fn redirect_output(relay_dir: &Path) -> eyre::Result<()> {
+    let files = RelayFiles::in_dir(relay_dir);
+    for (path, handle) in [
+        (&files.stdout, STD_OUTPUT_HANDLE),
+        (&files.stderr, STD_ERROR_HANDLE),
+    ] {
+        let file = std::fs::File::create(path)
+            .wrap_err_with(|| format!("Failed to create {}", path.display()))?;
+        // The handle stays open for the rest of the process, std looks it up on every write
+        unsafe {
+            SetStdHandle(handle, HANDLE(file.into_raw_handle()))
+                .wrap_err_with(|| format!("Failed to redirect output to {}", path.display()))?;
+        }
     }
+    Ok(())
 }
 
 pub fn ensure_elevated() -> eyre::Result<()> {
     if !is_elevated() {
         warn!("Program needs to be ran with elevated privileges.");
         info!("Relaunching as administrator");
-        match relaunch_as_admin() {
-            Ok(module) if module.0 as usize > 32 => {
-                info!("Successfully relaunched as administrator.");
-                std::process::exit(0); // Exit the current process
-            }
-            Ok(module) => {
-                return Err(eyre!(
-                    "Failed to relaunch as administrator. Error code: {}",
-                    module.0 as usize
-                ));
-            }
-            Err(e) => {
-                return Err(eyre!("Failed to relaunch as administrator: {}", e));
-            }
+        let exit_code = relaunch_as_admin()?;
+        if exit_code != 0 {
+            debug!("The elevated process exited with {exit_code}");
         }
+        std::process::exit(exit_code as i32);
     }
     Ok(())
 }
@@ -1,29 +1,91 @@
-use std::ffi::OsStr;
-use std::iter::once;
-use std::os::windows::ffi::OsStrExt;
-use windows::core::PCWSTR;
-use windows::core::w;
-
-pub const MACHINE_ENV_SUB_KEY: PCWSTR =
-    w!("SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment");
-
-/// Converts a Rust `&str` to a null-terminated wide string (`Vec<u16>`).
-pub fn to_wide_null(s: &str) -> Vec<u16> {
-    OsStr::new(s)
-        .encode_wide()
-        .chain(once(0)) // Append null terminator
-        .collect()
+#[cfg(windows)]
+pub use registry_keys::*;
+
+#[cfg(windows)]
+mod registry_keys {
+    use std::ffi::OsStr;
+    use std::iter::once;
+    use std::os::windows::ffi::OsStrExt;
+    use windows::Win32::System::Registry::HKEY;
+    use windows::Win32::System::Registry::HKEY_CURRENT_USER;
+    use windows::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+    use windows::core::PCWSTR;
+    use windows::core::w;
+
+    use crate::env_reader::EnvScope;
+
+    pub const MACHINE_ENV_SUB_KEY: PCWSTR =
+        w!("SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment");
+
+    pub const USER_ENV_SUB_KEY: PCWSTR = w!("Environment");
+
+    /// The registry root and sub-key holding the variables for `scope`.
+    pub fn env_registry_key(scope: EnvScope) -> (HKEY, PCWSTR) {
+        match scope {
+            EnvScope::Machine => (HKEY_LOCAL_MACHINE, MACHINE_ENV_SUB_KEY),
+            EnvScope::User => (HKEY_CURRENT_USER, USER_ENV_SUB_KEY),
+        }
+    }
+
+    /// Converts a Rust `&str` to a null-terminated wide string (`Vec<u16>`).
+    pub fn to_wide_null(s: &str) -> Vec<u16> {
+        OsStr::new(s)
+            .encode_wide()
+            .chain(once(0)) // Append null terminator
+            .collect()
+    }
 }
 
-/// Convert pairs of bytes to UTF-16, then to a Rust string. Stop at the first null terminator.
-pub fn utf16_from_bytes(bytes: &[u8]) -> String {
-    let wide_data: Vec<u16> = bytes
+/// Convert pairs of little-endian bytes to UTF-16 code units. A trailing odd byte is dropped.
+pub fn utf16_units_from_bytes(bytes: &[u8]) -> Vec<u16> {
+    bytes
         .chunks_exact(2)
         .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
-        .collect();
-    let str_end = wide_data
-        .iter()
-        .position(|&c| c == 0)
-        .unwrap_or(wide_data.len());
-    String::from_utf16_lossy(&wide_data[..str_end])
+        .collect()
+}
+
+/// Encode a string as null-terminated little-endian UTF-16 bytes, as the registry stores it.
+pub fn utf16_bytes_null(s: &str) -> Vec<u8> {
+    s.encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Quote a single argument so `CommandLineToArgvW` and the MSVC runtime split
+/// it back out unchanged.
+///
+/// Backslashes are only special right before a `"`, so they are doubled there
+/// and left alone everywhere else.
+pub fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\x0b', '"']) {
+        return arg.to_string();
+    }
+    force_quote_windows_arg(arg)
+}
+
+/// Like [`quote_windows_arg`], but always wrapped in quotes, e.g. to keep cmd
+/// metacharacters such as `&` inert.
+pub fn force_quote_windows_arg(arg: &str) -> String {
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            c => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
 }
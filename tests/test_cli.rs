@@ -0,0 +1,814 @@
+use env_edit::data_dir::DATA_DIR_ENV_VAR;
+use eyre::Result;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Output;
+
+/// A JSON store file and data directory in the temp dir, removed when dropped.
+struct TempStore {
+    path: PathBuf,
+    data_dir: PathBuf,
+}
+
+impl TempStore {
+    fn new(name: &str) -> Self {
+        let base =
+            std::env::temp_dir().join(format!("env-edit-test-cli-{name}-{}", std::process::id()));
+        let path = base.with_extension("json");
+        let data_dir = base.with_extension("data");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_dir_all(&data_dir);
+        Self { path, data_dir }
+    }
+
+    fn run(&self, args: &[&str]) -> Result<Output> {
+        let output = Command::new(env!("CARGO_BIN_EXE_env-edit"))
+            .env(DATA_DIR_ENV_VAR, &self.data_dir)
+            .arg("--store")
+            .arg(&self.path)
+            .args(args)
+            .output()?;
+        Ok(output)
+    }
+
+    fn run_ok(&self, args: &[&str]) -> Result<String> {
+        let output = self.run(args)?;
+        assert!(
+            output.status.success(),
+            "env-edit {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+impl Drop for TempStore {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_dir_all(&self.data_dir);
+    }
+}
+
+#[test]
+fn test_cli_set_show_list() -> Result<()> {
+    let store = TempStore::new("set-show-list");
+    store.run_ok(&["set", "--key", "ENV_EDIT_TEST", "--value", "42"])?;
+
+    let shown = store.run_ok(&["show", "--key", "env_edit_test"])?;
+    assert_eq!(shown.trim(), "env_edit_test = 42");
+
+    let listed = store.run_ok(&["list"])?;
+    let vars: serde_json::Value = serde_json::from_str(&listed)?;
+    assert_eq!(vars[0]["key"], "ENV_EDIT_TEST");
+    assert_eq!(vars[0]["value"], "42");
+    assert_eq!(vars[0]["scope"], "machine");
+    Ok(())
+}
+
+#[test]
+fn test_cli_user_scope() -> Result<()> {
+    let store = TempStore::new("user-scope");
+    store.run_ok(&["set", "--scope", "user", "--key", "EDITOR", "--value", "hx"])?;
+
+    let machine = store.run_ok(&["show", "--key", "EDITOR"])?;
+    assert_eq!(machine.trim(), "EDITOR is not set.");
+
+    let all = store.run_ok(&["show", "--scope", "all", "--key", "EDITOR"])?;
+    assert_eq!(
+        all.lines().collect::<Vec<_>>(),
+        ["[machine] EDITOR is not set.", "[user] EDITOR = hx"]
+    );
+
+    let output = store.run(&["set", "--scope", "all", "--key", "EDITOR", "--value", "vi"])?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_cli_set_preserves_kind() -> Result<()> {
+    let store = TempStore::new("preserve-kind");
+    let kind_of = |key: &str| -> Result<String> {
+        let listed = store.run_ok(&["list"])?;
+        let vars: Vec<serde_json::Value> = serde_json::from_str(&listed)?;
+        let var = vars.iter().find(|var| var["key"] == key).unwrap();
+        Ok(var["kind"].as_str().unwrap().to_string())
+    };
+
+    store.run_ok(&[
+        "set",
+        "--key",
+        "Path",
+        "--value",
+        "%SystemRoot%",
+        "--type",
+        "expand",
+    ])?;
+    store.run_ok(&["set", "--key", "Path", "--value", "C:\\Tools"])?;
+    assert_eq!(kind_of("Path")?, "REG_EXPAND_SZ");
+
+    store.run_ok(&[
+        "set",
+        "--key",
+        "Path",
+        "--value",
+        "C:\\Tools",
+        "--type",
+        "sz",
+    ])?;
+    assert_eq!(kind_of("Path")?, "REG_SZ");
+
+    store.run_ok(&[
+        "set",
+        "--key",
+        "HOME_BIN",
+        "--value",
+        "%USERPROFILE%\\bin",
+        "--type",
+        "auto",
+    ])?;
+    assert_eq!(kind_of("HOME_BIN")?, "REG_EXPAND_SZ");
+
+    store.run_ok(&[
+        "set", "--key", "PROMPT", "--value", "100%", "--type", "auto",
+    ])?;
+    assert_eq!(kind_of("PROMPT")?, "REG_SZ");
+    Ok(())
+}
+
+#[test]
+fn test_cli_unset_and_rename() -> Result<()> {
+    let store = TempStore::new("unset-rename");
+    store.run_ok(&[
+        "set", "--key", "OLD_NAME", "--value", "%TEMP%", "--type", "expand",
+    ])?;
+
+    store.run_ok(&["rename", "--from", "old_name", "--to", "NEW_NAME"])?;
+    assert_eq!(
+        store.run_ok(&["show", "--key", "OLD_NAME"])?.trim(),
+        "OLD_NAME is not set."
+    );
+    let listed: Vec<serde_json::Value> = serde_json::from_str(&store.run_ok(&["list"])?)?;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["key"], "NEW_NAME");
+    assert_eq!(listed[0]["kind"], "REG_EXPAND_SZ");
+
+    // Case-only renames must actually change the stored spelling
+    store.run_ok(&["rename", "--from", "NEW_NAME", "--to", "New_Name"])?;
+    let listed: Vec<serde_json::Value> = serde_json::from_str(&store.run_ok(&["list"])?)?;
+    assert_eq!(listed[0]["key"], "New_Name");
+
+    store.run_ok(&["delete", "--key", "new_name"])?;
+    assert_eq!(store.run_ok(&["list"])?.trim(), "[]");
+
+    let output = store.run(&["rename", "--from", "MISSING", "--to", "OTHER"])?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_cli_expand() -> Result<()> {
+    let store = TempStore::new("expand");
+    store.run_ok(&["set", "--key", "ROOT", "--value", "C:\\Apps"])?;
+    store.run_ok(&[
+        "set", "--scope", "user", "--key", "ROOT", "--value", "D:\\Apps",
+    ])?;
+    store.run_ok(&[
+        "set",
+        "--scope",
+        "user",
+        "--key",
+        "TOOLS",
+        "--value",
+        "%ROOT%\\tools",
+    ])?;
+
+    let merged = store.run_ok(&["expand", "--scope", "all", "--key", "tools"])?;
+    assert_eq!(merged.trim(), "D:\\Apps\\tools");
+
+    let traced = store.run_ok(&["expand", "--text", "%root%;%NOPE%", "--trace"])?;
+    assert_eq!(
+        traced.lines().collect::<Vec<_>>(),
+        [
+            "C:\\Apps;%NOPE%",
+            "  %root% -> \"C:\\\\Apps\" from machine ROOT"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_refs() -> Result<()> {
+    let store = TempStore::new("refs");
+    store.run_ok(&["set", "--key", "Path", "--value", "%NEWPATH%;%GONE%"])?;
+    store.run_ok(&["set", "--key", "NEWPATH", "--value", "%Path%"])?;
+
+    let refs = store.run_ok(&["refs", "newpath"])?;
+    assert_eq!(
+        refs.lines().collect::<Vec<_>>(),
+        [
+            "NEWPATH (machine) = %Path%",
+            "references:",
+            "  Path (machine)",
+            "referenced by:",
+            "  Path",
+        ]
+    );
+
+    let report = store.run_ok(&["refs"])?;
+    assert_eq!(
+        report.lines().collect::<Vec<_>>(),
+        [
+            "cycle: NEWPATH -> Path -> NEWPATH",
+            "undefined: Path references %GONE%",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_snapshot_restore() -> Result<()> {
+    let store = TempStore::new("snapshot");
+    let snapshot = std::env::temp_dir().join(format!(
+        "env-edit-test-cli-snapshot-{}.snapshot.json",
+        std::process::id()
+    ));
+    let snapshot_arg = snapshot.to_str().unwrap();
+    store.run_ok(&["set", "--key", "Path", "--value", "C:\\Windows"])?;
+    store.run_ok(&["set", "--scope", "user", "--key", "EDITOR", "--value", "hx"])?;
+    store.run_ok(&["snapshot", "--out", snapshot_arg])?;
+
+    store.run_ok(&["set", "--key", "Path", "--value", "C:\\oops"])?;
+    store.run_ok(&["unset", "--scope", "user", "--key", "EDITOR"])?;
+
+    let preview = store.run_ok(&["restore", snapshot_arg, "--dry-run"])?;
+    assert_eq!(
+        preview.lines().collect::<Vec<_>>(),
+        [
+            "~ machine Path = C:\\Windows (REG_SZ)",
+            "    was C:\\oops (REG_SZ)",
+            "+ user EDITOR = hx (REG_SZ)",
+        ]
+    );
+    assert_eq!(
+        store.run_ok(&["show", "--key", "Path"])?.trim(),
+        "Path = C:\\oops"
+    );
+
+    store.run_ok(&["restore", snapshot_arg])?;
+    assert_eq!(
+        store
+            .run_ok(&["show", "--scope", "all", "--key", "EDITOR"])?
+            .lines()
+            .collect::<Vec<_>>(),
+        ["[machine] EDITOR is not set.", "[user] EDITOR = hx"]
+    );
+    assert_eq!(
+        store.run_ok(&["restore", snapshot_arg])?.trim(),
+        "No changes"
+    );
+    let _ = std::fs::remove_file(&snapshot);
+    Ok(())
+}
+
+#[test]
+fn test_cli_backups_restore_last() -> Result<()> {
+    let store = TempStore::new("backups");
+    store.run_ok(&["set", "--key", "Path", "--value", "C:\\Windows"])?;
+    store.run_ok(&["set", "--key", "Path", "--value", "C:\\oops"])?;
+    store.run_ok(&["rename", "--from", "Path", "--to", "PATH_OLD"])?;
+
+    let backups = store.run_ok(&["backups", "list"])?;
+    let lines: Vec<&str> = backups.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].ends_with("  set machine Path  [machine Path]"));
+    assert!(
+        lines[2].ends_with("  rename machine Path to PATH_OLD  [machine PATH_OLD, machine Path]")
+    );
+
+    // Undo the rename, then the bad set
+    store.run_ok(&["restore", "--last"])?;
+    assert_eq!(
+        store.run_ok(&["show", "--key", "Path"])?.trim(),
+        "Path = C:\\oops"
+    );
+    let second = lines[1].split_whitespace().next().unwrap();
+    let preview = store.run_ok(&["restore", "--id", second, "--dry-run"])?;
+    assert_eq!(
+        preview.lines().collect::<Vec<_>>(),
+        [
+            "~ machine Path = C:\\Windows (REG_SZ)",
+            "    was C:\\oops (REG_SZ)"
+        ]
+    );
+    store.run_ok(&["restore", "--id", second])?;
+    assert_eq!(
+        store.run_ok(&["show", "--key", "Path"])?.trim(),
+        "Path = C:\\Windows"
+    );
+    assert_eq!(
+        store.run_ok(&["show", "--key", "PATH_OLD"])?.trim(),
+        "PATH_OLD is not set."
+    );
+
+    let output = store.run(&["restore", "--id", "nope"])?;
+    assert!(!output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_cli_restore_ignores_other_stores() -> Result<()> {
+    let first = TempStore::new("backups-first");
+    let mut second = TempStore::new("backups-second");
+    // Both stores share one data directory, and so one backups folder
+    second.data_dir = first.data_dir.clone();
+    first.run_ok(&["set", "--key", "ONLY_FIRST", "--value", "1"])?;
+    second.run_ok(&["set", "--key", "SECOND", "--value", "1"])?;
+    second.run_ok(&["set", "--key", "SECOND", "--value", "2"])?;
+
+    assert_eq!(first.run_ok(&["backups", "list"])?.lines().count(), 1);
+    assert_eq!(second.run_ok(&["backups", "list"])?.lines().count(), 2);
+
+    first.run_ok(&["restore", "--last"])?;
+    assert_eq!(
+        first.run_ok(&["show", "--key", "ONLY_FIRST"])?.trim(),
+        "ONLY_FIRST is not set."
+    );
+    assert_eq!(
+        second.run_ok(&["show", "--key", "SECOND"])?.trim(),
+        "SECOND = 2"
+    );
+
+    // A backup of the first store can not be restored to the second
+    let id = first.run_ok(&["backups", "list"])?;
+    let id = id.split_whitespace().next().unwrap();
+    assert!(!second.run(&["restore", "--id", id])?.status.success());
+    assert_eq!(
+        second.run_ok(&["show", "--key", "ONLY_FIRST"])?.trim(),
+        "ONLY_FIRST is not set."
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_diff() -> Result<()> {
+    let store = TempStore::new("diff");
+    let snapshot = store.data_dir.join("before.json");
+    let snapshot_arg = snapshot.to_str().unwrap();
+    store.run_ok(&["set", "--key", "Path", "--value", "C:\\Windows;C:\\Old"])?;
+    store.run_ok(&[
+        "set", "--scope", "user", "--key", "Path", "--value", "C:\\Mine",
+    ])?;
+    store.run_ok(&["snapshot", "--out", snapshot_arg])?;
+    store.run_ok(&["set", "--key", "Path", "--value", "C:\\Windows;C:\\New"])?;
+
+    let human = store.run_ok(&["diff", snapshot_arg])?;
+    assert_eq!(
+        human.lines().collect::<Vec<_>>(),
+        ["~ machine Path", "    - C:\\Old", "    + C:\\New"]
+    );
+
+    let json: serde_json::Value =
+        serde_json::from_str(&store.run_ok(&["diff", snapshot_arg, "--format", "json"])?)?;
+    assert_eq!(json["changes"][0]["change"], "modified");
+    assert_eq!(json["changes"][0]["entries"][1]["change"], "removed");
+
+    let unified = store.run_ok(&["diff", "--machine-vs-user", "--format", "unified"])?;
+    assert_eq!(
+        unified.lines().collect::<Vec<_>>(),
+        [
+            "--- machine/machine/Path\tREG_SZ",
+            "+++ user/user/Path\tREG_SZ",
+            "@@ -1,2 +1 @@",
+            "-C:\\Windows",
+            "-C:\\New",
+            "+C:\\Mine",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_reg_export_import() -> Result<()> {
+    let store = TempStore::new("reg");
+    let reg_file = store.data_dir.join("env.reg");
+    let reg_arg = reg_file.to_str().unwrap();
+    store.run_ok(&[
+        "set",
+        "--key",
+        "TOOLS",
+        "--value",
+        "%ROOT%\\tools",
+        "--type",
+        "expand",
+    ])?;
+    store.run_ok(&["reg", "export", "--out", reg_arg])?;
+    store.run_ok(&["unset", "--key", "TOOLS"])?;
+
+    let preview = store.run_ok(&["reg", "import", reg_arg, "--dry-run"])?;
+    assert_eq!(
+        preview.trim(),
+        "+ machine TOOLS = %ROOT%\\tools (REG_EXPAND_SZ)"
+    );
+    assert_eq!(store.run_ok(&["list"])?.trim(), "[]");
+
+    store.run_ok(&["reg", "import", reg_arg])?;
+    let listed: Vec<serde_json::Value> = serde_json::from_str(&store.run_ok(&["list"])?)?;
+    assert_eq!(listed[0]["value"], "%ROOT%\\tools");
+    assert_eq!(listed[0]["kind"], "REG_EXPAND_SZ");
+    Ok(())
+}
+
+#[test]
+fn test_cli_dotenv_import_export() -> Result<()> {
+    let store = TempStore::new("dotenv");
+    std::fs::create_dir_all(&store.data_dir)?;
+    let dotenv = store.data_dir.join(".env");
+    std::fs::write(
+        &dotenv,
+        "export APP_HOME=D:\\app\nAPP_LOGS=${APP_HOME}\\logs\n",
+    )?;
+    let dotenv_arg = dotenv.to_str().unwrap();
+
+    let preview = store.run_ok(&[
+        "import",
+        "--from",
+        dotenv_arg,
+        "--scope",
+        "user",
+        "--dry-run",
+    ])?;
+    assert_eq!(
+        preview.lines().collect::<Vec<_>>(),
+        [
+            "+ user APP_HOME = D:\\app (REG_SZ)",
+            "+ user APP_LOGS = D:\\app\\logs (REG_SZ)",
+        ]
+    );
+    assert_eq!(store.run_ok(&["list", "--scope", "user"])?.trim(), "[]");
+
+    store.run_ok(&["import", "--from", dotenv_arg, "--scope", "user"])?;
+    let exported = store.run_ok(&["export", "--format", "dotenv", "--scope", "user"])?;
+    assert_eq!(
+        exported.lines().collect::<Vec<_>>(),
+        [
+            "# Generated by env-edit",
+            "APP_HOME=\"D:\\\\app\"",
+            "APP_LOGS=\"D:\\\\app\\\\logs\"",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_path() -> Result<()> {
+    let store = TempStore::new("path");
+    store.run_ok(&[
+        "set",
+        "--key",
+        "Path",
+        "--value",
+        "%SystemRoot%\\system32;C:\\Tools",
+        "--type",
+        "expand",
+    ])?;
+    store.run_ok(&["set", "--key", "SystemRoot", "--value", "C:\\Windows"])?;
+    store.run_ok(&["path", "add", "C:\\Git\\cmd", "--before", "c:\\tools\\"])?;
+    store.run_ok(&["path", "add", "C:\\First", "--prepend"])?;
+    let output = store.run(&["path", "add", "c:/windows/system32/"])?;
+    assert!(!output.status.success(), "already present via expansion");
+
+    store.run_ok(&["path", "move", "C:\\Tools", "--index", "0"])?;
+    store.run_ok(&["path", "remove", "c:\\git\\CMD"])?;
+    assert_eq!(
+        store.run_ok(&["path", "list"])?.lines().collect::<Vec<_>>(),
+        [
+            "  0  C:\\Tools",
+            "  1  C:\\First",
+            "  2  %SystemRoot%\\system32",
+        ]
+    );
+    let listed: Vec<serde_json::Value> = serde_json::from_str(&store.run_ok(&["list"])?)?;
+    let path = listed.iter().find(|var| var["key"] == "Path").unwrap();
+    assert_eq!(path["kind"], "REG_EXPAND_SZ");
+
+    assert!(!store.run(&["path", "move", "C:\\Tools"])?.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_cli_path_lint() -> Result<()> {
+    let store = TempStore::new("path-lint");
+    let dir = std::env::temp_dir().display().to_string();
+    let other = std::env::current_dir()?.display().to_string();
+    let missing = format!("{dir}/env-edit-missing-nowhere");
+    let path = format!("{dir};;{dir};\"{other}\";{missing};relative");
+    store.run_ok(&["set", "--key", "Path", "--value", &path])?;
+
+    let output = store.run(&["path", "lint"])?;
+    assert!(!output.status.success(), "the quoted entry is an error");
+    let report = String::from_utf8(output.stdout)?;
+    assert_eq!(report.lines().count(), 5, "{report}");
+    assert!(report.contains("info: [1] : empty entry"), "{report}");
+    assert!(report.contains("[2]") && report.contains("duplicate of index 0"));
+    assert!(report.contains("error: [3]"), "{report}");
+
+    let dry_run = store.run_ok(&["path", "lint", "--fix", "--dry-run"])?;
+    assert!(
+        dry_run.contains(&format!("= {dir};{other};{missing};relative (REG_SZ)")),
+        "{dry_run}"
+    );
+    store.run_ok(&["path", "lint", "--fix", "--drop-missing"])?;
+    assert_eq!(
+        store.run_ok(&["path", "list"])?.lines().collect::<Vec<_>>(),
+        [
+            format!("  0  {dir}"),
+            format!("  1  {other}"),
+            "  2  relative".to_string()
+        ]
+    );
+    let report = store.run_ok(&["path", "lint"])?;
+    assert!(
+        report.contains("relative to the current directory"),
+        "{report}"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_which_and_shadows() -> Result<()> {
+    let store = TempStore::new("which");
+    let root = std::env::temp_dir().join(format!("env-edit-test-cli-which-{}", std::process::id()));
+    for dir in ["first", "second"] {
+        std::fs::create_dir_all(root.join(dir))?;
+        std::fs::write(root.join(dir).join("tool.exe"), "")?;
+    }
+    let first = root.join("first").display().to_string();
+    let second = root.join("second").display().to_string();
+    store.run_ok(&["set", "--key", "Path", "--value", &first])?;
+    store.run_ok(&[
+        "--scope", "user", "set", "--key", "Path", "--value", &second,
+    ])?;
+
+    let winner = root.join("first").join("tool.exe").display().to_string();
+    let loser = root.join("second").join("tool.exe").display().to_string();
+    assert_eq!(
+        store.run_ok(&["which", "tool"])?,
+        format!("{winner} (machine Path[0])\n")
+    );
+    assert_eq!(
+        store.run_ok(&["which", "TOOL", "--all"])?,
+        format!("* {winner} (machine Path[0])\n  {loser} (user Path[0])\n")
+    );
+    assert!(!store.run(&["which", "missing"])?.status.success());
+    assert_eq!(
+        store.run_ok(&["path", "shadows"])?,
+        format!("tool\n  * {winner} (machine Path[0])\n    {loser} (user Path[0])\n")
+    );
+    assert_eq!(store.run_ok(&["--scope", "user", "path", "shadows"])?, "");
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_cli_path_compact() -> Result<()> {
+    let store = TempStore::new("path-compact");
+    let root =
+        std::env::temp_dir().join(format!("env-edit-test-cli-compact-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("Git"))?;
+    let program_files = root.display().to_string();
+    let git = root.join("Git").display().to_string();
+    store.run_ok(&["set", "--key", "ProgramFiles", "--value", &program_files])?;
+    let path = format!("{git};{git};{program_files}/Gone");
+    store.run_ok(&["set", "--key", "Path", "--value", &path])?;
+
+    let kept = store.run_ok(&["path", "compact", "--dry-run"])?;
+    assert!(!kept.contains("does not exist"), "{kept}");
+    let dry_run = store.run_ok(&["path", "compact", "--drop-missing", "--dry-run"])?;
+    assert!(dry_run.contains("/Gone: does not exist"), "{dry_run}");
+    let raw_before = path.len();
+    let compacted = format!("%ProgramFiles%{}", &git[program_files.len()..]);
+    assert!(
+        dry_run.contains(&format!("~ {git} -> {compacted}")),
+        "{dry_run}"
+    );
+    assert!(
+        dry_run.contains(&format!(
+            "Raw length {raw_before} -> {}, expanded length {raw_before} -> {}",
+            compacted.len(),
+            git.len()
+        )),
+        "{dry_run}"
+    );
+    assert_eq!(
+        store.run_ok(&["path", "list"])?.lines().count(),
+        3,
+        "dry run"
+    );
+
+    store.run_ok(&["path", "compact", "--drop-missing"])?;
+    assert_eq!(
+        store.run_ok(&["path", "list"])?,
+        format!("  0  {compacted}\n")
+    );
+    let listed: Vec<serde_json::Value> = serde_json::from_str(&store.run_ok(&["list"])?)?;
+    let path = listed.iter().find(|var| var["key"] == "Path").unwrap();
+    assert_eq!(path["kind"], "REG_EXPAND_SZ");
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_cli_path_split_inline() -> Result<()> {
+    let store = TempStore::new("path-split");
+    let path = "C:\\Windows\\system32;C:\\Program Files\\Git\\cmd;C:\\Tools";
+    store.run_ok(&["set", "--key", "Path", "--value", path])?;
+
+    let dry_run = store.run_ok(&["path", "split", "--limit", "40", "--dry-run"])?;
+    assert_eq!(
+        dry_run,
+        "+ machine PATH_EXT1 = C:\\Program Files\\Git\\cmd;C:\\Tools (REG_SZ)\n\
+         ~ machine Path = C:\\Windows\\system32;%PATH_EXT1% (REG_EXPAND_SZ)\n    \
+         was C:\\Windows\\system32;C:\\Program Files\\Git\\cmd;C:\\Tools (REG_SZ)\n"
+    );
+    store.run_ok(&["path", "split", "--limit", "40"])?;
+    assert_eq!(store.run_ok(&["path", "list"])?.lines().count(), 2);
+
+    store.run_ok(&["path", "inline"])?;
+    let listed: Vec<serde_json::Value> = serde_json::from_str(&store.run_ok(&["list"])?)?;
+    assert_eq!(listed.len(), 1, "{listed:?}");
+    assert_eq!(listed[0]["value"], path);
+    Ok(())
+}
+
+#[test]
+fn test_cli_effective() -> Result<()> {
+    let store = TempStore::new("effective");
+    store.run_ok(&[
+        "set",
+        "--key",
+        "Path",
+        "--value",
+        "C:\\Windows;%TOOLS%",
+        "--type",
+        "expand",
+    ])?;
+    store.run_ok(&["set", "--key", "TOOLS", "--value", "C:\\Tools"])?;
+    store.run_ok(&[
+        "--scope",
+        "user",
+        "set",
+        "--key",
+        "Path",
+        "--value",
+        "%USERPROFILE%\\bin",
+        "--type",
+        "expand",
+    ])?;
+
+    let define = ["--define", "USERPROFILE=C:\\Users\\me"];
+    assert_eq!(
+        store.run_ok(&[&["effective", "--key", "PATH"][..], &define].concat())?,
+        "Path=C:\\Windows;C:\\Tools;C:\\Users\\me\\bin\n"
+    );
+    let json: serde_json::Value = serde_json::from_str(
+        &store.run_ok(&[&["effective", "--key", "tools", "--json"][..], &define].concat())?,
+    )?;
+    assert_eq!(
+        json,
+        serde_json::json!([{ "name": "TOOLS", "value": "C:\\Tools", "origin": "machine" }])
+    );
+    assert!(!store.run(&["effective", "--key", "NOPE"])?.status.success());
+    assert!(
+        !store
+            .run(&["effective", "--define", "novalue"])?
+            .status
+            .success()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cli_lists() -> Result<()> {
+    let store = TempStore::new("lists");
+    store.run_ok(&["set", "--key", "PATHEXT", "--value", ".COM;.EXE"])?;
+    store.run_ok(&["path", "--key", "PATHEXT", "add", ".PY"])?;
+    assert!(
+        !store
+            .run(&["path", "--key", "PATHEXT", "add", ".exe"])?
+            .status
+            .success()
+    );
+    assert!(
+        !store
+            .run(&["path", "--key", "pathext", "add", "PY"])?
+            .status
+            .success()
+    );
+    assert_eq!(
+        store.run_ok(&["path", "--key", "PATHEXT", "list"])?,
+        "  0  .COM\n  1  .EXE\n  2  .PY\n"
+    );
+
+    store.run_ok(&[
+        "lists",
+        "declare",
+        "MY_LIST",
+        "--separator",
+        ",",
+        "--entries",
+        "text",
+    ])?;
+    assert!(store.run_ok(&["lists", "show"])?.contains("MY_LIST"));
+    store.run_ok(&["path", "--key", "MY_LIST", "add", "one"])?;
+    store.run_ok(&["path", "--key", "MY_LIST", "add", "two", "--prepend"])?;
+    assert_eq!(
+        store.run_ok(&["path", "--key", "MY_LIST", "list"])?,
+        "  0  two\n  1  one\n"
+    );
+    store.run_ok(&["lists", "forget", "MY_LIST"])?;
+    assert!(!store.run(&["lists", "forget", "MY_LIST"])?.status.success());
+    assert!(
+        !store
+            .run(&["path", "--key", "PATHEXT", "compact"])?
+            .status
+            .success()
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cli_broker() -> Result<()> {
+    let store = TempStore::new("broker");
+    let endpoint = store.path.with_extension("sock");
+    let endpoint = endpoint.to_str().unwrap();
+    let mut broker = Command::new(env!("CARGO_BIN_EXE_env-edit"))
+        .env(DATA_DIR_ENV_VAR, &store.data_dir)
+        .arg("--store")
+        .arg(&store.path)
+        .args([
+            "broker",
+            "--endpoint",
+            endpoint,
+            "--allow",
+            "machine:TOOLS*",
+        ])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    let started = std::time::Instant::now();
+    while !std::path::Path::new(endpoint).exists() {
+        assert!(started.elapsed().as_secs() < 30, "the broker never started");
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+
+    let sent = store.run(&[
+        "--broker",
+        endpoint,
+        "set",
+        "--key",
+        "TOOLS_DIR",
+        "--value",
+        "C:\\Tools",
+    ]);
+    let refused = store.run(&[
+        "--broker", endpoint, "set", "--key", "OTHER", "--value", "1",
+    ]);
+    broker.kill()?;
+    broker.wait()?;
+    let _ = std::fs::remove_file(endpoint);
+
+    let sent = sent?;
+    assert!(
+        sent.status.success(),
+        "{}",
+        String::from_utf8_lossy(&sent.stderr)
+    );
+    let refused = refused?;
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("not on the broker's allowlist"));
+
+    let listed = store.run_ok(&["list"])?;
+    assert!(listed.contains("TOOLS_DIR"));
+    assert!(!listed.contains("OTHER"));
+    Ok(())
+}
+
+#[test]
+fn test_cli_pause() -> Result<()> {
+    let store = TempStore::new("pause");
+    let prompt = "Press Enter to exit";
+    let output = store.run(&["list"])?;
+    assert!(!String::from_utf8_lossy(&output.stderr).contains(prompt));
+    // stdin is closed, so the pause ends straight away
+    let output = store.run(&["list", "--pause"])?;
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(prompt));
+    let output = store.run(&["list", "--no-pause"])?;
+    assert!(!String::from_utf8_lossy(&output.stderr).contains(prompt));
+    let output = store.run(&["--pause", "list", "--no-pause"])?;
+    assert!(!String::from_utf8_lossy(&output.stderr).contains(prompt));
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use env_edit::console::PauseContext;
+
+#[test]
+fn test_should_pause() {
+    let double_clicked = PauseContext {
+        unattended: false,
+        interactive: true,
+        own_console: true,
+    };
+    assert!(double_clicked.should_pause(None));
+    assert!(!double_clicked.should_pause(Some(false)));
+
+    let from_shell = PauseContext {
+        own_console: false,
+        ..double_clicked
+    };
+    assert!(!from_shell.should_pause(None));
+    assert!(from_shell.should_pause(Some(true)));
+
+    let piped = PauseContext {
+        interactive: false,
+        ..double_clicked
+    };
+    assert!(!piped.should_pause(None));
+
+    let relaunched = PauseContext {
+        unattended: true,
+        ..double_clicked
+    };
+    assert!(!relaunched.should_pause(None));
+    assert!(!relaunched.should_pause(Some(true)));
+}
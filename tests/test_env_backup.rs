@@ -0,0 +1,94 @@
+use env_edit::env_backup::BackupStore;
+use env_edit::env_backup::format_timestamp;
+use env_edit::env_change::ChangeSet;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::MemoryEnvStore;
+use eyre::Result;
+
+#[test]
+fn test_backups_rotate_and_restore() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("env-edit-test-backups-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let backups = BackupStore::new(&dir, "memory", 3);
+    let mut store = MemoryEnvStore::new();
+    let scope = EnvScope::User;
+
+    let mut ids = Vec::new();
+    for value in ["one", "two", "three", "four"] {
+        let mut changes = ChangeSet::new();
+        changes.push_set(&store, scope, "COUNT", value, EnvValueKind::String)?;
+        ids.push(backups.record(&changes, &format!("set {value}"))?);
+        changes.apply(&mut store)?;
+    }
+    let listed = backups.list()?;
+    assert_eq!(listed.len(), 3, "the oldest backup is pruned");
+    assert_eq!(
+        listed.iter().map(|b| b.id.as_str()).collect::<Vec<_>>(),
+        ids[1..]
+    );
+    assert!(backups.get(&ids[0]).is_err());
+
+    // The first surviving backup saw COUNT=one before it became two
+    backups
+        .get(&ids[1])?
+        .plan_restore(&store, &EnvScope::ALL)?
+        .apply(&mut store)?;
+    assert_eq!(store.get(scope, "COUNT")?.unwrap().value, "one");
+
+    // Restoring a backup of a variable that did not exist removes it
+    let mut changes = ChangeSet::new();
+    changes.push_set(&store, scope, "NEW", "x", EnvValueKind::String)?;
+    backups.record(&changes, "set NEW")?;
+    changes.apply(&mut store)?;
+    let latest = backups.latest()?.unwrap();
+    assert_eq!(latest.description, "set NEW");
+    latest
+        .plan_restore(&store, &EnvScope::ALL)?
+        .apply(&mut store)?;
+    assert!(store.get(scope, "NEW")?.is_none());
+    assert_eq!(store.get(scope, "COUNT")?.unwrap().value, "one");
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_backups_stay_with_their_store() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!(
+        "env-edit-test-backups-stores-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    let first = BackupStore::new(&dir, "/tmp/a.json", 3);
+    let second = BackupStore::new(&dir, "/tmp/b.json", 3);
+    let store = MemoryEnvStore::new();
+
+    let mut changes = ChangeSet::new();
+    changes.push_set(&store, EnvScope::User, "A", "1", EnvValueKind::String)?;
+    let id = first.record(&changes, "set A")?;
+    for _ in 0..3 {
+        second.record(&changes, "set A")?;
+    }
+
+    assert!(second.get(&id).is_err());
+    assert_eq!(
+        first.latest()?.unwrap().id,
+        id,
+        "not pruned by other stores"
+    );
+    assert_eq!(first.list()?.len(), 1);
+    assert_eq!(second.list()?.len(), 3);
+    assert_ne!(second.latest()?.unwrap().id, id);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_format_timestamp() {
+    assert_eq!(format_timestamp(0), "19700101T000000Z");
+    assert_eq!(format_timestamp(951_782_400), "20000229T000000Z");
+    assert_eq!(format_timestamp(1_730_419_199), "20241031T235959Z");
+}
@@ -0,0 +1,243 @@
+use env_edit::env_broker::Allowlist;
+use env_edit::env_broker::BrokerEnvStore;
+use env_edit::env_broker::BrokerResponse;
+use env_edit::env_broker::RejectReason;
+use env_edit::env_broker::Rejection;
+use env_edit::env_broker::broker_args;
+use env_edit::env_broker::handle_changes;
+use env_edit::env_broker::serve_connection;
+use env_edit::env_change::ChangeSet;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::MemoryEnvStore;
+use eyre::Result;
+
+fn store() -> Result<MemoryEnvStore> {
+    let mut store = MemoryEnvStore::new();
+    store.set(
+        EnvScope::Machine,
+        "Path",
+        "C:\\Windows",
+        EnvValueKind::ExpandString,
+    )?;
+    store.set(EnvScope::Machine, "SECRET", "keep", EnvValueKind::String)?;
+    Ok(store)
+}
+
+fn allow(entries: &[&str]) -> Allowlist {
+    Allowlist::parse(entries).unwrap()
+}
+
+#[test]
+fn test_allowlist() {
+    let allowlist = allow(&["machine:Path", "USER:PATH_EXT*"]);
+    assert!(allowlist.allows(EnvScope::Machine, "PATH"));
+    assert!(!allowlist.allows(EnvScope::User, "Path"));
+    assert!(allowlist.allows(EnvScope::User, "path_ext12"));
+    assert!(allowlist.allows(EnvScope::User, "PATH_EXT"));
+    assert!(!allowlist.allows(EnvScope::Machine, "PATH_EXT1"));
+    assert!(!allowlist.allows(EnvScope::User, "PATHEXT"));
+    assert!(!allowlist.allows(EnvScope::User, "PATH_EX"));
+    assert!(!Allowlist::default().allows(EnvScope::Machine, "Path"));
+
+    assert!(Allowlist::parse(["Path"]).is_err());
+    assert!(Allowlist::parse(["system:Path"]).is_err());
+    assert!(Allowlist::parse(["user:"]).is_err());
+}
+
+#[test]
+fn test_handle_changes() -> Result<()> {
+    let mut store = store()?;
+    let allowlist = allow(&["machine:Path"]);
+
+    let mut changes = ChangeSet::new();
+    changes.push_set(
+        &store,
+        EnvScope::Machine,
+        "Path",
+        "C:\\Windows;C:\\Tools",
+        EnvValueKind::ExpandString,
+    )?;
+    changes.push_set(
+        &store,
+        EnvScope::Machine,
+        "SECRET",
+        "gone",
+        EnvValueKind::String,
+    )?;
+    assert_eq!(
+        handle_changes(&mut store, &allowlist, &changes),
+        BrokerResponse::Rejected {
+            rejections: vec![Rejection {
+                subject: "machine SECRET".to_string(),
+                reason: RejectReason::NotAllowed,
+            }]
+        }
+    );
+    assert_eq!(
+        store.get(EnvScope::Machine, "Path")?.unwrap().value,
+        "C:\\Windows"
+    );
+
+    changes.changes.pop();
+    let stale = changes.clone();
+    assert_eq!(
+        handle_changes(&mut store, &allowlist, &changes),
+        BrokerResponse::Applied { changes: 1 }
+    );
+    assert_eq!(
+        store.get(EnvScope::Machine, "Path")?.unwrap().value,
+        "C:\\Windows;C:\\Tools"
+    );
+    let BrokerResponse::Rejected { rejections } = handle_changes(&mut store, &allowlist, &stale)
+    else {
+        panic!("a change set made before the last one should be rejected");
+    };
+    assert_eq!(rejections[0].reason, RejectReason::Stale);
+    Ok(())
+}
+
+#[test]
+fn test_handle_changes_rejects_invalid() -> Result<()> {
+    let mut store = store()?;
+    let mut changes = ChangeSet::new();
+    changes.push_set(
+        &store,
+        EnvScope::Machine,
+        "Path",
+        &"x".repeat(40_000),
+        EnvValueKind::ExpandString,
+    )?;
+    let BrokerResponse::Rejected { rejections } =
+        handle_changes(&mut store, &allow(&["machine:*"]), &changes)
+    else {
+        panic!("a value over the limit should be rejected");
+    };
+    assert!(
+        rejections
+            .iter()
+            .all(|rejection| matches!(rejection.reason, RejectReason::Invalid { .. }))
+    );
+    Ok(())
+}
+
+#[test]
+fn test_serve_connection() -> Result<()> {
+    let mut store = store()?;
+    let mut changes = ChangeSet::new();
+    changes.push_set(&store, EnvScope::User, "NEW", "1", EnvValueKind::String)?;
+    let request = format!("{}\n", serde_json::to_string(&changes)?);
+
+    let mut output = Vec::new();
+    let response = serve_connection(
+        &mut store,
+        &allow(&["user:NEW"]),
+        request.as_bytes(),
+        &mut output,
+    )?;
+    assert_eq!(response, BrokerResponse::Applied { changes: 1 });
+    assert_eq!(
+        String::from_utf8(output)?,
+        "{\"status\":\"applied\",\"changes\":1}\n"
+    );
+    assert!(store.get(EnvScope::User, "NEW")?.is_some());
+
+    let mut output = Vec::new();
+    let response = serve_connection(
+        &mut store,
+        &allow(&["user:NEW"]),
+        &b"not json\n"[..],
+        &mut output,
+    )?;
+    assert!(matches!(response, BrokerResponse::Failed { .. }));
+    Ok(())
+}
+
+#[test]
+fn test_broker_args() {
+    assert_eq!(
+        broker_args("pipe", 42, &allow(&["Machine:Path", "user:PATH_EXT*"])),
+        [
+            "broker",
+            "--endpoint",
+            "pipe",
+            "--client-pid",
+            "42",
+            "--once",
+            "--allow",
+            "machine:Path",
+            "--allow",
+            "user:PATH_EXT*"
+        ]
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_broker_env_store_over_socket() -> Result<()> {
+    let endpoint = std::env::temp_dir()
+        .join(format!("env-edit-test-broker-{}.sock", std::process::id()))
+        .display()
+        .to_string();
+    let front = store()?;
+    let mut brokered =
+        BrokerEnvStore::launching(Box::new(front), &endpoint, |endpoint, allowlist| {
+            assert_eq!(allowlist, &allow(&["machine:Path"]));
+            let endpoint = endpoint.to_string();
+            let allowlist = allowlist.clone();
+            std::thread::spawn(move || {
+                let mut back = store().unwrap();
+                let client_pid = Some(std::process::id());
+                env_edit::env_broker::serve(&endpoint, &mut back, &allowlist, client_pid, true)
+                    .unwrap();
+                back
+            });
+            Ok(())
+        });
+
+    let mut changes = ChangeSet::new();
+    changes.push_set(
+        &brokered,
+        EnvScope::Machine,
+        "Path",
+        "C:\\Tools",
+        EnvValueKind::ExpandString,
+    )?;
+    changes.apply(&mut brokered)?;
+    // The front-end only reads, the broker made the write
+    assert_eq!(
+        brokered.get(EnvScope::Machine, "Path")?.unwrap().value,
+        "C:\\Windows"
+    );
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_broker_refuses_other_processes() -> Result<()> {
+    let endpoint = std::env::temp_dir()
+        .join(format!("env-edit-test-refuse-{}.sock", std::process::id()))
+        .display()
+        .to_string();
+    let server_endpoint = endpoint.clone();
+    // Only answers init, so the thread is left waiting when the test ends
+    std::thread::spawn(move || {
+        let mut back = store().unwrap();
+        let allowlist = allow(&["machine:*"]);
+        env_edit::env_broker::serve(&server_endpoint, &mut back, &allowlist, Some(1), true)
+    });
+    for _ in 0..100 {
+        if std::path::Path::new(&endpoint).exists() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+
+    let mut changes = ChangeSet::new();
+    changes.push_set(&store()?, EnvScope::User, "NEW", "1", EnvValueKind::String)?;
+    let response = env_edit::env_broker::request(&endpoint, &changes);
+    std::fs::remove_file(&endpoint)?;
+    assert!(response.is_err(), "got {response:?}");
+    Ok(())
+}
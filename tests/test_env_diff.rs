@@ -0,0 +1,90 @@
+use env_edit::env_diff::EntryDiff;
+use env_edit::env_diff::VarDiff;
+use env_edit::env_diff::diff;
+use env_edit::env_diff::diff_entries;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+
+#[test]
+fn test_diff_variables() {
+    let machine = EnvScope::Machine;
+    let before = [
+        EnvironmentVariable::new(
+            machine,
+            "Path",
+            "C:\\Windows;C:\\Old;C:\\Tools",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(machine, "TEMP", "C:\\Temp", EnvValueKind::String),
+        EnvironmentVariable::new(machine, "GONE", "x", EnvValueKind::String),
+        EnvironmentVariable::new(machine, "ROOT", "%SystemDrive%", EnvValueKind::String),
+    ];
+    let after = [
+        EnvironmentVariable::new(
+            machine,
+            "PATH",
+            "C:\\Windows;C:\\Tools;C:\\New",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(machine, "TEMP", "D:\\Temp", EnvValueKind::String),
+        EnvironmentVariable::new(machine, "ROOT", "%SystemDrive%", EnvValueKind::ExpandString),
+        EnvironmentVariable::new(EnvScope::User, "GONE", "x", EnvValueKind::String),
+    ];
+
+    let changes = diff(&before, &after, true);
+    assert_eq!(
+        changes.to_human().lines().collect::<Vec<_>>(),
+        [
+            "- machine GONE = x (REG_SZ)",
+            "~ machine PATH",
+            "    - C:\\Old",
+            "    + C:\\New",
+            "~ machine ROOT",
+            "    type REG_SZ -> REG_EXPAND_SZ",
+            "~ machine TEMP",
+            "    - C:\\Temp",
+            "    + D:\\Temp",
+            "+ user GONE = x (REG_SZ)",
+        ]
+    );
+    let VarDiff::Modified { entries, .. } = &changes.changes[1] else {
+        panic!("Path should be modified");
+    };
+    assert_eq!(entries.as_ref().unwrap().len(), 4);
+
+    assert_eq!(
+        changes
+            .to_unified("a", "b")
+            .lines()
+            .take(8)
+            .collect::<Vec<_>>(),
+        [
+            "--- a/machine/GONE\tREG_SZ",
+            "+++ /dev/null",
+            "@@ -1 +0,0 @@",
+            "-x",
+            "--- a/machine/Path\tREG_EXPAND_SZ",
+            "+++ b/machine/PATH\tREG_EXPAND_SZ",
+            "@@ -1,3 +1,3 @@",
+            " C:\\Windows",
+        ]
+    );
+
+    // Ignoring scopes pairs the machine and user GONE up
+    let across = diff(&before[2..3], &after[3..], false);
+    assert!(across.is_empty());
+}
+
+#[test]
+fn test_diff_entries_moves() {
+    assert_eq!(
+        diff_entries(&["a", "b", "c"], &["c", "a", "b"]),
+        [
+            EntryDiff::Added("c".to_string()),
+            EntryDiff::Unchanged("a".to_string()),
+            EntryDiff::Unchanged("b".to_string()),
+            EntryDiff::Removed("c".to_string()),
+        ]
+    );
+}
@@ -0,0 +1,94 @@
+use env_edit::env_dotenv::parse_dotenv;
+use env_edit::env_expand::VariableSet;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+use env_edit::env_script::Shell;
+use env_edit::env_script::write_script;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::MemoryEnvStore;
+use eyre::Result;
+
+#[test]
+fn test_parse_dotenv() -> Result<()> {
+    let text = r#"
+# database settings
+export DB_HOST=localhost # inline comment
+DB_PORT = 5432
+DB_URL="postgres://${DB_HOST}:$DB_PORT/app"
+RAW='${DB_HOST} stays \n as-is'
+MULTI="first
+second\tthird"
+PRICE="\$5"
+FALLBACK=${MISSING:-default}
+HOME_DIR=${USERPROFILE}\src
+EMPTY=
+GONE=$NOPE
+"#;
+    let mut env = VariableSet::new();
+    env.insert("UserProfile", "C:\\Users\\me", None);
+    let file = parse_dotenv(text, &env)?;
+    let entries: Vec<(&str, &str)> = file
+        .entries
+        .iter()
+        .map(|(key, value)| (key.as_str(), value.as_str()))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("DB_HOST", "localhost"),
+            ("DB_PORT", "5432"),
+            ("DB_URL", "postgres://localhost:5432/app"),
+            ("RAW", "${DB_HOST} stays \\n as-is"),
+            ("MULTI", "first\nsecond\tthird"),
+            ("PRICE", "$5"),
+            ("FALLBACK", "default"),
+            ("HOME_DIR", "C:\\Users\\me\\src"),
+            ("EMPTY", ""),
+            ("GONE", ""),
+        ]
+    );
+    assert_eq!(file.warnings, ["GONE: $NOPE is not defined, using \"\""]);
+
+    assert!(parse_dotenv("NO_EQUALS\n", &env).is_err());
+    assert!(parse_dotenv("OPEN=\"never closed\n", &env).is_err());
+    assert!(parse_dotenv("A=\"x\" trailing\n", &env).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_dotenv_round_trip_and_plan() -> Result<()> {
+    let vars = vec![
+        EnvironmentVariable::new(
+            EnvScope::User,
+            "TOOLS",
+            "%ROOT%\\bin",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(
+            EnvScope::User,
+            "NOTE",
+            "costs $5\n\"quoted\"",
+            EnvValueKind::String,
+        ),
+    ];
+    let script = write_script(&vars, Shell::Dotenv, false)?;
+    let mut env = VariableSet::new();
+    env.insert("ROOT", "C:\\Apps", None);
+    let file = parse_dotenv(&script, &env)?;
+    assert_eq!(
+        file.entries[0],
+        ("TOOLS".to_string(), "C:\\Apps\\bin".to_string())
+    );
+    assert_eq!(file.entries[1], ("NOTE".to_string(), vars[1].value.clone()));
+
+    let mut store = MemoryEnvStore::new();
+    store.set(EnvScope::User, "NOTE", &vars[1].value, EnvValueKind::String)?;
+    store.set(EnvScope::User, "TOOLS", "old", EnvValueKind::ExpandString)?;
+    let changes = file.plan(&store, EnvScope::User)?;
+    assert_eq!(
+        changes.to_string(),
+        "~ user TOOLS = C:\\Apps\\bin (REG_EXPAND_SZ)\n    was old (REG_EXPAND_SZ)\n"
+    );
+    Ok(())
+}
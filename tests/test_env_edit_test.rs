@@ -1,3 +1,4 @@
+#![cfg(windows)]
 use env_edit::env_reader::get_machine_env_var;
 use env_edit::env_writer::set_machine_env_var;
 use eyre::Result;
@@ -0,0 +1,106 @@
+use env_edit::env_effective::EffectiveEnvironment;
+use env_edit::env_effective::Origin;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+
+fn defaults() -> Vec<(String, String)> {
+    vec![
+        ("SystemRoot".to_string(), "C:\\Windows".to_string()),
+        ("USERPROFILE".to_string(), "C:\\Users\\me".to_string()),
+    ]
+}
+
+#[test]
+fn test_simulate_merges_scopes() {
+    use EnvScope::Machine;
+    use EnvScope::User;
+    use EnvValueKind::ExpandString;
+    use EnvValueKind::String;
+    let vars = [
+        EnvironmentVariable::new(
+            Machine,
+            "Path",
+            "%SystemRoot%\\system32;%TOOLS%",
+            ExpandString,
+        ),
+        EnvironmentVariable::new(Machine, "TOOLS", "C:\\Tools", String),
+        EnvironmentVariable::new(Machine, "TEMP", "%SystemRoot%\\TEMP", ExpandString),
+        EnvironmentVariable::new(Machine, "Binary", "00", EnvValueKind::Binary),
+        EnvironmentVariable::new(User, "PATH", "%USERPROFILE%\\bin", ExpandString),
+        EnvironmentVariable::new(
+            User,
+            "temp",
+            "%USERPROFILE%\\AppData\\Local\\Temp",
+            ExpandString,
+        ),
+        EnvironmentVariable::new(User, "EDITOR", "code", String),
+    ];
+    let env = EffectiveEnvironment::simulate(&defaults(), &vars);
+
+    let path = env.get("path").unwrap();
+    assert_eq!(path.name, "Path");
+    assert_eq!(
+        path.value,
+        "C:\\Windows\\system32;C:\\Tools;C:\\Users\\me\\bin"
+    );
+    assert_eq!(path.origin, Origin::Appended);
+
+    let temp = env.get("TEMP").unwrap();
+    assert_eq!(temp.name, "TEMP", "keeps the first spelling");
+    assert_eq!(temp.value, "C:\\Users\\me\\AppData\\Local\\Temp");
+    assert_eq!(temp.origin, Origin::User);
+
+    assert_eq!(env.get("SystemRoot").unwrap().origin, Origin::Default);
+    assert_eq!(env.get("EDITOR").unwrap().origin, Origin::User);
+    assert!(env.get("Binary").is_none(), "not a string");
+    assert_eq!(env.len(), 6);
+
+    let names: Vec<_> = env.iter().map(|var| var.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "EDITOR",
+            "Path",
+            "SystemRoot",
+            "TEMP",
+            "TOOLS",
+            "USERPROFILE"
+        ]
+    );
+    assert_eq!(
+        env.variable_set().get("PATH").unwrap().scope,
+        Some(EnvScope::User)
+    );
+}
+
+#[test]
+fn test_simulate_expansion_order() {
+    use EnvScope::Machine;
+    use EnvValueKind::ExpandString;
+    // Expandable values are expanded in order, so only earlier ones are visible
+    let vars = [
+        EnvironmentVariable::new(Machine, "LATE_USER", "%LATER%\\x", ExpandString),
+        EnvironmentVariable::new(Machine, "LATER", "C:\\Later", ExpandString),
+        EnvironmentVariable::new(Machine, "AFTER", "%LATER%\\y", ExpandString),
+    ];
+    let env = EffectiveEnvironment::simulate(&[], &vars);
+    assert_eq!(env.get("LATE_USER").unwrap().value, "%LATER%\\x");
+    assert_eq!(env.get("LATE_USER").unwrap().unresolved, ["LATER"]);
+    assert_eq!(env.get("AFTER").unwrap().value, "C:\\Later\\y");
+    assert!(env.get("AFTER").unwrap().unresolved.is_empty());
+}
+
+#[test]
+fn test_simulate_user_path_without_machine_path() {
+    let vars = [EnvironmentVariable::new(
+        EnvScope::User,
+        "Path",
+        "C:\\Mine",
+        EnvValueKind::String,
+    )];
+    let env = EffectiveEnvironment::simulate(&[], &vars);
+    let path = env.get("Path").unwrap();
+    assert_eq!(path.value, "C:\\Mine");
+    assert_eq!(path.origin, Origin::User);
+}
@@ -0,0 +1,92 @@
+use env_edit::env_expand::SpanOrigin;
+use env_edit::env_expand::VariableSet;
+use env_edit::env_expand::expand;
+use env_edit::env_expand::fill_expanded;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+
+fn vars() -> VariableSet {
+    let mut vars = VariableSet::new();
+    vars.insert("SystemRoot", "C:\\Windows", Some(EnvScope::Machine));
+    vars.insert("PATH", "C:\\Tools", Some(EnvScope::User));
+    vars.insert("NESTED", "%SystemRoot%", Some(EnvScope::User));
+    vars
+}
+
+#[test]
+fn test_expand_matches_windows_rules() {
+    let vars = vars();
+    let cases = [
+        ("%systemroot%\\system32", "C:\\Windows\\system32"),
+        ("%UNKNOWN%", "%UNKNOWN%"),
+        // The closing % of an unknown name can open the next reference
+        ("%NOPE%PATH%", "%NOPEC:\\Tools"),
+        ("100%%", "100%%"),
+        ("%%PATH%", "%C:\\Tools"),
+        ("50% off", "50% off"),
+        // Values are substituted once, not expanded again
+        ("%NESTED%", "%SystemRoot%"),
+        ("", ""),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(expand(input, &vars).output, expected, "expanding {input:?}");
+    }
+}
+
+#[test]
+fn test_expand_trace() {
+    let input = "%SystemRoot%;%MISSING%;%Path%";
+    let expansion = expand(input, &vars());
+    assert_eq!(expansion.output, "C:\\Windows;%MISSING%;C:\\Tools");
+    assert_eq!(expansion.unresolved, ["MISSING"]);
+
+    let substitutions: Vec<_> = expansion
+        .substitutions()
+        .map(|span| {
+            let SpanOrigin::Variable { name, scope } = &span.origin else {
+                unreachable!()
+            };
+            (&input[span.source.clone()], name.as_str(), *scope)
+        })
+        .collect();
+    assert_eq!(
+        substitutions,
+        [
+            ("%SystemRoot%", "SystemRoot", Some(EnvScope::Machine)),
+            ("%Path%", "PATH", Some(EnvScope::User)),
+        ]
+    );
+
+    // Every byte of output is accounted for by exactly one span
+    let covered: usize = expansion.spans.iter().map(|span| span.output.len()).sum();
+    assert_eq!(covered, expansion.output.len());
+}
+
+#[test]
+fn test_fill_expanded() {
+    let mut vars = [
+        EnvironmentVariable::new(
+            EnvScope::Machine,
+            "SystemRoot",
+            "C:\\Windows",
+            EnvValueKind::String,
+        ),
+        EnvironmentVariable::new(
+            EnvScope::Machine,
+            "Path",
+            "%SystemRoot%\\system32;%MISSING%",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(EnvScope::User, "RAW", "%SystemRoot%", EnvValueKind::String),
+    ];
+    fill_expanded(&mut vars);
+    assert_eq!(vars[0].value_expanded, None);
+    assert_eq!(
+        vars[1].value_expanded.as_deref(),
+        Some("C:\\Windows\\system32;%MISSING%")
+    );
+    assert_eq!(vars[1].get_value(), "C:\\Windows\\system32;%MISSING%");
+    // Only REG_EXPAND_SZ values are expanded
+    assert_eq!(vars[2].get_value(), "%SystemRoot%");
+}
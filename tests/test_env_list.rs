@@ -0,0 +1,69 @@
+use env_edit::env_list::EntryKind;
+use env_edit::env_list::ListSpec;
+use env_edit::env_list::ListSpecs;
+use eyre::Result;
+
+#[test]
+fn test_builtin_lists() {
+    let lists = ListSpecs::builtin();
+    assert_eq!(lists.get("PATH"), Some(ListSpec::path()));
+    assert_eq!(
+        lists.get("pathext").map(|spec| spec.entries),
+        Some(EntryKind::Extension)
+    );
+    assert_eq!(lists.get("MY_LIST"), None);
+    assert_eq!(
+        lists.spec_for("MY_LIST"),
+        ListSpec::new("MY_LIST", ';', EntryKind::Text)
+    );
+    assert!(lists.save().is_err());
+}
+
+#[test]
+fn test_declare_and_forget() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("env-edit-test-lists-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("lists.json");
+
+    let mut lists = ListSpecs::load(&path)?;
+    assert!(lists.all().iter().all(|(_, declared)| !declared));
+    lists.declare(ListSpec::new("MY_LIST", ',', EntryKind::Text))?;
+    lists.declare(ListSpec::new("PSModulePath", ';', EntryKind::Path))?;
+    assert!(
+        lists
+            .declare(ListSpec::new("BAD", '%', EntryKind::Text))
+            .is_err()
+    );
+    assert!(
+        lists
+            .declare(ListSpec::new("A=B", ';', EntryKind::Text))
+            .is_err()
+    );
+    lists.save()?;
+
+    let mut loaded = ListSpecs::load(&path)?;
+    assert_eq!(loaded, lists);
+    assert_eq!(loaded.spec_for("my_list").separator, ',');
+    assert_eq!(
+        loaded.get("PSModulePath").map(|spec| spec.entries),
+        Some(EntryKind::Path)
+    );
+    assert_eq!(
+        loaded
+            .all()
+            .iter()
+            .filter(|(spec, _)| spec.name.eq_ignore_ascii_case("PSModulePath"))
+            .count(),
+        1
+    );
+
+    assert!(loaded.forget("psmodulepath"));
+    assert!(!loaded.forget("psmodulepath"));
+    assert_eq!(
+        loaded.get("PSModulePath").map(|spec| spec.entries),
+        Some(EntryKind::Directory)
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,282 @@
+use env_edit::env_expand::VariableSet;
+use env_edit::env_list::EntryKind;
+use env_edit::env_list::ListSpec;
+use env_edit::env_list::ListSpecs;
+use env_edit::env_path::PathList;
+use env_edit::env_path::PathProblem;
+use env_edit::env_path::Position;
+use env_edit::env_path::list_kind;
+use env_edit::env_path::normalize_entry;
+use env_edit::env_path::prefix_variables;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_validate::Severity;
+use eyre::Result;
+
+fn vars() -> VariableSet {
+    let mut vars = VariableSet::new();
+    vars.insert("SystemRoot", "C:\\Windows", None);
+    vars
+}
+
+#[test]
+fn test_normalize_entry() {
+    let vars = vars();
+    assert_eq!(
+        normalize_entry("%SYSTEMROOT%\\System32\\", &vars),
+        "c:\\windows\\system32"
+    );
+    assert_eq!(
+        normalize_entry(" \"C:/Windows/System32\" ", &vars),
+        "c:\\windows\\system32"
+    );
+    assert_eq!(normalize_entry("C:\\", &vars), "c:\\");
+    assert_eq!(normalize_entry("", &vars), "");
+}
+
+#[test]
+fn test_path_list_edits() -> Result<()> {
+    let vars = vars();
+    let mut list = PathList::parse("%SystemRoot%\\system32;C:\\Tools\\;;C:\\Git\\cmd");
+    assert_eq!(list.entries.len(), 4);
+
+    assert!(
+        list.insert("c:\\windows\\System32", &Position::Append, &vars)
+            .is_err()
+    );
+    assert_eq!(list.insert("C:\\First", &Position::Prepend, &vars)?, 0);
+    assert_eq!(
+        list.insert("C:\\Node", &Position::Before("c:/tools".into()), &vars)?,
+        2
+    );
+    assert_eq!(list.insert("C:\\Last", &Position::Index(99), &vars)?, 6);
+    assert_eq!(
+        list.to_value(),
+        "C:\\First;%SystemRoot%\\system32;C:\\Node;C:\\Tools\\;;C:\\Git\\cmd;C:\\Last"
+    );
+
+    assert_eq!(list.move_entry("C:\\Last", &Position::Index(1), &vars)?, 1);
+    assert!(
+        list.move_entry("C:\\Missing", &Position::Prepend, &vars)
+            .is_err()
+    );
+    assert!(
+        list.move_entry("C:\\Node", &Position::Before("C:\\Nope".into()), &vars)
+            .is_err()
+    );
+    assert_eq!(
+        list.entries[2], "%SystemRoot%\\system32",
+        "a failed move changes nothing"
+    );
+
+    list.entries.push("C:\\windows\\system32\\".into());
+    list.entries.push("c:\\tools".into());
+    assert_eq!(list.dedupe(&vars), ["C:\\windows\\system32\\", "c:\\tools"]);
+    assert_eq!(list.remove("c:\\first\\", &vars), ["C:\\First"]);
+    assert_eq!(
+        list.to_value(),
+        "C:\\Last;%SystemRoot%\\system32;C:\\Node;C:\\Tools\\;;C:\\Git\\cmd"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_list_kind() {
+    use EnvValueKind::*;
+    assert_eq!(list_kind(Some(ExpandString), "C:\\Tools"), ExpandString);
+    assert_eq!(list_kind(Some(String), "%JAVA_HOME%\\bin"), ExpandString);
+    assert_eq!(list_kind(Some(String), "C:\\Tools"), String);
+    assert_eq!(list_kind(None, "C:\\Tools"), String);
+}
+
+#[test]
+fn test_path_lint() -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("env-edit-test-lint-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("bin"))?;
+    std::fs::write(dir.join("tool.exe"), "")?;
+    let root = dir.display().to_string();
+
+    let mut vars = VariableSet::new();
+    vars.insert("ROOT", &root, None);
+    let list = PathList::parse(&format!(
+        "%ROOT%/bin;;{root}/BIN/;\"{root}/tool.exe\";{root}/tool.exe;{root}/gone;relative\\bin;\\\\server\\share"
+    ));
+    let issues = list.lint(&vars);
+    let problems: Vec<_> = issues
+        .iter()
+        .map(|issue| (issue.index, issue.problem.clone()))
+        .collect();
+    assert_eq!(
+        problems,
+        [
+            (1, PathProblem::Empty),
+            (2, PathProblem::Duplicate { first: 0 }),
+            (3, PathProblem::Quoted),
+            (3, PathProblem::NotADirectory),
+            (4, PathProblem::Duplicate { first: 3 }),
+            (5, PathProblem::Missing),
+            (6, PathProblem::Relative),
+            (7, PathProblem::Network),
+        ]
+    );
+    assert_eq!(issues[0].severity(), Severity::Info);
+    assert!(!PathProblem::Network.is_fixable(true));
+    assert!(!PathProblem::Missing.is_fixable(false));
+
+    // Missing entries stay unless asked for
+    let kept = list.fixed(&issues, false);
+    assert_eq!(
+        kept.entries,
+        [
+            "%ROOT%/bin".to_string(),
+            format!("{root}/gone"),
+            "relative\\bin".to_string(),
+            "\\\\server\\share".to_string()
+        ]
+    );
+    let fixed = list.fixed(&issues, true);
+    assert_eq!(
+        fixed.entries,
+        ["%ROOT%/bin", "relative\\bin", "\\\\server\\share"]
+    );
+    assert!(
+        fixed
+            .lint(&vars)
+            .iter()
+            .all(|issue| !issue.problem.is_fixable(true))
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_path_lint_quoted_directory_is_unquoted() {
+    let dir = std::env::temp_dir().display().to_string();
+    let list = PathList::parse(&format!("\"{dir}\""));
+    let issues = list.lint(&VariableSet::new());
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].problem, PathProblem::Quoted);
+    assert_eq!(list.fixed(&issues, false).entries, [dir]);
+}
+
+#[test]
+fn test_path_lint_unresolved() {
+    let list = PathList::parse("%NOPE%\\bin");
+    let issues = list.lint(&VariableSet::new());
+    assert_eq!(
+        issues[0].problem,
+        PathProblem::Unresolved {
+            names: vec!["NOPE".to_string()]
+        }
+    );
+    assert!(!issues[0].problem.is_fixable(true));
+    assert_eq!(list.fixed(&issues, true), list);
+}
+
+#[cfg(not(windows))]
+#[test]
+fn test_path_lint_skips_drives_elsewhere() {
+    let list = PathList::parse("C:\\Windows\\system32;D:/Tools");
+    assert!(list.lint(&VariableSet::new()).is_empty());
+}
+
+#[test]
+fn test_pathext_list() -> Result<()> {
+    let vars = VariableSet::new();
+    let spec = ListSpecs::builtin().spec_for("PATHEXT");
+    let mut list = PathList::parse_as(".COM;.EXE;.BAT", &spec);
+    assert!(list.insert(".exe", &Position::Append, &vars).is_err());
+    assert!(list.insert("PY", &Position::Append, &vars).is_err());
+    assert!(list.insert(".PY;.PYW", &Position::Append, &vars).is_err());
+    assert_eq!(
+        list.insert(".PY", &Position::Before(".bat".into()), &vars)?,
+        2
+    );
+    assert_eq!(list.remove(".com", &vars), [".COM"]);
+    assert_eq!(list.to_value(), ".EXE;.PY;.BAT");
+
+    let mut list = PathList::parse_as(".EXE;cmd;.exe", &spec);
+    let issues = list.lint(&vars);
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0].problem, PathProblem::NotAnExtension);
+    assert_eq!(issues[0].severity(), Severity::Error);
+    assert_eq!(issues[1].problem, PathProblem::Duplicate { first: 0 });
+    assert_eq!(list.fixed(&issues, false).to_value(), ".EXE;.cmd");
+    assert_eq!(list.dedupe(&vars), [".exe"]);
+    Ok(())
+}
+
+#[test]
+fn test_text_list_with_separator() -> Result<()> {
+    let vars = VariableSet::new();
+    let spec = ListSpec::new("MY_LIST", ',', EntryKind::Text);
+    let mut list = PathList::parse_as("a, b,A", &spec);
+    assert_eq!(list.entries, ["a", " b", "A"]);
+    assert!(list.insert("b", &Position::Append, &vars).is_err());
+    assert_eq!(list.insert("c;d", &Position::Append, &vars)?, 3);
+    assert!(list.insert("e,f", &Position::Append, &vars).is_err());
+    assert!(list.dedupe(&vars).is_empty());
+    assert!(list.lint(&vars).is_empty());
+    assert_eq!(list.to_value(), "a, b,A,c;d");
+    Ok(())
+}
+
+#[test]
+fn test_path_compact() -> Result<()> {
+    let root = std::env::temp_dir().join(format!("env-edit-test-compact-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for dir in [
+        "Program Files/Git/cmd",
+        "Program Files/Node",
+        "Program FilesX",
+        "Tools",
+    ] {
+        std::fs::create_dir_all(root.join(dir))?;
+    }
+    let root = root.display().to_string();
+    let mut prefixes = VariableSet::new();
+    prefixes.insert("ProgramFiles", &format!("{root}/Program Files/"), None);
+    prefixes.insert("GIT", &format!("{root}/Program Files/Git"), None);
+    prefixes.insert("ROOT", &root, None);
+    prefixes.insert("SHORT", "/", None);
+
+    let list = PathList::parse(&format!(
+        "{root}/Program Files/Git/cmd;{root}/Program Files/Node;{root}/Program FilesX;\
+         {root}/Program Files/Node/;{root}/Gone;%ROOT%/Tools;;%NOPE%"
+    ));
+    let kept = list.compact(&prefixes, &prefixes, false);
+    assert!(kept.list.entries.contains(&"%ROOT%/Gone".to_string()));
+    let compaction = list.compact(&prefixes, &prefixes, true);
+    assert_eq!(
+        compaction.list.entries,
+        [
+            "%GIT%/cmd",
+            "%ProgramFiles%/Node",
+            "%ROOT%/Program FilesX",
+            "%ROOT%/Tools",
+            "%NOPE%",
+        ]
+    );
+    let removed: Vec<_> = compaction.removed.iter().map(|issue| issue.index).collect();
+    assert_eq!(removed, [3, 4, 6]);
+    assert_eq!(compaction.substituted.len(), 3);
+    assert_eq!(
+        compaction.substituted[1],
+        (
+            format!("{root}/Program Files/Node"),
+            "%ProgramFiles%/Node".to_string()
+        )
+    );
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_prefix_variables() {
+    assert!(prefix_variables(EnvScope::User).contains(&"USERPROFILE"));
+    assert!(!prefix_variables(EnvScope::Machine).contains(&"USERPROFILE"));
+    assert!(prefix_variables(EnvScope::Machine).contains(&"ProgramFiles"));
+}
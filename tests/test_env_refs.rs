@@ -0,0 +1,60 @@
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+use env_edit::env_refs::DanglingRef;
+use env_edit::env_refs::RefGraph;
+
+#[test]
+fn test_ref_graph() {
+    let graph = RefGraph::build(&[
+        EnvironmentVariable::new(
+            EnvScope::Machine,
+            "SystemRoot",
+            "C:\\Windows",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(
+            EnvScope::Machine,
+            "Path",
+            "%SystemRoot%\\system32;%NEWPATH%;%GONE%",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(
+            EnvScope::User,
+            "NEWPATH",
+            "%TOOLS%\\bin",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(
+            EnvScope::User,
+            "TOOLS",
+            "%systemroot%\\tools",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(EnvScope::User, "A", "%B%", EnvValueKind::ExpandString),
+        EnvironmentVariable::new(EnvScope::User, "B", "%a%", EnvValueKind::ExpandString),
+        EnvironmentVariable::new(
+            EnvScope::User,
+            "SELF",
+            "%SELF%;x",
+            EnvValueKind::ExpandString,
+        ),
+    ]);
+
+    assert_eq!(graph.references("PATH"), ["NEWPATH", "SystemRoot"]);
+    assert_eq!(graph.dangling_references("path"), ["GONE"]);
+    assert_eq!(graph.dependents("SYSTEMROOT"), ["Path", "TOOLS"]);
+    assert_eq!(graph.transitive_dependents("TOOLS"), ["NEWPATH", "Path"]);
+    assert_eq!(graph.definitions("newpath")[0].scope, EnvScope::User);
+
+    assert_eq!(graph.cycles(), [vec!["A", "B"], vec!["SELF"]]);
+    assert_eq!(graph.cycle_containing("b"), Some(vec!["A", "B"]));
+    assert_eq!(graph.cycle_containing("Path"), None);
+    assert_eq!(
+        graph.dangling(),
+        [DanglingRef {
+            from: "Path".to_string(),
+            name: "GONE".to_string()
+        }]
+    );
+}
@@ -0,0 +1,107 @@
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+use env_edit::env_regfile::RegEntry;
+use env_edit::env_regfile::RegFormat;
+use env_edit::env_regfile::decode_reg_file;
+use env_edit::env_regfile::encode_reg_file;
+use env_edit::env_regfile::parse_reg;
+use env_edit::env_regfile::write_reg;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::MemoryEnvStore;
+use eyre::Result;
+
+#[test]
+fn test_reg_round_trip() -> Result<()> {
+    let vars = vec![
+        EnvironmentVariable::new(
+            EnvScope::Machine,
+            "Path",
+            "%SystemRoot%\\system32;C:\\Program Files\\Tools",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(EnvScope::Machine, "Quote\"d", "a\\b", EnvValueKind::String),
+        EnvironmentVariable::new(EnvScope::Machine, "FLAG", "42", EnvValueKind::Dword),
+        EnvironmentVariable::new(
+            EnvScope::User,
+            "HOME",
+            "%USERPROFILE%",
+            EnvValueKind::ExpandString,
+        ),
+    ];
+    for format in [RegFormat::Regedit4, RegFormat::Regedit5] {
+        let text = write_reg(&vars, format)?;
+        let parsed = parse_reg(&decode_reg_file(&encode_reg_file(&text, format)))?;
+        assert_eq!(parsed.format, format);
+        assert!(parsed.warnings.is_empty());
+        let values: Vec<(EnvScope, &str, &str, EnvValueKind)> = parsed
+            .entries
+            .iter()
+            .map(|entry| match entry {
+                RegEntry::Set(var) => (var.scope, var.key.as_str(), var.value.as_str(), var.kind),
+                RegEntry::Delete { .. } => panic!("nothing was deleted"),
+            })
+            .collect();
+        let expected: Vec<(EnvScope, &str, &str, EnvValueKind)> = vars
+            .iter()
+            .map(|var| (var.scope, var.key.as_str(), var.value.as_str(), var.kind))
+            .collect();
+        assert_eq!(values, expected, "{format:?}");
+    }
+
+    let text = write_reg(&vars[3..], RegFormat::Regedit5)?;
+    assert_eq!(
+        text.lines().collect::<Vec<_>>(),
+        [
+            "Windows Registry Editor Version 5.00",
+            "",
+            "[HKEY_CURRENT_USER\\Environment]",
+            "\"HOME\"=hex(2):25,00,55,00,53,00,45,00,52,00,50,00,52,00,4f,00,46,00,49,00,4c,\\",
+            "  00,45,00,25,00,00,00",
+            "",
+        ]
+    );
+    let text = write_reg(&vars[3..], RegFormat::Regedit4)?;
+    assert!(text.contains("\"HOME\"=hex(2):25,55,53,45,52,50,52,4f,46,49,4c,45,25,00\r\n"));
+    Ok(())
+}
+
+#[test]
+fn test_reg_import_plan() -> Result<()> {
+    let text = r#"Windows Registry Editor Version 5.00
+
+; from the build server
+[HKEY_CURRENT_USER\Software\Other]
+"Path"="not an environment variable"
+
+[HKEY_LOCAL_MACHINE\SYSTEM\CurrentControlSet\Control\Session Manager\Environment]
+"JAVA_HOME"="C:\\Java"
+"OLD"=-
+"TOOLS"=hex(2):25,00,4a,00,41,00,56,00,41,00,5f,00,48,00,4f,00,4d,00,45,00,25,\
+  00,00,00
+"SAME"="unchanged"
+"#;
+    let reg = parse_reg(text)?;
+    assert_eq!(reg.warnings.len(), 1);
+
+    let mut store = MemoryEnvStore::new();
+    store.set(EnvScope::Machine, "OLD", "x", EnvValueKind::String)?;
+    store.set(EnvScope::Machine, "SAME", "unchanged", EnvValueKind::String)?;
+    let changes = reg.plan(&store, &EnvScope::ALL)?;
+    assert_eq!(
+        changes.to_string().lines().collect::<Vec<_>>(),
+        [
+            "+ machine JAVA_HOME = C:\\Java (REG_SZ)",
+            "- machine OLD",
+            "+ machine TOOLS = %JAVA_HOME% (REG_EXPAND_SZ)",
+        ]
+    );
+    assert!(reg.plan(&store, &[EnvScope::User])?.is_empty());
+
+    assert!(parse_reg("[-HKEY_CURRENT_USER\\Environment]").is_err());
+    assert!(
+        parse_reg("Windows Registry Editor Version 5.00\n[-HKEY_CURRENT_USER\\Environment]")
+            .is_err()
+    );
+    Ok(())
+}
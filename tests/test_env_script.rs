@@ -0,0 +1,161 @@
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+use env_edit::env_script::Shell;
+use env_edit::env_script::write_script;
+use eyre::Result;
+
+fn sample() -> Vec<EnvironmentVariable> {
+    vec![
+        EnvironmentVariable::new(
+            EnvScope::Machine,
+            "TOOLS",
+            "%ROOT%\\bin;50% $off",
+            EnvValueKind::ExpandString,
+        ),
+        EnvironmentVariable::new(
+            EnvScope::User,
+            "GREETING",
+            "it's $HOME",
+            EnvValueKind::String,
+        ),
+        EnvironmentVariable::new(
+            EnvScope::Machine,
+            "ProgramFiles(x86)",
+            "C:\\Program Files (x86)",
+            EnvValueKind::String,
+        ),
+        EnvironmentVariable::new(EnvScope::Machine, "FLAG", "1", EnvValueKind::Dword),
+    ]
+}
+
+fn lines(script: &str) -> Vec<&str> {
+    script
+        .lines()
+        .skip_while(|line| !line.contains("Generated"))
+        .skip(1)
+        .collect()
+}
+
+#[test]
+fn test_session_scripts() -> Result<()> {
+    let vars = sample();
+    assert_eq!(
+        lines(&write_script(&vars, Shell::Cmd, false)?),
+        [
+            "set \"TOOLS=%ROOT%\\bin;50%% $off\"",
+            "set \"GREETING=it's $HOME\"",
+            "set \"ProgramFiles(x86)=C:\\Program Files (x86)\"",
+            "REM Skipping FLAG: REG_DWORD is not part of the environment",
+        ]
+    );
+    assert_eq!(
+        lines(&write_script(&vars, Shell::PowerShell, false)?),
+        [
+            "$env:TOOLS = \"${env:ROOT}\\bin;50% `$off\"",
+            "$env:GREETING = 'it''s $HOME'",
+            "${env:ProgramFiles(x86)} = 'C:\\Program Files (x86)'",
+            "# Skipping FLAG: REG_DWORD is not part of the environment",
+        ]
+    );
+    assert_eq!(
+        lines(&write_script(&vars, Shell::Bash, false)?),
+        [
+            "export TOOLS=\"${ROOT}\\\\bin;50% \\$off\"",
+            "export GREETING='it'\\''s $HOME'",
+            "# Skipping ProgramFiles(x86): not a valid variable name here",
+            "# Skipping FLAG: REG_DWORD is not part of the environment",
+        ]
+    );
+    assert_eq!(
+        lines(&write_script(&vars, Shell::Fish, false)?)[..2],
+        [
+            "set -gx TOOLS $ROOT\"\\\\bin;50% \\$off\"",
+            "set -gx GREETING 'it\\'s $HOME'",
+        ]
+    );
+    assert_eq!(
+        lines(&write_script(&vars, Shell::Nu, false)?)[..3],
+        [
+            "$env.TOOLS = $\"($env.ROOT)\\\\bin;50% $off\"",
+            "$env.GREETING = r#'it's $HOME'#",
+            "$env.\"ProgramFiles(x86)\" = 'C:\\Program Files (x86)'",
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_fish_references() -> Result<()> {
+    let vars = vec![EnvironmentVariable::new(
+        EnvScope::User,
+        "JAVA",
+        "%JAVA_HOME%%VERSION%bin;%APPDATA%",
+        EnvValueKind::ExpandString,
+    )];
+    assert_eq!(
+        lines(&write_script(&vars, Shell::Fish, false)?),
+        ["set -gx JAVA $JAVA_HOME$VERSION\"bin;\"$APPDATA"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_persistent_scripts() -> Result<()> {
+    let vars = sample();
+    assert_eq!(
+        lines(&write_script(&vars[..2], Shell::Cmd, true)?),
+        [
+            "reg add \"HKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment\" /v \"TOOLS\" /t REG_EXPAND_SZ /d \"%%ROOT%%\\bin;50%% $off\" /f",
+            "setx \"GREETING\" \"it's $HOME\"",
+        ]
+    );
+    assert_eq!(
+        lines(&write_script(&vars[..2], Shell::PowerShell, true)?),
+        [
+            "[Microsoft.Win32.Registry]::SetValue('HKEY_LOCAL_MACHINE\\SYSTEM\\CurrentControlSet\\Control\\Session Manager\\Environment', 'TOOLS', '%ROOT%\\bin;50% $off', [Microsoft.Win32.RegistryValueKind]::ExpandString)",
+            "[Environment]::SetEnvironmentVariable('GREETING', 'it''s $HOME', 'User')",
+        ]
+    );
+    assert!(write_script(&vars, Shell::Bash, true).is_err());
+
+    let expandable = EnvironmentVariable::new(
+        EnvScope::User,
+        "CACHE",
+        "%LOCALAPPDATA%\\cache",
+        EnvValueKind::ExpandString,
+    );
+    assert_eq!(
+        lines(&write_script(
+            std::slice::from_ref(&expandable),
+            Shell::Cmd,
+            true
+        )?),
+        [
+            "reg add \"HKEY_CURRENT_USER\\Environment\" /v \"CACHE\" /t REG_EXPAND_SZ /d \"%%LOCALAPPDATA%%\\cache\" /f"
+        ]
+    );
+    // No references, but still REG_EXPAND_SZ
+    let unexpanded = EnvironmentVariable::new(
+        EnvScope::User,
+        "LATER",
+        "C:\\Tools\\",
+        EnvValueKind::ExpandString,
+    );
+    assert_eq!(
+        lines(&write_script(&[unexpanded], Shell::Cmd, true)?),
+        [
+            "reg add \"HKEY_CURRENT_USER\\Environment\" /v \"LATER\" /t REG_EXPAND_SZ /d \"C:\\Tools\\\\\" /f"
+        ]
+    );
+    assert_eq!(
+        lines(&write_script(&[expandable], Shell::PowerShell, true)?),
+        [
+            "[Microsoft.Win32.Registry]::SetValue('HKEY_CURRENT_USER\\Environment', 'CACHE', '%LOCALAPPDATA%\\cache', [Microsoft.Win32.RegistryValueKind]::ExpandString)"
+        ]
+    );
+
+    let quoted = EnvironmentVariable::new(EnvScope::User, "Q", "say \"hi\"", EnvValueKind::String);
+    assert!(write_script(&[quoted], Shell::Cmd, false).is_err());
+    Ok(())
+}
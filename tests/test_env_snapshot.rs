@@ -0,0 +1,67 @@
+use env_edit::env_change::Change;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_snapshot::Snapshot;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::MemoryEnvStore;
+use eyre::Result;
+
+#[test]
+fn test_restore_plans_minimal_changes() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    store.set(
+        EnvScope::Machine,
+        "Path",
+        "%SystemRoot%",
+        EnvValueKind::ExpandString,
+    )?;
+    store.set(EnvScope::Machine, "KEEP", "same", EnvValueKind::String)?;
+    store.set(EnvScope::Machine, "GONE", "x", EnvValueKind::String)?;
+    store.set(EnvScope::User, "EDITOR", "hx", EnvValueKind::String)?;
+    let snapshot = Snapshot::capture(&store, &EnvScope::ALL)?;
+
+    store.set(EnvScope::Machine, "Path", "C:\\oops", EnvValueKind::String)?;
+    store.delete(EnvScope::Machine, "GONE")?;
+    store.set(EnvScope::Machine, "NEW", "y", EnvValueKind::String)?;
+    store.delete(EnvScope::User, "EDITOR")?;
+    store.set(EnvScope::User, "Editor", "hx", EnvValueKind::String)?;
+
+    let changes = snapshot.plan_restore(&store, &EnvScope::ALL)?;
+    let summary: Vec<String> = changes.changes.iter().map(|c| c.to_string()).collect();
+    assert_eq!(
+        summary,
+        [
+            "- machine NEW",
+            "~ machine Path = %SystemRoot% (REG_EXPAND_SZ)\n    was C:\\oops (REG_SZ)",
+            "+ machine GONE = x (REG_SZ)",
+            "- user Editor",
+            "~ user EDITOR = hx (REG_SZ)\n    was named Editor",
+        ]
+    );
+
+    // Restricting the scopes leaves the other one alone
+    let machine_only = snapshot.plan_restore(&store, &[EnvScope::Machine])?;
+    assert!(
+        machine_only
+            .changes
+            .iter()
+            .all(|c| c.scope() == EnvScope::Machine)
+    );
+
+    changes.apply(&mut store)?;
+    assert!(snapshot.plan_restore(&store, &EnvScope::ALL)?.is_empty());
+    assert_eq!(store.list(EnvScope::User)?[0].key, "EDITOR");
+
+    // Undoing the restore gets back to where it started
+    changes.inverse().apply(&mut store)?;
+    assert_eq!(
+        store.get(EnvScope::Machine, "Path")?.unwrap().value,
+        "C:\\oops"
+    );
+    assert!(store.get(EnvScope::Machine, "GONE")?.is_none());
+    assert!(matches!(
+        snapshot.plan_restore(&store, &EnvScope::ALL)?.changes[0],
+        Change::Delete { .. }
+    ));
+    Ok(())
+}
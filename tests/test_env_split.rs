@@ -0,0 +1,167 @@
+use env_edit::env_change::Change;
+use env_edit::env_list::EntryKind;
+use env_edit::env_list::ListSpec;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_split::DEFAULT_HELPER_PREFIX;
+use env_edit::env_split::plan_inline;
+use env_edit::env_split::plan_split;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::MemoryEnvStore;
+use eyre::Result;
+
+const MACHINE: EnvScope = EnvScope::Machine;
+
+fn value(store: &MemoryEnvStore, key: &str) -> Result<Option<String>> {
+    Ok(store.get(MACHINE, key)?.map(|var| var.value))
+}
+
+#[test]
+fn test_split_and_inline() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    // Ten entries of 9 characters, 99 characters joined
+    let entries: Vec<String> = (0..10).map(|i| format!("C:\\Dir{i:03}")).collect();
+    let original = entries.join(";");
+    store.set(MACHINE, "Path", &original, EnvValueKind::String)?;
+    // Taken in the other scope, so skipped
+    store.set(EnvScope::User, "PATH_EXT1", "x", EnvValueKind::String)?;
+
+    let plan = plan_split(
+        &store,
+        MACHINE,
+        &ListSpec::path(),
+        50,
+        DEFAULT_HELPER_PREFIX,
+    )?;
+    assert!(plan.warnings.is_empty());
+    plan.changes.write(&mut store)?;
+    let path = store.get(MACHINE, "Path")?.unwrap();
+    assert_eq!(path.kind, EnvValueKind::ExpandString);
+    assert_eq!(path.value, "C:\\Dir000;C:\\Dir001;%PATH_EXT2%;%PATH_EXT3%");
+    assert_eq!(
+        value(&store, "PATH_EXT2")?.as_deref(),
+        Some(entries[2..7].join(";").as_str())
+    );
+    assert_eq!(
+        value(&store, "PATH_EXT3")?.as_deref(),
+        Some(entries[7..].join(";").as_str())
+    );
+    for var in store.list(MACHINE)? {
+        assert!(var.value.len() <= 50, "{var:?}");
+        if var.key != "Path" {
+            assert_eq!(var.kind, EnvValueKind::String);
+        }
+    }
+
+    let plan = plan_inline(&store, MACHINE, &ListSpec::path(), DEFAULT_HELPER_PREFIX)?;
+    assert!(plan.warnings.is_empty());
+    plan.changes.write(&mut store)?;
+    assert_eq!(value(&store, "Path")?.as_deref(), Some(original.as_str()));
+    assert_eq!(store.list(MACHINE)?.len(), 1, "helpers removed");
+    assert!(
+        plan_inline(&store, MACHINE, &ListSpec::path(), DEFAULT_HELPER_PREFIX)?
+            .changes
+            .is_empty()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_short_enough() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    store.set(MACHINE, "Path", "C:\\A;C:\\B", EnvValueKind::String)?;
+    assert!(
+        plan_split(&store, MACHINE, &ListSpec::path(), 10, "HELP")?
+            .changes
+            .is_empty()
+    );
+    assert!(
+        plan_split(&store, MACHINE, &ListSpec::path(), 3, "HELP").is_err(),
+        "an entry is too long"
+    );
+    assert!(
+        plan_split(
+            &store,
+            MACHINE,
+            &ListSpec::new("Missing", ';', EntryKind::Directory),
+            3,
+            "HELP"
+        )
+        .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_warns_about_nested_references() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    let path = "C:\\Windows\\system32;%ProgramFiles%\\Git\\cmd";
+    store.set(MACHINE, "Path", path, EnvValueKind::ExpandString)?;
+    let plan = plan_split(&store, MACHINE, &ListSpec::path(), 30, "HELP")?;
+    assert_eq!(plan.warnings.len(), 1);
+    assert!(plan.warnings[0].starts_with("%ProgramFiles%\\Git\\cmd moves into HELP1"));
+    let Change::Set { var, .. } = &plan.changes.changes[0] else {
+        panic!("expected the helper to be set first");
+    };
+    assert_eq!(var.kind, EnvValueKind::ExpandString);
+    Ok(())
+}
+
+#[test]
+fn test_inline_keeps_helpers_still_in_use() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    store.set(
+        MACHINE,
+        "Path",
+        "%PATH_EXT1%;%OTHER%",
+        EnvValueKind::ExpandString,
+    )?;
+    store.set(MACHINE, "PATH_EXT1", "C:\\A;C:\\B", EnvValueKind::String)?;
+    store.set(MACHINE, "OTHER", "C:\\C", EnvValueKind::String)?;
+    store.set(
+        EnvScope::User,
+        "Path",
+        "%path_ext1%",
+        EnvValueKind::ExpandString,
+    )?;
+
+    let plan = plan_inline(&store, MACHINE, &ListSpec::path(), DEFAULT_HELPER_PREFIX)?;
+    assert_eq!(
+        plan.warnings,
+        ["Keeping PATH_EXT1 because user Path still references it"]
+    );
+    assert_eq!(plan.changes.len(), 1);
+    plan.changes.write(&mut store)?;
+    assert_eq!(
+        value(&store, "Path")?.as_deref(),
+        Some("C:\\A;C:\\B;%OTHER%")
+    );
+    Ok(())
+}
+
+#[test]
+fn test_split_and_inline_with_separator() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    let spec = ListSpec::new("DIRS", ',', EntryKind::Directory);
+    let original = "C:\\One;Two,C:\\Three,C:\\Four";
+    store.set(MACHINE, "DIRS", original, EnvValueKind::String)?;
+
+    let plan = plan_split(&store, MACHINE, &spec, 20, "HELP")?;
+    plan.changes.write(&mut store)?;
+    // The `;` is part of the first entry, not a place to split
+    assert_eq!(
+        value(&store, "DIRS")?.as_deref(),
+        Some("C:\\One;Two,%HELP1%")
+    );
+    assert_eq!(
+        value(&store, "HELP1")?.as_deref(),
+        Some("C:\\Three,C:\\Four")
+    );
+
+    plan_inline(&store, MACHINE, &spec, "HELP")?
+        .changes
+        .write(&mut store)?;
+    assert_eq!(value(&store, "DIRS")?.as_deref(), Some(original));
+    assert_eq!(value(&store, "HELP1")?, None);
+    Ok(())
+}
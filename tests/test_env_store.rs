@@ -0,0 +1,85 @@
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_store::EnvStore;
+use env_edit::env_store::JsonFileEnvStore;
+use env_edit::env_store::MemoryEnvStore;
+use eyre::Result;
+
+#[test]
+fn test_memory_store_is_case_insensitive() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    let scope = EnvScope::Machine;
+    store.set(scope, "Path", "C:\\Windows", EnvValueKind::ExpandString)?;
+    store.set(scope, "PATH", "C:\\Tools", EnvValueKind::ExpandString)?;
+
+    let vars = store.list(scope)?;
+    assert_eq!(vars.len(), 1);
+    assert_eq!(vars[0].key, "Path", "overwriting keeps the original name");
+    assert_eq!(store.get(scope, "path")?.unwrap().value, "C:\\Tools");
+
+    assert!(store.delete(scope, "pAtH")?);
+    assert!(!store.delete(scope, "Path")?);
+    assert!(store.get(scope, "Path")?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_memory_store_keeps_scopes_apart() -> Result<()> {
+    let mut store = MemoryEnvStore::new();
+    store.set(
+        EnvScope::Machine,
+        "TEMP",
+        "C:\\Windows\\Temp",
+        EnvValueKind::String,
+    )?;
+    store.set(
+        EnvScope::User,
+        "TEMP",
+        "C:\\Users\\me\\Temp",
+        EnvValueKind::String,
+    )?;
+
+    assert_eq!(store.list(EnvScope::Machine)?.len(), 1);
+    assert_eq!(
+        store.get(EnvScope::User, "temp")?.unwrap().value,
+        "C:\\Users\\me\\Temp"
+    );
+
+    store.delete(EnvScope::User, "TEMP")?;
+    assert!(store.get(EnvScope::User, "TEMP")?.is_none());
+    assert!(store.get(EnvScope::Machine, "TEMP")?.is_some());
+    Ok(())
+}
+
+#[test]
+fn test_json_file_store_round_trip() -> Result<()> {
+    let path =
+        std::env::temp_dir().join(format!("env-edit-test-store-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let mut store = JsonFileEnvStore::open(&path)?;
+    store.set(
+        EnvScope::Machine,
+        "ENV_EDIT_TEST",
+        "1",
+        EnvValueKind::String,
+    )?;
+    store.set(
+        EnvScope::User,
+        "TOOLS",
+        "%ProgramFiles%\\Tools",
+        EnvValueKind::ExpandString,
+    )?;
+    store.delete(EnvScope::Machine, "ENV_EDIT_TEST")?;
+
+    let reopened = JsonFileEnvStore::open(&path)?;
+    let machine = reopened.list(EnvScope::Machine)?;
+    let user = reopened.list(EnvScope::User)?;
+    std::fs::remove_file(&path)?;
+
+    assert!(machine.is_empty());
+    assert_eq!(user.len(), 1);
+    assert_eq!(user[0].key, "TOOLS");
+    assert_eq!(user[0].kind, EnvValueKind::ExpandString);
+    Ok(())
+}
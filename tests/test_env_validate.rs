@@ -0,0 +1,115 @@
+use env_edit::env_change::Change;
+use env_edit::env_change::ChangeSet;
+use env_edit::env_expand::VariableSet;
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+use env_edit::env_validate::DIALOG_VALUE_LIMIT;
+use env_edit::env_validate::Severity;
+use env_edit::env_validate::VALUE_LIMIT;
+use env_edit::env_validate::check_value;
+use env_edit::env_validate::env_block_len;
+use env_edit::env_validate::value_lengths;
+
+fn var(key: &str, value: &str, kind: EnvValueKind) -> EnvironmentVariable {
+    EnvironmentVariable::new(EnvScope::Machine, key, value, kind)
+}
+
+fn set(var: EnvironmentVariable) -> ChangeSet {
+    let mut changes = ChangeSet::new();
+    changes.push(Change::Set {
+        var,
+        previous: None,
+    });
+    changes
+}
+
+#[test]
+fn test_value_length_limits() {
+    let long_dir = "x".repeat(100);
+    let mut vars = VariableSet::new();
+    vars.insert("LONG", &long_dir, None);
+
+    // Short raw value, long once expanded
+    let path = var("Path", &"%LONG%;".repeat(30), EnvValueKind::ExpandString);
+    let lengths = value_lengths(&path, &vars);
+    assert_eq!(lengths.raw, 7 * 30);
+    assert_eq!(lengths.expanded, 101 * 30);
+    assert!(check_value(&path, &vars).is_empty());
+
+    let over_dialog = var(
+        "Path",
+        &"y".repeat(DIALOG_VALUE_LIMIT + 1),
+        EnvValueKind::String,
+    );
+    let issues = check_value(&over_dialog, &vars);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].severity, Severity::Warning);
+
+    let at_limit = var("Path", &"y".repeat(VALUE_LIMIT - 1), EnvValueKind::String);
+    assert_eq!(check_value(&at_limit, &vars)[0].severity, Severity::Warning);
+    let over_limit = var("Path", &"y".repeat(VALUE_LIMIT), EnvValueKind::String);
+    assert_eq!(check_value(&over_limit, &vars)[0].severity, Severity::Error);
+
+    // The hard limit also applies after expansion
+    let expands_over = var("Path", &"%LONG%".repeat(400), EnvValueKind::ExpandString);
+    assert_eq!(
+        check_value(&expands_over, &vars)[0].severity,
+        Severity::Error
+    );
+}
+
+#[test]
+fn test_env_block_limit() {
+    let existing: Vec<_> = (0..10)
+        .map(|i| var(&format!("VAR{i}"), &"z".repeat(3000), EnvValueKind::String))
+        .collect();
+    let small = var("SMALL", "1", EnvValueKind::String);
+    assert!(set(small).validate(&existing).is_empty());
+
+    // Individually fine, but pushes the whole block over the limit
+    let big = var("BIG", &"z".repeat(3000), EnvValueKind::String);
+    let issues = set(big).validate(&existing);
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[1].subject, "environment");
+    assert_eq!(issues[1].severity, Severity::Error);
+
+    // Replacing an existing variable does not count it twice
+    let replaced = var("var0", &"z".repeat(3000), EnvValueKind::String);
+    assert!(
+        !set(replaced)
+            .validate(&existing)
+            .iter()
+            .any(|i| i.severity == Severity::Error)
+    );
+}
+
+#[test]
+fn test_env_block_appends_user_path() {
+    let machine = var("Path", &"m".repeat(20_000), EnvValueKind::String);
+    let user = EnvironmentVariable::new(
+        EnvScope::User,
+        "Path",
+        "u".repeat(15_000),
+        EnvValueKind::String,
+    );
+    // Path=<machine>;<user>\0 and the final \0
+    assert_eq!(
+        env_block_len(&[machine.clone(), user.clone()]),
+        4 + 1 + 35_001 + 1 + 1
+    );
+
+    let issues = set(user).validate(std::slice::from_ref(&machine));
+    assert!(
+        issues
+            .iter()
+            .any(|issue| issue.subject == "environment" && issue.severity == Severity::Error),
+        "{issues:?}"
+    );
+
+    // Other user variables still replace the machine value
+    let machine_temp = var("TEMP", "C:\\Windows\\Temp", EnvValueKind::String);
+    let user_temp =
+        EnvironmentVariable::new(EnvScope::User, "TEMP", "C:\\Temp", EnvValueKind::String);
+    assert_eq!(env_block_len(&[machine_temp, user_temp]), 4 + 1 + 7 + 1 + 1);
+}
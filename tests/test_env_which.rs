@@ -0,0 +1,160 @@
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::EnvironmentVariable;
+use env_edit::env_which::SearchPath;
+use env_edit::env_which::parse_pathext;
+use eyre::Result;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Three Path directories with a few overlapping commands.
+fn make_dirs(name: &str) -> Result<PathBuf> {
+    let root =
+        std::env::temp_dir().join(format!("env-edit-test-which-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    for (dir, files) in [
+        ("python", &["python.exe", "pip.exe", "README.txt"][..]),
+        ("apps", &["Python.EXE", "python3.exe"][..]),
+        ("git", &["git.exe", "pip.cmd", "pip.bat"][..]),
+    ] {
+        std::fs::create_dir_all(root.join(dir))?;
+        for file in files {
+            std::fs::write(root.join(dir).join(file), "")?;
+        }
+    }
+    // A directory named like a command is not a match
+    std::fs::create_dir_all(root.join("git").join("python.exe"))?;
+    Ok(root)
+}
+
+fn search(root: &Path) -> SearchPath {
+    let root = root.display();
+    SearchPath::from_vars(
+        &[
+            EnvironmentVariable::new(
+                EnvScope::Machine,
+                "TOOLS",
+                root.to_string(),
+                EnvValueKind::ExpandString,
+            ),
+            EnvironmentVariable::new(
+                EnvScope::Machine,
+                "Path",
+                format!("%TOOLS%/python;;{root}/missing;{root}/git"),
+                EnvValueKind::ExpandString,
+            ),
+            EnvironmentVariable::new(
+                EnvScope::User,
+                "Path",
+                format!("{root}/apps"),
+                EnvValueKind::ExpandString,
+            ),
+            EnvironmentVariable::new(
+                EnvScope::User,
+                "PATHEXT",
+                ".CMD;.EXE;BAT",
+                EnvValueKind::ExpandString,
+            ),
+        ],
+        &[],
+        &EnvScope::ALL,
+    )
+}
+
+#[test]
+fn test_search_path_from_vars() -> Result<()> {
+    let root = make_dirs("from-vars")?;
+    let search = search(&root);
+    let indexes: Vec<_> = search
+        .dirs
+        .iter()
+        .map(|dir| (dir.scope, dir.index))
+        .collect();
+    assert_eq!(
+        indexes,
+        [
+            (EnvScope::Machine, 0),
+            (EnvScope::Machine, 2),
+            (EnvScope::Machine, 3),
+            (EnvScope::User, 0),
+        ]
+    );
+    assert_eq!(search.dirs[0].entry, "%TOOLS%/python");
+    assert_eq!(
+        search.dirs[0].dir,
+        PathBuf::from(format!("{}/python", root.display()))
+    );
+    assert_eq!(search.extensions, [".cmd", ".exe", ".bat"]);
+
+    let machine_only = SearchPath::from_vars(
+        &[EnvironmentVariable::new(
+            EnvScope::User,
+            "Path",
+            "C:\\Tools",
+            EnvValueKind::ExpandString,
+        )],
+        &[],
+        &[EnvScope::Machine],
+    );
+    assert!(machine_only.dirs.is_empty());
+    assert_eq!(
+        machine_only.extensions.len(),
+        11,
+        "defaults without PATHEXT"
+    );
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_which() -> Result<()> {
+    let root = make_dirs("which")?;
+    let search = search(&root);
+    let found = |name: &str| -> Vec<PathBuf> {
+        search
+            .which(name)
+            .into_iter()
+            .map(|found| found.path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect()
+    };
+
+    assert_eq!(
+        found("PYTHON"),
+        [Path::new("python/python.exe"), Path::new("apps/Python.EXE")]
+    );
+    // Within a directory the PATHEXT order wins
+    assert_eq!(
+        found("pip"),
+        [
+            Path::new("python/pip.exe"),
+            Path::new("git/pip.cmd"),
+            Path::new("git/pip.bat"),
+        ]
+    );
+    assert_eq!(found("pip.bat"), [Path::new("git/pip.bat")]);
+    assert!(found("README").is_empty(), "not in PATHEXT");
+    assert!(found("nothing").is_empty());
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_shadows() -> Result<()> {
+    let root = make_dirs("shadows")?;
+    let search = search(&root);
+    let shadows = search.shadows();
+    assert_eq!(shadows.keys().collect::<Vec<_>>(), ["pip", "python"]);
+    let dirs: Vec<_> = shadows["pip"].iter().map(|found| found.dir).collect();
+    assert_eq!(dirs, [0, 2, 2]);
+    assert!(shadows["pip"][1].path.ends_with("pip.cmd"));
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_parse_pathext() {
+    assert_eq!(
+        parse_pathext(" .EXE;;bat;.exe;.Cmd"),
+        [".exe", ".bat", ".cmd"]
+    );
+}
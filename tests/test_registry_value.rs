@@ -0,0 +1,41 @@
+use env_edit::env_reader::EnvScope;
+use env_edit::env_reader::EnvValueKind;
+use env_edit::env_reader::decode_registry_value;
+use eyre::Result;
+
+/// Odd values in the Environment key should decode with diagnostics rather than fail.
+#[test]
+fn test_decode_non_string_values() -> Result<()> {
+    let scope = EnvScope::Machine;
+
+    let dword = decode_registry_value(scope, "Flag", EnvValueKind::Dword, &[1, 0, 0, 0]);
+    assert_eq!(dword.value, "1");
+    assert_eq!(dword.diagnostics.len(), 1);
+
+    let truncated = decode_registry_value(scope, "Flag", EnvValueKind::Dword, &[1, 0]);
+    assert_eq!(truncated.value, "01,00");
+    assert_eq!(truncated.diagnostics.len(), 2);
+
+    let multi_data = EnvValueKind::MultiString.encode("first\nsecond")?;
+    let multi = decode_registry_value(scope, "List", EnvValueKind::MultiString, &multi_data);
+    assert_eq!(multi.value, "first\nsecond");
+
+    let unknown = decode_registry_value(scope, "Link", EnvValueKind::from_type_code(6), &[0xab]);
+    assert_eq!(unknown.kind, EnvValueKind::Unknown(6));
+    assert_eq!(unknown.value, "ab");
+    assert_eq!(EnvValueKind::Unknown(6).encode(&unknown.value)?, [0xab]);
+    Ok(())
+}
+
+#[test]
+fn test_decode_string_values() -> Result<()> {
+    let data = EnvValueKind::ExpandString.encode("%SystemRoot%\\system32")?;
+    let var = decode_registry_value(EnvScope::User, "Path", EnvValueKind::ExpandString, &data);
+    assert_eq!(var.value, "%SystemRoot%\\system32");
+    assert!(var.diagnostics.is_empty());
+
+    let odd = decode_registry_value(EnvScope::User, "X", EnvValueKind::String, &[b'a', 0, 0]);
+    assert_eq!(odd.value, "a");
+    assert_eq!(odd.diagnostics.len(), 1);
+    Ok(())
+}
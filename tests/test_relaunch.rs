@@ -0,0 +1,100 @@
+use env_edit::relaunch::RELAY_OUTPUT_ARG;
+use env_edit::relaunch::RelayFiles;
+use env_edit::relaunch::WORKING_DIR_ARG;
+use env_edit::relaunch::relaunch_command_line;
+use std::path::Path;
+
+/// Split a command line the way `CommandLineToArgvW` does for arguments after
+/// the program name.
+fn split_windows_args(command_line: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut chars = command_line.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ' ' || *c == '\t').is_some() {}
+        if chars.peek().is_none() {
+            return args;
+        }
+        let mut arg = String::new();
+        let mut quoted = false;
+        let mut backslashes = 0;
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    backslashes += 1;
+                    continue;
+                }
+                '"' => {
+                    arg.extend(std::iter::repeat_n('\\', backslashes / 2));
+                    if backslashes % 2 == 1 {
+                        arg.push('"');
+                    } else if quoted && chars.peek() == Some(&'"') {
+                        chars.next();
+                        arg.push('"');
+                    } else {
+                        quoted = !quoted;
+                    }
+                }
+                ' ' | '\t' if !quoted => {
+                    arg.extend(std::iter::repeat_n('\\', backslashes));
+                    break;
+                }
+                c => {
+                    arg.extend(std::iter::repeat_n('\\', backslashes));
+                    arg.push(c);
+                }
+            }
+            backslashes = 0;
+        }
+        arg.extend(std::iter::repeat_n('\\', backslashes));
+        args.push(arg);
+    }
+}
+
+#[test]
+fn test_relaunch_command_line_round_trips() {
+    let args: Vec<String> = [
+        "set",
+        "--key",
+        "X",
+        "--value",
+        "C:\\Program Files\\Tool\\",
+        "",
+        "say \"hi\" & exit",
+        "a\\\\\"b",
+        "tab\there",
+        "%PATH%;C:\\bin",
+    ]
+    .map(String::from)
+    .to_vec();
+    let relay_dir = Path::new("C:\\Users\\Some One\\AppData\\Local\\Temp\\env-edit-relay-1");
+    let working_dir = Path::new("C:\\work dir\\");
+    let command_line = relaunch_command_line(&args, relay_dir, working_dir);
+
+    let mut expected = vec![
+        RELAY_OUTPUT_ARG.to_string(),
+        relay_dir.display().to_string(),
+        WORKING_DIR_ARG.to_string(),
+        working_dir.display().to_string(),
+    ];
+    expected.extend(args);
+    assert_eq!(split_windows_args(&command_line), expected);
+}
+
+#[test]
+fn test_relaunch_command_line_quotes_only_when_needed() {
+    let command_line = relaunch_command_line(
+        &["list".to_string()],
+        Path::new("C:\\Temp\\relay"),
+        Path::new("C:\\work"),
+    );
+    assert_eq!(
+        command_line,
+        "--relay-output C:\\Temp\\relay --working-dir C:\\work list"
+    );
+}
+
+#[test]
+fn test_relay_missing_files() -> eyre::Result<()> {
+    let dir = std::env::temp_dir().join(format!("env-edit-test-relay-{}", std::process::id()));
+    RelayFiles::in_dir(&dir).relay()
+}
@@ -0,0 +1,20 @@
+use env_edit::win_strings::force_quote_windows_arg;
+use env_edit::win_strings::quote_windows_arg;
+
+#[test]
+fn test_quote_windows_arg() {
+    let cases = [
+        ("plain", "plain"),
+        ("", "\"\""),
+        ("two words", "\"two words\""),
+        ("C:\\Program Files\\", "\"C:\\Program Files\\\\\""),
+        ("C:\\no-spaces\\", "C:\\no-spaces\\"),
+        ("say \"hi\"", "\"say \\\"hi\\\"\""),
+        ("a\\\"b", "\"a\\\\\\\"b\""),
+        ("tab\there", "\"tab\there\""),
+    ];
+    for (arg, expected) in cases {
+        assert_eq!(quote_windows_arg(arg), expected, "{arg:?}");
+    }
+    assert_eq!(force_quote_windows_arg("C:\\dir\\"), "\"C:\\dir\\\\\"");
+}